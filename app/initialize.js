@@ -3,5 +3,7 @@ document.addEventListener('DOMContentLoaded', () => {
   window.jQuery = $;
   window.$ = $;
   require('jquery-ujs');
+  require('uploads')($);
+  require('rich_text_attachments')($);
   console.log('Initialized app');
 });
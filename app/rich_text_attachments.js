@@ -0,0 +1,86 @@
+// Uploads files into a rich text's attachments as soon as they're picked, then
+// drops a markdown reference to them into the editor at the cursor. A file
+// input opts in with `data-rich-text-upload-url`, along with the selectors of
+// the editor textarea and the list of attachments in `data-rich-text-editor`
+// and `data-rich-text-attachment-list`.
+function markdownFor(attachment) {
+  var reference = '(attachment:' + attachment.id + ')';
+  if (attachment.mime_type.indexOf('image/') === 0) {
+    return '![' + attachment.name + ']' + reference;
+  }
+  return '[' + attachment.name + ']' + reference;
+}
+
+function insertAtCursor(textarea, text) {
+  var start = textarea.selectionStart || 0;
+  var end = textarea.selectionEnd || start;
+  textarea.value =
+    textarea.value.slice(0, start) + text + textarea.value.slice(end);
+  textarea.selectionStart = textarea.selectionEnd = start + text.length;
+  textarea.focus();
+}
+
+module.exports = function initializeRichTextAttachments($) {
+  $(document).on('change', 'input[type=file][data-rich-text-upload-url]', function () {
+    var $input = $(this);
+    var file = this.files[0];
+
+    if (!file) {
+      return;
+    }
+
+    var formData = new FormData();
+    formData.append('file', file);
+
+    $input.removeClass('is-invalid').prop('disabled', true);
+    $input.siblings('.invalid-feedback').text('');
+
+    $.ajax({
+      url: $input.data('rich-text-upload-url'),
+      method: 'POST',
+      data: formData,
+      processData: false,
+      contentType: false,
+      dataType: 'json'
+    }).done(function (attachment) {
+      var detachUrl = $input.data('rich-text-upload-url') + '/' + attachment.id;
+      var $item = $('<li>').attr('data-attachment-id', attachment.id)
+        .append($('<a>').attr('href', attachment.url).text(attachment.name))
+        .append(' ')
+        .append($('<code>').text('attachment:' + attachment.id))
+        .append(' ')
+        .append($('<a href="#" class="link-danger">Remove</a>')
+          .attr('data-rich-text-detach-url', detachUrl));
+      $($input.data('rich-text-attachment-list')).append($item);
+
+      var editor = $($input.data('rich-text-editor')).get(0);
+      if (editor) {
+        insertAtCursor(editor, markdownFor(attachment));
+      }
+    }).fail(function (xhr) {
+      var error = (xhr.responseJSON && xhr.responseJSON.error) ||
+        'Your file could not be uploaded.';
+      $input.addClass('is-invalid');
+      $input.siblings('.invalid-feedback').text(error);
+    }).always(function () {
+      $input.prop('disabled', false).val('');
+    });
+  });
+
+  $(document).on('click', '[data-rich-text-detach-url]', function (event) {
+    var $link = $(this);
+    event.preventDefault();
+
+    if (!window.confirm('Remove this attachment? Links to it will stop working.')) {
+      return;
+    }
+
+    $.ajax({
+      url: $link.data('rich-text-detach-url'),
+      method: 'DELETE',
+      dataType: 'json'
+    }).done(function () {
+      $link.closest('li').remove();
+    });
+  });
+};
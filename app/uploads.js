@@ -0,0 +1,49 @@
+// Uploads files as soon as they're picked, rather than with the rest of the
+// form. A file input opts in with `data-upload-target`, the selector of a
+// hidden input which receives the id of the new attachment, and optionally
+// `data-upload-preview`, the selector of an img to show it in.
+module.exports = function initializeUploads($) {
+  $(document).on('change', 'input[type=file][data-upload-target]', function () {
+    var $input = $(this);
+    var file = this.files[0];
+
+    if (!file) {
+      return;
+    }
+
+    var formData = new FormData();
+    formData.append('file', file);
+
+    $input.removeClass('is-invalid').prop('disabled', true);
+    $input.siblings('.invalid-feedback').text('');
+
+    $.ajax({
+      url: '/attachments',
+      method: 'POST',
+      data: formData,
+      processData: false,
+      contentType: false,
+      dataType: 'json'
+    }).done(function (attachment) {
+      $($input.data('upload-target')).val(attachment.id);
+      $($input.data('upload-preview'))
+        .attr('src', attachment.url)
+        .removeClass('d-none');
+    }).fail(function (xhr) {
+      var error = (xhr.responseJSON && xhr.responseJSON.error) ||
+        'Your file could not be uploaded.';
+      $input.addClass('is-invalid');
+      $input.siblings('.invalid-feedback').text(error);
+    }).always(function () {
+      $input.prop('disabled', false).val('');
+    });
+  });
+
+  // clears the attachment, leaving the field as though nothing was uploaded
+  $(document).on('click', '[data-upload-clear]', function (event) {
+    var $input = $($(this).data('upload-clear'));
+    event.preventDefault();
+    $($input.data('upload-target')).val('');
+    $($input.data('upload-preview')).attr('src', '').addClass('d-none');
+  });
+};
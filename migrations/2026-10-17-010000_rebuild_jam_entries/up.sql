@@ -0,0 +1,28 @@
+-- the original jam_entries table never recorded which jam an entry was made
+-- for, insisted on a summary image before the participant had a chance to
+-- upload one, and its approval_state check forgot to allow "approved" (4). no
+-- code has ever written to this table, so it is rebuilt rather than migrated.
+DROP TABLE jam_entries;
+CREATE TABLE jam_entries(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the jam this entry was made for
+    jam_id INTEGER NOT NULL,
+    -- who submitted this entry
+    submitter_user_id BIGINT NOT NULL,
+    -- the approval state, one of "draft" (0), "submitted" (2), "approved" (4),
+    -- or "rejected" (8). the approval system is there to prevent malicious
+    -- actors from spamming the site.
+    approval_state INTEGER CHECK(approval_state IN (0, 2, 4, 8)) NOT NULL DEFAULT 0,
+    -- the title of this jam entry
+    title TEXT NOT NULL,
+    -- the slug of this jam entry, which is part of the url and makes it easy
+    -- for users to see what they're going to read when passing around the url.
+    slug TEXT NOT NULL,
+    -- a summary text which can appear in a list of jam entries.
+    summary TEXT NOT NULL,
+    -- summary image, which is an attachment. optional so that a participant
+    -- can start a draft before they have anything to show.
+    summary_attachment_id INTEGER,
+    -- the text of this entry
+    rich_text_id INTEGER NOT NULL
+);
@@ -0,0 +1,11 @@
+DROP TABLE jam_entry_updates;
+CREATE TABLE jam_entry_updates(
+    id INTEGER PRIMARY KEY NOT NULL,
+    jam_entry_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    rich_text_id INTEGER,
+    external_content_url TEXT,
+    approval_state INTEGER CHECK(approval_state IN (0, 1, 2, 5, 8)) NOT NULL DEFAULT 0
+);
@@ -0,0 +1,30 @@
+-- like jam_entries, nothing has ever written to jam_entry_updates, and its
+-- approval_state check also forgot "approved" (4). updates are shown as a
+-- feed, so they need to know when they were made, and the "either rich text or
+-- an external link, never both" rule is now enforced by the database too.
+DROP TABLE jam_entry_updates;
+CREATE TABLE jam_entry_updates(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the jam entry this is attached to
+    jam_entry_id INTEGER NOT NULL,
+    -- the title of this update
+    title TEXT NOT NULL,
+    -- the slug of this update, which is part of the url and makes it easy for
+    -- users to see what they're going to read when passing around the url.
+    slug TEXT NOT NULL,
+    -- a summary text which can appear in a list of updates.
+    summary TEXT NOT NULL,
+    -- the rich text content this applies to.
+    -- should not be present if external_content_url is present.
+    rich_text_id INTEGER,
+    -- the external content link this applies to.
+    -- should not be present if rich_text_id is present.
+    external_content_url TEXT,
+    -- the approval state, one of "draft" (0), "submitted" (2), "approved" (4),
+    -- or "rejected" (8). the approval system is there to prevent malicious
+    -- actors from spamming the site.
+    approval_state INTEGER CHECK(approval_state IN (0, 2, 4, 8)) NOT NULL DEFAULT 0,
+    -- when this update was written, which orders the feed on an entry's page.
+    created_at TIMESTAMP NOT NULL,
+    CHECK((rich_text_id IS NULL) <> (external_content_url IS NULL))
+);
@@ -0,0 +1,56 @@
+-- sqlite can't drop columns, so each table is rebuilt without the reason.
+CREATE TABLE jams_without_rejection_reason(
+    id INTEGER PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    summary_attachment_id INTEGER,
+    rich_text_id INTEGER NOT NULL,
+    start_date TIMESTAMP NOT NULL,
+    end_date TIMESTAMP NOT NULL,
+    approval_state INTEGER CHECK(approval_state IN (0, 1, 2, 4, 8)) NOT NULL DEFAULT 0
+);
+INSERT INTO jams_without_rejection_reason
+    SELECT id, title, slug, summary, summary_attachment_id, rich_text_id,
+        start_date, end_date, approval_state
+    FROM jams;
+DROP TABLE jams;
+ALTER TABLE jams_without_rejection_reason RENAME TO jams;
+
+CREATE TABLE jam_entries_without_rejection_reason(
+    id INTEGER PRIMARY KEY NOT NULL,
+    jam_id INTEGER NOT NULL,
+    submitter_user_id BIGINT NOT NULL,
+    approval_state INTEGER CHECK(approval_state IN (0, 2, 4, 8)) NOT NULL DEFAULT 0,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    summary_attachment_id INTEGER,
+    rich_text_id INTEGER NOT NULL
+);
+INSERT INTO jam_entries_without_rejection_reason
+    SELECT id, jam_id, submitter_user_id, approval_state, title, slug, summary,
+        summary_attachment_id, rich_text_id
+    FROM jam_entries;
+DROP TABLE jam_entries;
+ALTER TABLE jam_entries_without_rejection_reason RENAME TO jam_entries;
+
+CREATE TABLE jam_entry_updates_without_rejection_reason(
+    id INTEGER PRIMARY KEY NOT NULL,
+    jam_entry_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    rich_text_id INTEGER,
+    external_content_url TEXT,
+    approval_state INTEGER CHECK(approval_state IN (0, 2, 4, 8)) NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL,
+    CHECK((rich_text_id IS NULL) <> (external_content_url IS NULL))
+);
+INSERT INTO jam_entry_updates_without_rejection_reason
+    SELECT id, jam_entry_id, title, slug, summary, rich_text_id,
+        external_content_url, approval_state, created_at
+    FROM jam_entry_updates;
+DROP TABLE jam_entry_updates;
+ALTER TABLE jam_entry_updates_without_rejection_reason
+    RENAME TO jam_entry_updates;
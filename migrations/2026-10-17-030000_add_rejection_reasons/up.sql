@@ -0,0 +1,6 @@
+-- when an admin rejects something they have to say why, and the reason is kept
+-- alongside the item so that whoever submitted it can see what to fix. it is
+-- cleared again when the item is resubmitted or approved.
+ALTER TABLE jams ADD COLUMN rejection_reason TEXT;
+ALTER TABLE jam_entries ADD COLUMN rejection_reason TEXT;
+ALTER TABLE jam_entry_updates ADD COLUMN rejection_reason TEXT;
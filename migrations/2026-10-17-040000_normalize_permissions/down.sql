@@ -0,0 +1,6 @@
+-- the original spelling of normalized names is lost, but the unrecognized ones
+-- can go back the way they were.
+INSERT INTO permissions (gh_user_id, name)
+SELECT gh_user_id, name FROM unrecognized_permissions;
+
+DROP TABLE unrecognized_permissions;
@@ -0,0 +1,25 @@
+-- permission names used to be any string at all, so "Admin" and "admin" were
+-- two different permissions and a typo made a grant which did nothing. names
+-- are now checked against a fixed list, so existing grants are normalized and
+-- anything which still isn't recognized is set aside for an admin to look at,
+-- keeping the name exactly as it was granted.
+CREATE TABLE unrecognized_permissions(
+    id INTEGER PRIMARY KEY NOT NULL,
+    gh_user_id BIGINT NOT NULL,
+    name TEXT NOT NULL
+);
+
+INSERT INTO unrecognized_permissions (gh_user_id, name)
+SELECT gh_user_id, name FROM permissions
+WHERE lower(trim(name)) NOT IN ('admin', 'banned', 'moderator', 'judge');
+
+DELETE FROM permissions
+WHERE lower(trim(name)) NOT IN ('admin', 'banned', 'moderator', 'judge');
+
+UPDATE permissions SET name = lower(trim(name));
+
+-- normalizing can leave a user with the same grant twice, keep the oldest
+DELETE FROM permissions
+WHERE id NOT IN (
+    SELECT min(id) FROM permissions GROUP BY gh_user_id, name
+);
@@ -0,0 +1,13 @@
+-- sqlite can't drop columns, so the table is rebuilt without the sha256.
+CREATE TABLE attachments_without_sha256(
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT TRUE,
+    mime_type TEXT NOT NULL,
+    md5 BLOB NOT NULL
+);
+INSERT INTO attachments_without_sha256
+    SELECT id, name, published, mime_type, md5
+    FROM attachments;
+DROP TABLE attachments;
+ALTER TABLE attachments_without_sha256 RENAME TO attachments;
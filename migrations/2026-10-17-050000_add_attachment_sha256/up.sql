@@ -0,0 +1,4 @@
+-- the sha256 of the file, which is what's shown to people downloading binaries
+-- so they can check what they got. it is null for attachments uploaded before
+-- it was recorded until `attachments verify` fills it in.
+ALTER TABLE attachments ADD COLUMN sha256 BLOB;
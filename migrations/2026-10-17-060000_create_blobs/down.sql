@@ -0,0 +1,16 @@
+-- sqlite can't drop columns, so the table is rebuilt without the blob_id.
+-- attachments which were moved into blobs are left where they are in storage.
+CREATE TABLE attachments_without_blob_id(
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT TRUE,
+    mime_type TEXT NOT NULL,
+    md5 BLOB NOT NULL,
+    sha256 BLOB
+);
+INSERT INTO attachments_without_blob_id
+    SELECT id, name, published, mime_type, md5, sha256
+    FROM attachments;
+DROP TABLE attachments;
+ALTER TABLE attachments_without_blob_id RENAME TO attachments;
+DROP TABLE blobs;
@@ -0,0 +1,17 @@
+-- blobs are the files behind attachments, stored once however many attachments
+-- have the same contents. each is stored under the hex of its sha256.
+CREATE TABLE blobs(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- what the blob is stored under, so no two blobs have the same one
+    sha256 BLOB NOT NULL UNIQUE,
+    md5 BLOB NOT NULL,
+    -- the length of the file in bytes
+    size BIGINT NOT NULL,
+    -- how many attachments are this blob. the blob and its file are removed
+    -- when the last of them goes.
+    ref_count INTEGER NOT NULL
+);
+-- the blob an attachment is. it is null for attachments uploaded before blobs
+-- existed, which are still stored under their id, until `attachments dedupe`
+-- moves them over.
+ALTER TABLE attachments ADD COLUMN blob_id INTEGER REFERENCES blobs(id);
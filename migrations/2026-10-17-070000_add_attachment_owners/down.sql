@@ -0,0 +1,15 @@
+-- sqlite can't drop columns, so the table is rebuilt without the owners.
+CREATE TABLE attachments_without_owners(
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT TRUE,
+    mime_type TEXT NOT NULL,
+    md5 BLOB NOT NULL,
+    sha256 BLOB,
+    blob_id INTEGER REFERENCES blobs(id)
+);
+INSERT INTO attachments_without_owners
+    SELECT id, name, published, mime_type, md5, sha256, blob_id
+    FROM attachments;
+DROP TABLE attachments;
+ALTER TABLE attachments_without_owners RENAME TO attachments;
@@ -0,0 +1,46 @@
+-- who uploaded the attachment, who may always see it. it is null for
+-- attachments uploaded before this was recorded.
+ALTER TABLE attachments ADD COLUMN uploader_user_id BIGINT
+    REFERENCES gh_user_records(id);
+-- what the attachment belongs to, whose approval decides whether the world
+-- may see it. at most one of these is set, and none are until the upload is
+-- first used as a summary image or attached to a rich text.
+ALTER TABLE attachments ADD COLUMN jam_id INTEGER REFERENCES jams(id);
+ALTER TABLE attachments ADD COLUMN jam_entry_id INTEGER
+    REFERENCES jam_entries(id);
+ALTER TABLE attachments ADD COLUMN jam_entry_update_id INTEGER
+    REFERENCES jam_entry_updates(id);
+
+-- existing attachments belong to whatever already uses them, and were
+-- uploaded by whoever submitted the entry they belong to.
+UPDATE attachments SET jam_id = (
+    SELECT jams.id FROM jams
+    LEFT JOIN rich_text_attachments
+        ON rich_text_attachments.rich_text_id = jams.rich_text_id
+    WHERE jams.summary_attachment_id = attachments.id
+        OR rich_text_attachments.attachment_id = attachments.id
+    ORDER BY jams.id LIMIT 1
+);
+UPDATE attachments SET jam_entry_id = (
+    SELECT jam_entries.id FROM jam_entries
+    LEFT JOIN rich_text_attachments
+        ON rich_text_attachments.rich_text_id = jam_entries.rich_text_id
+    WHERE jam_entries.summary_attachment_id = attachments.id
+        OR rich_text_attachments.attachment_id = attachments.id
+    ORDER BY jam_entries.id LIMIT 1
+) WHERE jam_id IS NULL;
+UPDATE attachments SET jam_entry_update_id = (
+    SELECT jam_entry_updates.id FROM jam_entry_updates
+    JOIN rich_text_attachments
+        ON rich_text_attachments.rich_text_id = jam_entry_updates.rich_text_id
+    WHERE rich_text_attachments.attachment_id = attachments.id
+    ORDER BY jam_entry_updates.id LIMIT 1
+) WHERE jam_id IS NULL AND jam_entry_id IS NULL;
+UPDATE attachments SET uploader_user_id = (
+    SELECT jam_entries.submitter_user_id FROM jam_entries
+    LEFT JOIN jam_entry_updates
+        ON jam_entry_updates.jam_entry_id = jam_entries.id
+    WHERE jam_entries.id = attachments.jam_entry_id
+        OR jam_entry_updates.id = attachments.jam_entry_update_id
+    LIMIT 1
+);
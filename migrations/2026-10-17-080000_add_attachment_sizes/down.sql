@@ -0,0 +1,20 @@
+-- sqlite can't drop columns, so the table is rebuilt without the size.
+CREATE TABLE attachments_without_size(
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT TRUE,
+    mime_type TEXT NOT NULL,
+    md5 BLOB NOT NULL,
+    sha256 BLOB,
+    blob_id INTEGER REFERENCES blobs(id),
+    uploader_user_id BIGINT REFERENCES gh_user_records(id),
+    jam_id INTEGER REFERENCES jams(id),
+    jam_entry_id INTEGER REFERENCES jam_entries(id),
+    jam_entry_update_id INTEGER REFERENCES jam_entry_updates(id)
+);
+INSERT INTO attachments_without_size
+    SELECT id, name, published, mime_type, md5, sha256, blob_id,
+        uploader_user_id, jam_id, jam_entry_id, jam_entry_update_id
+    FROM attachments;
+DROP TABLE attachments;
+ALTER TABLE attachments_without_size RENAME TO attachments;
@@ -0,0 +1,8 @@
+-- the length of the attachment's file in bytes, which is what counts against
+-- upload quotas. attachments which share a blob each count its whole size,
+-- since each was uploaded in full. attachments uploaded before blobs existed
+-- count as empty until `attachments dedupe` moves them over.
+ALTER TABLE attachments ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
+UPDATE attachments SET size = (
+    SELECT blobs.size FROM blobs WHERE blobs.id = attachments.blob_id
+) WHERE blob_id IS NOT NULL;
@@ -0,0 +1,11 @@
+-- when the attachment was uploaded. sqlite can't default a new column to the
+-- current time, so existing attachments are given the time of migrating,
+-- which gives any of them nothing uses yet a full retention window before
+-- `attachments gc` removes them.
+ALTER TABLE attachments ADD COLUMN created_at TIMESTAMP NOT NULL
+    DEFAULT '1970-01-01 00:00:00';
+UPDATE attachments SET created_at = CURRENT_TIMESTAMP;
+-- when the attachment was deleted. deleted attachments are gone as far as the
+-- site is concerned, but keep their row and file until `attachments gc` runs
+-- after the retention window.
+ALTER TABLE attachments ADD COLUMN deleted_at TIMESTAMP;
@@ -0,0 +1,2 @@
+DROP TABLE release_artifacts;
+DROP TABLE releases;
@@ -0,0 +1,30 @@
+-- releases are versions of a jam entry which can be downloaded, rather than
+-- builds tucked away among the attachments of its description.
+CREATE TABLE releases(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the jam entry this is a release of
+    jam_entry_id INTEGER NOT NULL REFERENCES jam_entries(id),
+    -- the version, such as "1.0.1", which is unique within an entry
+    version TEXT NOT NULL,
+    -- the changelog, saying what changed since the last release
+    rich_text_id INTEGER NOT NULL REFERENCES rich_texts(id),
+    -- when this release was made, which orders the entry's download table
+    created_at TIMESTAMP NOT NULL,
+    UNIQUE(jam_entry_id, version)
+);
+-- the builds which make up a release, one for each platform and architecture
+-- it runs on.
+CREATE TABLE release_artifacts(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the release this is a build of
+    release_id INTEGER NOT NULL REFERENCES releases(id),
+    -- the build itself
+    attachment_id INTEGER NOT NULL REFERENCES attachments(id),
+    -- one of "macos", "windows", "linux" or "web"
+    platform TEXT CHECK(platform IN ('macos', 'windows', 'linux', 'web'))
+        NOT NULL,
+    -- one of "x86_64", "arm64", "universal" or "any"
+    architecture TEXT
+        CHECK(architecture IN ('x86_64', 'arm64', 'universal', 'any'))
+        NOT NULL
+);
@@ -0,0 +1,18 @@
+-- web builds are release artifacts which can be played in the browser, having
+-- been extracted from their zip archive so that the files inside can be
+-- served one at a time.
+CREATE TABLE web_builds(
+    id INTEGER PRIMARY KEY NOT NULL,
+    -- the artifact this was extracted from, which is for the web platform
+    release_artifact_id INTEGER NOT NULL UNIQUE
+        REFERENCES release_artifacts(id),
+    -- the directory it was extracted to, named for the hex sha256 of the
+    -- archive, which artifacts of the same archive share
+    directory TEXT NOT NULL,
+    -- the page to play it from, relative to the directory
+    entry_point TEXT NOT NULL,
+    -- the total size of the extracted files, in bytes
+    size BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+CREATE INDEX web_builds_directory ON web_builds(directory);
@@ -0,0 +1,170 @@
+use super::{
+    AttachmentReader, AttachmentStorageError, ByteRange, LoadedAttachment,
+    StorageBackend, StoredFile,
+};
+use chrono::{DateTime, Utc};
+use std::{
+    fs::read_dir,
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::{remove_file, rename, File},
+    io::AsyncReadExt,
+};
+
+/// Keeps attachments as files in a local directory, each named for its key.
+pub struct LocalStorage {
+    /// Where the files are stored.
+    storage_path: PathBuf,
+}
+
+impl LocalStorage {
+    /// Creates a new local storage managing the given directory.
+    pub fn new(storage_path: PathBuf) -> LocalStorage {
+        LocalStorage { storage_path }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let mut path = self.storage_path.clone();
+        path.push(key);
+        path
+    }
+}
+
+#[rocket::async_trait]
+impl StorageBackend for LocalStorage {
+    /// Files are renamed into place, so they need to be on the same
+    /// filesystem as the storage directory.
+    async fn put(
+        &self,
+        file: &Path,
+        key: &str,
+        _mime_type: &str,
+    ) -> Result<(), AttachmentStorageError> {
+        Ok(rename(file, self.path(key)).await?)
+    }
+
+    async fn load(
+        &self,
+        key: &str,
+        range: Option<ByteRange>,
+        _mime_type: &str,
+        _name: &str,
+    ) -> Result<LoadedAttachment, AttachmentStorageError> {
+        let path = self.path(key);
+
+        if !path.is_file() {
+            return Err(AttachmentStorageError::NotFound(key.to_string()));
+        }
+
+        let mut file = File::open(path).await?;
+        let metadata = file.metadata().await?;
+        let size = metadata.len();
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+
+        let (reader, range): (AttachmentReader, _) = match range {
+            Some(range) => {
+                let (start, end) = range
+                    .resolve(size)
+                    .ok_or(AttachmentStorageError::RangeNotSatisfiable(size))?;
+                file.seek(SeekFrom::Start(start)).await?;
+                (Box::pin(file.take(end - start + 1)), Some((start, end)))
+            }
+            None => (Box::pin(file), None),
+        };
+
+        Ok(LoadedAttachment::Stream(StoredFile {
+            reader,
+            size,
+            modified,
+            range,
+        }))
+    }
+
+    async fn open(
+        &self,
+        key: &str,
+    ) -> Result<AttachmentReader, AttachmentStorageError> {
+        let path = self.path(key);
+
+        if !path.is_file() {
+            return Err(AttachmentStorageError::NotFound(key.to_string()));
+        }
+
+        Ok(Box::pin(File::open(path).await?))
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, AttachmentStorageError> {
+        Ok(self.path(key).is_file())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AttachmentStorageError> {
+        match remove_file(self.path(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Skips dotfiles, which are uploads still in progress.
+    async fn keys(&self) -> Result<Vec<String>, AttachmentStorageError> {
+        let mut keys = Vec::new();
+
+        for dir_entry in read_dir(&self.storage_path)? {
+            let dir_entry = dir_entry?;
+            let key = dir_entry.file_name().to_string_lossy().to_string();
+
+            if dir_entry.file_type()?.is_file() && !key.starts_with('.') {
+                keys.push(key);
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attachments::local::*;
+
+    #[tokio::test]
+    async fn test_load_range() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("1"), "0123456789").unwrap();
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+
+        let range = Some(ByteRange::From(2, Some(4)));
+        let mut file = match storage
+            .load("1", range, "text/plain", "1.txt")
+            .await
+            .unwrap()
+        {
+            LoadedAttachment::Stream(file) => file,
+            LoadedAttachment::Redirect(_) => panic!("Expected a stream"),
+        };
+        let mut content = String::new();
+        file.reader.read_to_string(&mut content).await.unwrap();
+        assert_eq!("234", content);
+        assert_eq!(10, file.size);
+        assert_eq!(Some((2, 4)), file.range);
+        assert!(file.modified.is_some());
+
+        assert!(matches!(
+            storage
+                .load(
+                    "1",
+                    Some(ByteRange::From(10, None)),
+                    "text/plain",
+                    "1.txt"
+                )
+                .await,
+            Err(AttachmentStorageError::RangeNotSatisfiable(10))
+        ));
+        assert!(matches!(
+            storage.load("2", None, "text/plain", "2.txt").await,
+            Err(AttachmentStorageError::NotFound(_))
+        ));
+        assert!(storage.exists("1").await.unwrap());
+        assert!(!storage.exists("2").await.unwrap());
+    }
+}
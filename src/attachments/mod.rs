@@ -0,0 +1,498 @@
+mod local;
+mod s3;
+mod variants;
+
+pub use local::LocalStorage;
+pub use s3::{S3Config, S3Storage};
+pub use variants::{
+    is_resizable, is_variant_key_of, VARIANT_MIME_TYPE, VARIANT_WIDTHS,
+};
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{Error as IOError, ErrorKind, Read},
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+#[derive(Debug, Error)]
+pub enum AttachmentStorageError {
+    #[error("IOError {0}")]
+    IoError(#[from] IOError),
+
+    #[error("Nothing is stored under {0}")]
+    NotFound(String),
+
+    #[error("Object store request failed with error {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Object store responded {0} to {1}")]
+    UnexpectedResponse(u16, String),
+
+    #[error("Object store endpoint {0} is not a URL with a host")]
+    InvalidEndpoint(String),
+
+    #[error("The range asked for is outside a file of {0} bytes")]
+    RangeNotSatisfiable(u64),
+
+    #[error("Could not read the image with error {0}")]
+    ImageError(#[from] image::ImageError),
+
+    #[error("Could not encode the image as WebP with error {0}")]
+    WebPError(String),
+
+    #[error("The image is {0}x{1}, which is too many pixels to resize")]
+    ImageTooLarge(u32, u32),
+}
+
+/// The contents of a stored attachment, read as it arrives from storage.
+pub type AttachmentReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Some bytes of a file, as asked for by a downloader resuming a download.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    /// From the first offset through the second, inclusive, or through the end
+    /// of the file when there is no second.
+    From(u64, Option<u64>),
+
+    /// However many bytes are at the end of the file.
+    Last(u64),
+}
+
+impl ByteRange {
+    /// The first and last offset, inclusive, this picks out of a file of the
+    /// given size. Nothing is picked out when the range starts past the end
+    /// or the file is empty.
+    pub fn resolve(&self, size: u64) -> Option<(u64, u64)> {
+        match *self {
+            _ if size == 0 => None,
+            ByteRange::From(start, _) if start >= size => None,
+            ByteRange::From(start, Some(end)) if end < start => None,
+            ByteRange::From(start, end) => {
+                Some((start, end.map_or(size - 1, |end| end.min(size - 1))))
+            }
+            ByteRange::Last(0) => None,
+            ByteRange::Last(length) => {
+                Some((size.saturating_sub(length), size - 1))
+            }
+        }
+    }
+}
+
+/// A file coming out of storage, along with what a downloader is told about
+/// it.
+pub struct StoredFile {
+    /// The contents, or just the range of them which was asked for.
+    pub reader: AttachmentReader,
+
+    /// The length of the whole file, even when only part of it is read.
+    pub size: u64,
+
+    /// When the file was stored, if storage knows.
+    pub modified: Option<DateTime<Utc>>,
+
+    /// The first and last offset, inclusive, being read when only part of the
+    /// file was asked for.
+    pub range: Option<(u64, u64)>,
+}
+
+/// How to get an attachment to whoever asked for it.
+pub enum LoadedAttachment {
+    /// Its contents, to be passed along.
+    Stream(StoredFile),
+
+    /// A URL it can be downloaded from directly, without going through us.
+    Redirect(String),
+}
+
+/// Somewhere attachments can be kept. Everything is addressed by a key, which
+/// the backend is free to map onto a file name or an object name as it likes.
+#[rocket::async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Puts the file into storage under the given key, replacing anything
+    /// already there, as a file of the given MIME type. The file may be moved
+    /// rather than copied.
+    async fn put(
+        &self,
+        file: &Path,
+        key: &str,
+        mime_type: &str,
+    ) -> Result<(), AttachmentStorageError>;
+
+    /// Loads what's stored under the key for serving to a downloader, only
+    /// reading the range of it which was asked for. A range which picks out
+    /// nothing is [`AttachmentStorageError::RangeNotSatisfiable`]. The MIME
+    /// type and name are what the downloader is told they are getting, when
+    /// it is served by something other than us.
+    async fn load(
+        &self,
+        key: &str,
+        range: Option<ByteRange>,
+        mime_type: &str,
+        name: &str,
+    ) -> Result<LoadedAttachment, AttachmentStorageError>;
+
+    /// Reads what's stored under the key, which unlike `load` always goes
+    /// through us.
+    async fn open(
+        &self,
+        key: &str,
+    ) -> Result<AttachmentReader, AttachmentStorageError>;
+
+    /// Whether anything is stored under the key, without reading it.
+    async fn exists(&self, key: &str) -> Result<bool, AttachmentStorageError>;
+
+    /// Removes what's stored under the key. A key which is already gone is
+    /// not an error.
+    async fn delete(&self, key: &str) -> Result<(), AttachmentStorageError>;
+
+    /// Lists every key in storage.
+    async fn keys(&self) -> Result<Vec<String>, AttachmentStorageError>;
+}
+
+/// Hides the logic of placing files into storage and retrieving them again.
+pub struct AttachmentStorage {
+    /// Where the files are stored.
+    backend: Box<dyn StorageBackend>,
+    /// Where uploads are written as they arrive, before they are stored.
+    temp_path: PathBuf,
+}
+
+/// Digests of a stored file. The md5 is what has always been recorded, and the
+/// sha256 is what downloaders are shown to check their download against and
+/// what the file is stored under.
+#[derive(Debug, Clone, Copy)]
+pub struct Digests {
+    pub md5: [u8; 16],
+    pub sha256: [u8; 32],
+    /// The length of the file in bytes, which comes for free with reading it.
+    pub size: u64,
+}
+
+impl AttachmentStorage {
+    /// Creates a new attachment storage on top of the given backend, staging
+    /// uploads in the given temporary path.
+    pub fn new(
+        backend: impl StorageBackend + 'static,
+        temp_path: PathBuf,
+    ) -> AttachmentStorage {
+        AttachmentStorage {
+            backend: Box::new(backend),
+            temp_path,
+        }
+    }
+
+    /// Creates a new attachment storage managing the given local directory.
+    /// Uploads are staged in the same directory, so that storing them is a
+    /// rename rather than a copy.
+    pub fn local(storage_path: PathBuf) -> AttachmentStorage {
+        AttachmentStorage::new(
+            LocalStorage::new(storage_path.clone()),
+            storage_path,
+        )
+    }
+
+    /// Creates an empty temporary file to receive an upload, which is removed
+    /// again if it is dropped without being stored.
+    pub fn temp_file(&self) -> Result<NamedTempFile, AttachmentStorageError> {
+        Ok(tempfile::Builder::new()
+            .prefix(".upload")
+            .tempfile_in(&self.temp_path)?)
+    }
+
+    /// Stores the file under the given key as a file of the given MIME type.
+    /// The original file is consumed.
+    pub async fn store(
+        &self,
+        file: &Path,
+        key: &str,
+        mime_type: &str,
+    ) -> Result<(), AttachmentStorageError> {
+        self.backend.put(file, key, mime_type).await
+    }
+
+    /// Whether a file is stored under the given key.
+    pub async fn exists(
+        &self,
+        key: &str,
+    ) -> Result<bool, AttachmentStorageError> {
+        self.backend.exists(key).await
+    }
+
+    /// Loads a file out of storage for a downloader, or just the range of it
+    /// they asked for, as a file of the given MIME type and name.
+    pub async fn load(
+        &self,
+        key: &str,
+        range: Option<ByteRange>,
+        mime_type: &str,
+        name: &str,
+    ) -> Result<LoadedAttachment, AttachmentStorageError> {
+        self.backend.load(key, range, mime_type, name).await
+    }
+
+    /// Loads a resized WebP variant of an image for a downloader, under the
+    /// name of the original. Variants are made the first time they are asked
+    /// for and kept next to the original from then on.
+    pub async fn load_variant(
+        &self,
+        key: &str,
+        width: u32,
+        range: Option<ByteRange>,
+        name: &str,
+    ) -> Result<LoadedAttachment, AttachmentStorageError> {
+        let variant_key = variants::variant_key(key, width);
+
+        // loading can't be relied on to say the variant is missing, as a
+        // backend which redirects downloaders to it doesn't look first
+        if self.backend.exists(&variant_key).await? {
+            return self
+                .backend
+                .load(&variant_key, range, variants::VARIANT_MIME_TYPE, name)
+                .await;
+        }
+
+        let mut original = Vec::new();
+        self.backend
+            .open(key)
+            .await?
+            .read_to_end(&mut original)
+            .await?;
+
+        // resizing takes a while, so keep it off the threads serving requests
+        let variant = tokio::task::spawn_blocking(move || {
+            variants::make_variant(&original, width)
+        })
+        .await
+        .map_err(|e| IOError::new(ErrorKind::Other, e))??;
+
+        let temp_file = self.temp_file()?;
+        let mut file = tokio::fs::File::from_std(temp_file.reopen()?);
+        file.write_all(&variant).await?;
+        file.flush().await?;
+        self.backend
+            .put(
+                &temp_file.into_temp_path(),
+                &variant_key,
+                variants::VARIANT_MIME_TYPE,
+            )
+            .await?;
+
+        self.backend
+            .load(&variant_key, range, variants::VARIANT_MIME_TYPE, name)
+            .await
+    }
+
+    /// Recomputes the digests of a file in storage, so that they can be
+    /// checked against what was recorded when it was stored.
+    pub async fn digests(
+        &self,
+        key: &str,
+    ) -> Result<Digests, AttachmentStorageError> {
+        let reader = self.backend.open(key).await?;
+        Ok(digest_reader(reader).await?)
+    }
+
+    /// Copies a file in storage to another key, by way of a temporary file.
+    /// The copy is stored as a file of the given MIME type.
+    pub async fn copy(
+        &self,
+        from_key: &str,
+        to_key: &str,
+        mime_type: &str,
+    ) -> Result<(), AttachmentStorageError> {
+        let mut reader = self.backend.open(from_key).await?;
+        let temp_file = self.temp_file()?;
+        let mut file = tokio::fs::File::from_std(temp_file.reopen()?);
+        tokio::io::copy(&mut reader, &mut file).await?;
+        file.flush().await?;
+
+        self.backend
+            .put(&temp_file.into_temp_path(), to_key, mime_type)
+            .await
+    }
+
+    /// Copies a file out of storage into a temporary file, for working on
+    /// locally. The temporary file is removed again when it is dropped.
+    pub async fn fetch(
+        &self,
+        key: &str,
+    ) -> Result<NamedTempFile, AttachmentStorageError> {
+        let mut reader = self.backend.open(key).await?;
+        let temp_file = self.temp_file()?;
+        let mut file = tokio::fs::File::from_std(temp_file.reopen()?);
+        tokio::io::copy(&mut reader, &mut file).await?;
+        file.flush().await?;
+
+        Ok(temp_file)
+    }
+
+    /// Removes a file from storage, along with any variants made of it. A
+    /// file which is already gone is not an error.
+    pub async fn delete(
+        &self,
+        key: &str,
+    ) -> Result<(), AttachmentStorageError> {
+        for width in VARIANT_WIDTHS.iter() {
+            self.backend
+                .delete(&variants::variant_key(key, *width))
+                .await?;
+        }
+
+        self.backend.delete(key).await
+    }
+
+    /// Lists the name of every file in storage. Files are named for the key
+    /// they are stored under, but nothing stops anything else from ending up
+    /// in there.
+    pub async fn file_names(
+        &self,
+    ) -> Result<Vec<String>, AttachmentStorageError> {
+        let mut file_names = self.backend.keys().await?;
+        file_names.sort();
+        Ok(file_names)
+    }
+}
+
+/// How much of a file is digested at a time.
+const DIGEST_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Computes every digest in one pass, since reading the file is the expensive
+/// part.
+struct Digester {
+    md5: md5::Context,
+    sha256: Sha256,
+    size: u64,
+}
+
+impl Digester {
+    fn new() -> Digester {
+        Digester {
+            md5: md5::Context::new(),
+            sha256: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.md5.consume(data);
+        self.sha256.update(data);
+        self.size += data.len() as u64;
+    }
+
+    fn finish(self) -> Digests {
+        let mut digests = Digests {
+            md5: self.md5.compute().0,
+            sha256: [0; 32],
+            size: self.size,
+        };
+        digests.sha256.copy_from_slice(&self.sha256.finalize());
+        digests
+    }
+}
+
+/// Digests a file a chunk at a time, so that a large game build doesn't have
+/// to fit in memory.
+pub fn digest_file(file: &Path) -> Result<Digests, IOError> {
+    let mut file = File::open(file)?;
+    let mut digester = Digester::new();
+    let mut buffer = vec![0; DIGEST_BUFFER_SIZE];
+
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        digester.update(&buffer[..read]);
+    }
+
+    Ok(digester.finish())
+}
+
+/// Digests whatever comes out of the reader, a chunk at a time.
+pub async fn digest_reader(
+    mut reader: impl AsyncRead + Unpin,
+) -> Result<Digests, IOError> {
+    let mut digester = Digester::new();
+    let mut buffer = vec![0; DIGEST_BUFFER_SIZE];
+
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        digester.update(&buffer[..read]);
+    }
+
+    Ok(digester.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attachments::*;
+    use hex::decode as hex_decode;
+    use std::io::Write;
+
+    /// This may look dumb to test... and it kind of is. But I wanted to make
+    /// sure the digester was working in a way consistent with expectation. So I
+    /// wrote a test for it. It didn't make sense to delete it.
+    #[test]
+    fn test_file_hashing() {
+        let example_content = "this is an example";
+        let expected_sum =
+            hex_decode("9202816dabaaf34bb106a10421b9a0d0").unwrap();
+        let expected_sha256 = hex_decode(
+            "d44c035835f1c5e0668b7d186a2ff5b0dc2e3137ec3c50b12a34c47b7af51e44",
+        )
+        .unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write!(&file, "{}", example_content).unwrap();
+        let digests = digest_file(file.path()).unwrap();
+        assert_eq!(expected_sum, digests.md5);
+        assert_eq!(expected_sha256, digests.sha256);
+    }
+
+    /// Files bigger than the buffer have to come out the same as if they had
+    /// been digested all at once.
+    #[test]
+    fn test_file_hashing_in_chunks() {
+        let example_content = vec![7u8; 200 * 1024 + 3];
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&example_content).unwrap();
+        let digests = digest_file(file.path()).unwrap();
+        assert_eq!(example_content.len() as u64, digests.size);
+        assert_eq!(md5::compute(&example_content).0, digests.md5);
+        assert_eq!(&Sha256::digest(&example_content)[..], &digests.sha256[..]);
+    }
+
+    #[test]
+    fn test_byte_range_resolve() {
+        assert_eq!(Some((0, 9)), ByteRange::From(0, Some(9)).resolve(100));
+        assert_eq!(Some((90, 99)), ByteRange::From(90, None).resolve(100));
+        assert_eq!(Some((90, 99)), ByteRange::From(90, Some(500)).resolve(100));
+        assert_eq!(Some((90, 99)), ByteRange::Last(10).resolve(100));
+        assert_eq!(Some((0, 99)), ByteRange::Last(500).resolve(100));
+        assert_eq!(None, ByteRange::From(100, None).resolve(100));
+        assert_eq!(None, ByteRange::From(9, Some(0)).resolve(100));
+        assert_eq!(None, ByteRange::Last(0).resolve(100));
+        assert_eq!(None, ByteRange::From(0, None).resolve(0));
+    }
+
+    #[tokio::test]
+    async fn test_reader_hashing_matches_file_hashing() {
+        let example_content = vec![7u8; 200 * 1024 + 3];
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&example_content).unwrap();
+        let from_file = digest_file(file.path()).unwrap();
+        let from_reader = digest_reader(&example_content[..]).await.unwrap();
+        assert_eq!(from_file.md5, from_reader.md5);
+        assert_eq!(from_file.sha256, from_reader.sha256);
+    }
+}
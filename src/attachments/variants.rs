@@ -0,0 +1,129 @@
+use super::AttachmentStorageError;
+use image::{imageops::FilterType, io::Reader, GenericImageView};
+use std::io::Cursor;
+
+/// The widths images are resized to, which is what a `srcset` offers.
+pub const VARIANT_WIDTHS: [u32; 3] = [320, 640, 1280];
+
+/// What every variant is, whatever the original was.
+pub const VARIANT_MIME_TYPE: &str = "image/webp";
+
+/// How hard the WebP encoder squeezes, out of 100. Thumbnails don't need to
+/// be perfect.
+const WEBP_QUALITY: f32 = 80.0;
+
+/// The most pixels an image may have for variants to be made of it. A small
+/// file can claim to be an enormous image, and decoding it would take all the
+/// memory there is; this is about a 40 megapixel photo.
+const MAX_PIXELS: u64 = 40_000_000;
+
+/// Whether resized variants can be made of a file of this type.
+pub fn is_resizable(mime_type: &str) -> bool {
+    match mime_type {
+        "image/gif" | "image/jpeg" | "image/png" | "image/webp" => true,
+        _ => false,
+    }
+}
+
+/// What the variant of the file stored under a key is stored under, right
+/// next to the original.
+pub fn variant_key(key: &str, width: u32) -> String {
+    format!("{}.{}w.webp", key, width)
+}
+
+/// Whether a key is that of a variant of the file stored under another.
+pub fn is_variant_key_of(variant: &str, key: &str) -> bool {
+    VARIANT_WIDTHS
+        .iter()
+        .any(|width| variant == variant_key(key, *width))
+}
+
+/// Resizes an image to the given width as a WebP. Images which are already
+/// narrower than that keep their size, since scaling them up only makes them
+/// bigger. Images with more than [`MAX_PIXELS`] are refused before they are
+/// decoded.
+pub fn make_variant(
+    original: &[u8],
+    width: u32,
+) -> Result<Vec<u8>, AttachmentStorageError> {
+    let (claimed_width, claimed_height) = Reader::new(Cursor::new(original))
+        .with_guessed_format()?
+        .into_dimensions()?;
+    if claimed_width as u64 * claimed_height as u64 > MAX_PIXELS {
+        return Err(AttachmentStorageError::ImageTooLarge(
+            claimed_width,
+            claimed_height,
+        ));
+    }
+
+    let image = image::load_from_memory(original)?;
+
+    let (original_width, original_height) = image.dimensions();
+
+    let image = if original_width > width {
+        let height = (original_height as u64 * width as u64
+            / original_width as u64) as u32;
+        image.resize_exact(width, height.max(1), FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let encoder = webp::Encoder::from_image(&image)
+        .map_err(|e| AttachmentStorageError::WebPError(e.to_string()))?;
+
+    Ok(encoder.encode(WEBP_QUALITY).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attachments::variants::*;
+    use image::{png::PngEncoder, ColorType, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn test_make_variant() {
+        let variant = make_variant(&png(2000, 1000), 640).unwrap();
+        let variant = image::load_from_memory(&variant).unwrap();
+        assert_eq!((640, 320), variant.dimensions());
+
+        // never scaled up
+        let variant = make_variant(&png(100, 50), 640).unwrap();
+        let variant = image::load_from_memory(&variant).unwrap();
+        assert_eq!((100, 50), variant.dimensions());
+
+        assert!(make_variant(b"not an image", 640).is_err());
+    }
+
+    #[test]
+    fn test_make_variant_refuses_huge_images() {
+        // a PNG which says it is 50000x50000 but has no pixels at all, only
+        // its header and an empty IDAT chunk
+        let mut png = Vec::new();
+        assert!(PngEncoder::new(&mut png)
+            .encode(&[], 50000, 50000, ColorType::Rgb8)
+            .is_err());
+        png.truncate(33);
+        png.extend_from_slice(b"\0\0\0\0IDAT\x35\xaf\x06\x1e");
+
+        assert!(matches!(
+            make_variant(&png, 640),
+            Err(AttachmentStorageError::ImageTooLarge(50000, 50000))
+        ));
+    }
+
+    #[test]
+    fn test_variant_keys() {
+        assert_eq!("abc.320w.webp", variant_key("abc", 320));
+        assert!(is_variant_key_of("abc.1280w.webp", "abc"));
+        assert!(!is_variant_key_of("abc.1000w.webp", "abc"));
+        assert!(!is_variant_key_of("abd.320w.webp", "abc"));
+    }
+}
@@ -0,0 +1,599 @@
+//! Backs up and restores the whole site. Everything lives in the SQLite
+//! database and the flat attachment directory, so a backup is a gzipped tar of
+//! a snapshot of the one and a copy of the other, along with a manifest of
+//! md5s so that a restore can check it has everything intact before it
+//! replaces anything.
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use libsqlite3_sys as ffi;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    fs::{copy, create_dir, read_dir, remove_file, rename, File},
+    io::{Error as IOError, ErrorKind, Read},
+    os::raw::c_int,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+/// Bumped whenever the layout of a backup changes.
+const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest.toml";
+const DATABASE_NAME: &str = "database.sqlite";
+const ATTACHMENTS_DIR: &str = "attachments";
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("IOError {0}")]
+    IoError(#[from] IOError),
+
+    #[error("SQLite error {0}")]
+    SqliteError(String),
+
+    #[error("Path is not valid UTF-8 {0}")]
+    InvalidPath(PathBuf),
+
+    #[error("Could not read the manifest {0}")]
+    InvalidManifest(#[from] toml::de::Error),
+
+    #[error("Could not write the manifest {0}")]
+    ManifestWriteError(#[from] toml::ser::Error),
+
+    #[error("Backup has no manifest")]
+    MissingManifest,
+
+    #[error("Backup is version {0}, which this build cannot restore")]
+    UnsupportedVersion(u32),
+
+    #[error("Backup contains a file not in its manifest {0}")]
+    UnexpectedFile(String),
+
+    #[error("Backup is missing {0}")]
+    MissingFile(String),
+
+    #[error("Checksum mismatch on {0}")]
+    ChecksumMismatch(String),
+}
+
+/// Describes what is in a backup, and the md5 of each file so that it can be
+/// checked on restore.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub created_at: String,
+    pub database_md5: String,
+    pub attachments: Vec<ManifestAttachment>,
+}
+
+/// An attachment in a backup, named the same as it is in storage.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestAttachment {
+    pub name: String,
+    pub md5: String,
+}
+
+/// Writes a backup of the database and every file in the attachment
+/// directory to the given archive. The database is snapshotted with SQLite's
+/// online backup API, so the site can keep running while this happens.
+pub fn backup(
+    database_path: &Path,
+    attachment_dir: &Path,
+    archive_path: &Path,
+) -> Result<Manifest, BackupError> {
+    // everything is staged next to the archive, which is only put in place
+    // once it is complete
+    let archive_dir = parent_dir(archive_path);
+    let staging_dir = tempfile::Builder::new()
+        .prefix(".backup")
+        .tempdir_in(archive_dir)?;
+    let snapshot_path = staging_dir.path().join(DATABASE_NAME);
+    snapshot_database(database_path, &snapshot_path)?;
+
+    let archive_file = NamedTempFile::new_in(archive_dir)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(
+        archive_file.reopen()?,
+        Compression::default(),
+    ));
+
+    let database_md5 =
+        append_file(&mut archive, &snapshot_path, DATABASE_NAME)?;
+
+    let mut attachments = Vec::new();
+    for name in attachment_names(attachment_dir)? {
+        let md5 = append_file(
+            &mut archive,
+            &attachment_dir.join(&name),
+            &format!("{}/{}", ATTACHMENTS_DIR, name),
+        )?;
+        attachments.push(ManifestAttachment { name, md5 });
+    }
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        database_md5,
+        attachments,
+    };
+
+    // the manifest goes last, since it's only known once everything else has
+    // been read
+    let manifest_contents = toml::to_string(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(
+        &mut header,
+        MANIFEST_NAME,
+        manifest_contents.as_bytes(),
+    )?;
+    archive.into_inner()?.finish()?;
+
+    archive_file.persist(archive_path).map_err(|e| e.error)?;
+
+    Ok(manifest)
+}
+
+/// Replaces the database and every file in the attachment directory with
+/// what's in the given archive. Nothing is replaced until every file in the
+/// archive has been checked against the manifest. The site must not be
+/// running while this happens.
+pub fn restore(
+    archive_path: &Path,
+    database_path: &Path,
+    attachment_dir: &Path,
+) -> Result<Manifest, BackupError> {
+    // staged inside the attachment directory so that the attachments can be
+    // renamed into place, and with a leading dot so it isn't mistaken for one
+    let staging_dir = tempfile::Builder::new()
+        .prefix(".restore")
+        .tempdir_in(attachment_dir)?;
+    create_dir(staging_dir.path().join(ATTACHMENTS_DIR))?;
+
+    let mut archive =
+        tar::Archive::new(GzDecoder::new(File::open(archive_path)?));
+    let mut manifest_contents: Option<String> = None;
+    let mut found: HashMap<String, String> = HashMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+
+        if !entry.header().entry_type().is_file() {
+            return Err(BackupError::UnexpectedFile(entry_path));
+        }
+
+        if entry_path == MANIFEST_NAME {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+            manifest_contents = Some(contents);
+            continue;
+        }
+
+        if !is_restorable_path(&entry_path) {
+            return Err(BackupError::UnexpectedFile(entry_path));
+        }
+
+        let mut destination =
+            File::create(staging_dir.path().join(&entry_path))?;
+        let md5 = copy_with_md5(&mut entry, &mut destination)?;
+        found.insert(entry_path, md5);
+    }
+
+    let manifest: Manifest = match manifest_contents {
+        Some(contents) => toml::from_str(&contents)?,
+        None => return Err(BackupError::MissingManifest),
+    };
+    verify(&manifest, &found)?;
+
+    // the database is copied rather than renamed, since it may well be on a
+    // different filesystem from the attachments. any journal left over from
+    // the old one would be replayed onto the new one, so those go first.
+    let database_file = NamedTempFile::new_in(parent_dir(database_path))?;
+    copy(staging_dir.path().join(DATABASE_NAME), database_file.path())?;
+    for suffix in &["-wal", "-shm", "-journal"] {
+        let mut journal_path = database_path.as_os_str().to_owned();
+        journal_path.push(suffix);
+        remove_if_exists(Path::new(&journal_path))?;
+    }
+    database_file.persist(database_path).map_err(|e| e.error)?;
+
+    for name in attachment_names(attachment_dir)? {
+        if !manifest.attachments.iter().any(|a| a.name == name) {
+            remove_file(attachment_dir.join(name))?;
+        }
+    }
+    for attachment in manifest.attachments.iter() {
+        rename(
+            staging_dir
+                .path()
+                .join(ATTACHMENTS_DIR)
+                .join(&attachment.name),
+            attachment_dir.join(&attachment.name),
+        )?;
+    }
+
+    Ok(manifest)
+}
+
+/// Checks that what was found in an archive is exactly what its manifest says
+/// should be there, keyed by path within the archive.
+fn verify(
+    manifest: &Manifest,
+    found: &HashMap<String, String>,
+) -> Result<(), BackupError> {
+    if manifest.version != MANIFEST_VERSION {
+        return Err(BackupError::UnsupportedVersion(manifest.version));
+    }
+
+    let mut expected: Vec<(String, &str)> =
+        vec![(DATABASE_NAME.to_string(), &manifest.database_md5)];
+    for attachment in manifest.attachments.iter() {
+        expected.push((
+            format!("{}/{}", ATTACHMENTS_DIR, attachment.name),
+            &attachment.md5,
+        ));
+    }
+
+    for (path, md5) in expected.iter() {
+        match found.get(path) {
+            Some(found_md5) if found_md5.eq_ignore_ascii_case(md5) => {}
+            Some(_) => return Err(BackupError::ChecksumMismatch(path.clone())),
+            None => return Err(BackupError::MissingFile(path.clone())),
+        }
+    }
+
+    for path in found.keys() {
+        if !expected
+            .iter()
+            .any(|(expected_path, _)| expected_path == path)
+        {
+            return Err(BackupError::UnexpectedFile(path.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Only the database and plain files directly in the attachments directory
+/// may come out of an archive. Anything else could be an attempt to write
+/// somewhere it shouldn't.
+fn is_restorable_path(path: &str) -> bool {
+    if path == DATABASE_NAME {
+        return true;
+    }
+
+    match path
+        .strip_prefix(ATTACHMENTS_DIR)
+        .and_then(|p| p.strip_prefix('/'))
+    {
+        Some(name) => is_attachment_name(name),
+        None => false,
+    }
+}
+
+/// Attachments are stored flat, by the hex of their blob's sha256 or by id.
+/// Dotfiles are uploads in progress and the like, and aren't attachments.
+fn is_attachment_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains('/')
+        && !name.contains('\\')
+}
+
+/// The names of every attachment file in the attachment directory, sorted so
+/// that backups come out in a stable order.
+fn attachment_names(attachment_dir: &Path) -> Result<Vec<String>, BackupError> {
+    let mut names = Vec::new();
+
+    for dir_entry in read_dir(attachment_dir)? {
+        let dir_entry = dir_entry?;
+        if !dir_entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let name = dir_entry
+            .file_name()
+            .into_string()
+            .map_err(|_| BackupError::InvalidPath(dir_entry.path()))?;
+        if is_attachment_name(&name) {
+            names.push(name);
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+/// Appends a file to the archive, returning its md5 as read on the way in.
+fn append_file<W: std::io::Write>(
+    archive: &mut tar::Builder<W>,
+    path: &Path,
+    archive_path: &str,
+) -> Result<String, BackupError> {
+    let file = File::open(path)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata(&file.metadata()?);
+    header.set_cksum();
+
+    let mut reader = Md5Reader::new(file);
+    archive.append_data(&mut header, archive_path, &mut reader)?;
+
+    Ok(reader.hex_digest())
+}
+
+/// Copies everything from the reader to the writer, returning its md5.
+fn copy_with_md5<R: Read, W: std::io::Write>(
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<String, IOError> {
+    let mut reader = Md5Reader::new(reader);
+    std::io::copy(&mut reader, writer)?;
+    Ok(reader.hex_digest())
+}
+
+/// Digests everything read through it.
+struct Md5Reader<R: Read> {
+    inner: R,
+    context: md5::Context,
+}
+
+impl<R: Read> Md5Reader<R> {
+    fn new(inner: R) -> Md5Reader<R> {
+        Md5Reader {
+            inner,
+            context: md5::Context::new(),
+        }
+    }
+
+    fn hex_digest(self) -> String {
+        format!("{:x}", self.context.compute())
+    }
+}
+
+impl<R: Read> Read for Md5Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IOError> {
+        let read = self.inner.read(buf)?;
+        self.context.consume(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Copies the database at the source path to the destination path using
+/// SQLite's online backup API, which gives a consistent snapshot even while
+/// other connections are writing to it.
+fn snapshot_database(
+    source_path: &Path,
+    destination_path: &Path,
+) -> Result<(), BackupError> {
+    let source = RawDatabase::open(source_path, ffi::SQLITE_OPEN_READONLY)?;
+    let destination = RawDatabase::open(
+        destination_path,
+        ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE,
+    )?;
+    let main = CString::new("main").unwrap();
+
+    unsafe {
+        let backup = ffi::sqlite3_backup_init(
+            destination.0,
+            main.as_ptr(),
+            source.0,
+            main.as_ptr(),
+        );
+        if backup.is_null() {
+            return Err(destination.error());
+        }
+
+        // copy every page in one step, waiting out anyone holding a lock
+        let rc = loop {
+            match ffi::sqlite3_backup_step(backup, -1) {
+                ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED => {
+                    ffi::sqlite3_sleep(100);
+                }
+                rc => break rc,
+            }
+        };
+        ffi::sqlite3_backup_finish(backup);
+
+        if rc != ffi::SQLITE_DONE {
+            return Err(BackupError::SqliteError(
+                CStr::from_ptr(ffi::sqlite3_errstr(rc))
+                    .to_string_lossy()
+                    .into_owned(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// A bare SQLite connection, closed on drop. Diesel doesn't expose the
+/// handle underneath its connections, which the backup API needs.
+struct RawDatabase(*mut ffi::sqlite3);
+
+impl RawDatabase {
+    fn open(path: &Path, flags: c_int) -> Result<RawDatabase, BackupError> {
+        let c_path = match path.to_str() {
+            Some(path) => CString::new(path)
+                .map_err(|_| BackupError::InvalidPath(path.into()))?,
+            None => return Err(BackupError::InvalidPath(path.to_path_buf())),
+        };
+
+        let mut handle: *mut ffi::sqlite3 = std::ptr::null_mut();
+        let rc = unsafe {
+            ffi::sqlite3_open_v2(
+                c_path.as_ptr(),
+                &mut handle,
+                flags,
+                std::ptr::null(),
+            )
+        };
+
+        // a handle is usually allocated even when opening fails, and still
+        // needs closing
+        let database = RawDatabase(handle);
+        if rc != ffi::SQLITE_OK {
+            return Err(database.error());
+        }
+
+        Ok(database)
+    }
+
+    fn error(&self) -> BackupError {
+        let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.0)) };
+        BackupError::SqliteError(message.to_string_lossy().into_owned())
+    }
+}
+
+impl Drop for RawDatabase {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::sqlite3_close(self.0);
+        }
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), IOError> {
+    match remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The directory a file is in, which for a bare file name is the current one.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::*;
+    use diesel::{Connection, RunQueryDsl, SqliteConnection};
+    use std::io::Write;
+
+    #[derive(QueryableByName)]
+    struct Thing {
+        #[sql_type = "diesel::sql_types::Text"]
+        name: String,
+    }
+
+    fn manifest_with(attachments: &[(&str, &str)]) -> Manifest {
+        Manifest {
+            version: MANIFEST_VERSION,
+            created_at: "2020-11-01T00:00:00+00:00".to_string(),
+            database_md5: "00".to_string(),
+            attachments: attachments
+                .iter()
+                .map(|(name, md5)| ManifestAttachment {
+                    name: name.to_string(),
+                    md5: md5.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn found_with(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files
+            .iter()
+            .map(|(path, md5)| (path.to_string(), md5.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_verify() {
+        let manifest = manifest_with(&[("1", "aa")]);
+
+        assert!(verify(
+            &manifest,
+            &found_with(&[(DATABASE_NAME, "00"), ("attachments/1", "AA")])
+        )
+        .is_ok());
+        assert!(matches!(
+            verify(
+                &manifest,
+                &found_with(&[(DATABASE_NAME, "00"), ("attachments/1", "bb")])
+            ),
+            Err(BackupError::ChecksumMismatch(path)) if path == "attachments/1"
+        ));
+        assert!(matches!(
+            verify(&manifest, &found_with(&[(DATABASE_NAME, "00")])),
+            Err(BackupError::MissingFile(path)) if path == "attachments/1"
+        ));
+        assert!(matches!(
+            verify(
+                &manifest,
+                &found_with(&[
+                    (DATABASE_NAME, "00"),
+                    ("attachments/1", "aa"),
+                    ("attachments/2", "aa"),
+                ])
+            ),
+            Err(BackupError::UnexpectedFile(path)) if path == "attachments/2"
+        ));
+    }
+
+    #[test]
+    fn test_is_restorable_path() {
+        assert!(is_restorable_path("database.sqlite"));
+        assert!(is_restorable_path("attachments/12"));
+        assert!(!is_restorable_path("attachments/"));
+        assert!(!is_restorable_path("attachments/.upload1234"));
+        assert!(!is_restorable_path("attachments/../../etc/passwd"));
+        assert!(!is_restorable_path("../database.sqlite"));
+        assert!(!is_restorable_path("/etc/passwd"));
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let site = tempfile::tempdir().unwrap();
+        let database_path = site.path().join("app.sqlite");
+        let attachment_dir = site.path().join("attachments");
+        let archive_path = site.path().join("backup.tar.gz");
+        create_dir(&attachment_dir).unwrap();
+
+        let conn = SqliteConnection::establish(database_path.to_str().unwrap())
+            .unwrap();
+        diesel::sql_query("CREATE TABLE things (name TEXT NOT NULL)")
+            .execute(&conn)
+            .unwrap();
+        diesel::sql_query("INSERT INTO things (name) VALUES ('before')")
+            .execute(&conn)
+            .unwrap();
+        write!(File::create(attachment_dir.join("1")).unwrap(), "one").unwrap();
+        File::create(attachment_dir.join(".upload1234")).unwrap();
+
+        let manifest =
+            backup(&database_path, &attachment_dir, &archive_path).unwrap();
+        assert_eq!(manifest.attachments.len(), 1);
+        assert_eq!(manifest.attachments[0].name, "1");
+
+        // change everything after the backup was taken
+        diesel::sql_query("DELETE FROM things")
+            .execute(&conn)
+            .unwrap();
+        drop(conn);
+        remove_file(attachment_dir.join("1")).unwrap();
+        write!(File::create(attachment_dir.join("2")).unwrap(), "two").unwrap();
+
+        restore(&archive_path, &database_path, &attachment_dir).unwrap();
+
+        let conn = SqliteConnection::establish(database_path.to_str().unwrap())
+            .unwrap();
+        let things = diesel::sql_query("SELECT name FROM things")
+            .load::<Thing>(&conn)
+            .unwrap();
+        assert_eq!(things.len(), 1);
+        assert_eq!(things[0].name, "before");
+        assert_eq!(
+            std::fs::read_to_string(attachment_dir.join("1")).unwrap(),
+            "one"
+        );
+        assert!(!attachment_dir.join("2").exists());
+        assert!(attachment_dir.join(".upload1234").exists());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::models::KnownPermission;
 use clap::{crate_authors, crate_version, Clap};
 
 #[derive(Clap)]
@@ -12,6 +13,11 @@ pub enum SubCommand {
     Migrate(Migrate),
     Serve(Serve),
     Permission(Permission),
+    User(User),
+    Jam(Jam),
+    Attachments(Attachments),
+    Backup(Backup),
+    Restore(Restore),
 }
 
 /// Migrates the uDevGames database to the current schema
@@ -43,9 +49,10 @@ pub struct PermissionGrant {
     #[clap(short, long)]
     pub user: String,
 
-    /// The permission to grant to the user
+    /// The permission to grant to the user: admin, banned, moderator, judge,
+    /// or unmetered
     #[clap(short, long)]
-    pub permission: String,
+    pub permission: KnownPermission,
 }
 
 /// Revokes a permission from a user
@@ -57,7 +64,7 @@ pub struct PermissionRevoke {
 
     /// The permission to revoke from the user
     #[clap(short, long)]
-    pub permission: String,
+    pub permission: KnownPermission,
 }
 
 /// Show permissions for a user, or users with a permission
@@ -69,5 +76,231 @@ pub struct PermissionShow {
 
     /// Show all users with this permission
     #[clap(short, long)]
-    pub permission: Option<String>,
+    pub permission: Option<KnownPermission>,
+}
+
+/// List, inspect, delete, and sync users
+#[derive(Clap)]
+pub struct User {
+    #[clap(subcommand)]
+    pub subcmd: UserSubCommand,
+}
+
+#[derive(Clap)]
+pub enum UserSubCommand {
+    List(UserList),
+    Show(UserShow),
+    Delete(UserDelete),
+    Sync(UserSync),
+}
+
+/// Lists users, optionally only those matching every filter given
+#[derive(Clap)]
+pub struct UserList {
+    /// Only list users with this permission
+    #[clap(short, long)]
+    pub permission: Option<KnownPermission>,
+
+    /// Only list users whose login contains this, ignoring case
+    #[clap(short, long)]
+    pub login: Option<String>,
+}
+
+/// Shows a user's profile, permissions, entries, and attachments
+#[derive(Clap)]
+pub struct UserShow {
+    /// The user to show, either by @login or numeric id
+    #[clap(short, long)]
+    pub user: String,
+}
+
+/// Deletes a user and their permissions
+#[derive(Clap)]
+pub struct UserDelete {
+    /// The user to delete, either by @login or numeric id
+    #[clap(short, long)]
+    pub user: String,
+
+    /// Also delete their entries, updates, and any attachments which nothing
+    /// else uses. Without this a user who has made anything is not deleted
+    #[clap(long)]
+    pub purge: bool,
+}
+
+/// Refreshes cached users from Github, sorting out logins which more than one
+/// cached user has ended up with after renames
+#[derive(Clap)]
+pub struct UserSync {
+    /// The user to sync, either by @login or numeric id
+    #[clap(short, long, conflicts_with = "all")]
+    pub user: Option<String>,
+
+    /// Sync every cached user
+    #[clap(long)]
+    pub all: bool,
+}
+
+/// Create, list, import, and export jams
+#[derive(Clap)]
+pub struct Jam {
+    #[clap(subcommand)]
+    pub subcmd: JamSubCommand,
+}
+
+#[derive(Clap)]
+pub enum JamSubCommand {
+    Create(JamCreate),
+    List(JamList),
+    SetState(JamSetState),
+    Import(JamImport),
+    Export(JamExport),
+}
+
+/// Creates a draft jam
+#[derive(Clap)]
+pub struct JamCreate {
+    /// The title of the jam
+    #[clap(short, long)]
+    pub title: String,
+
+    /// The jam's part of its url, which follows the title if left out
+    #[clap(long)]
+    pub slug: Option<String>,
+
+    /// A sentence or so about the jam
+    #[clap(long, default_value = "")]
+    pub summary: String,
+
+    /// The first day of the jam, like 2021-10-01
+    #[clap(long)]
+    pub start: String,
+
+    /// The last day of the jam, like 2021-10-31
+    #[clap(long)]
+    pub end: String,
+
+    /// A markdown file with the jam's full description
+    #[clap(long, alias = "summary-file")]
+    pub description_file: Option<String>,
+}
+
+/// Lists jams, newest first
+#[derive(Clap)]
+pub struct JamList {
+    /// Only list jams in this approval state
+    #[clap(short, long)]
+    pub state: Option<String>,
+}
+
+/// Moves a jam to an approval state: draft, submitted, approved, or rejected
+#[derive(Clap)]
+pub struct JamSetState {
+    /// The id of the jam
+    #[clap(short, long)]
+    pub jam: i32,
+
+    /// The approval state to move it to
+    #[clap(short, long)]
+    pub state: String,
+
+    /// Why the jam was rejected, which is needed to reject it
+    #[clap(short, long)]
+    pub reason: Option<String>,
+}
+
+/// Creates a draft jam from a jam file, or updates an existing jam from one
+#[derive(Clap)]
+pub struct JamImport {
+    /// The jam file to read
+    #[clap(short, long)]
+    pub file: String,
+
+    /// Update this jam instead of creating a new one
+    #[clap(short, long)]
+    pub jam: Option<i32>,
+}
+
+/// Writes a jam out as a jam file
+#[derive(Clap)]
+pub struct JamExport {
+    /// The id of the jam
+    #[clap(short, long)]
+    pub jam: i32,
+
+    /// Where to write the jam file, rather than to standard out
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+/// Check attachments in storage
+#[derive(Clap)]
+pub struct Attachments {
+    #[clap(subcommand)]
+    pub subcmd: AttachmentsSubCommand,
+}
+
+#[derive(Clap)]
+pub enum AttachmentsSubCommand {
+    Verify(AttachmentsVerify),
+    Dedupe(AttachmentsDedupe),
+    Usage(AttachmentsUsage),
+    Gc(AttachmentsGc),
+}
+
+/// Recomputes the digests of every attachment, reporting any whose file is
+/// missing or doesn't match, and any file in storage which isn't an
+/// attachment. Records the sha256 of intact attachments which don't have one
+/// yet. Exits with a failure if anything is found
+#[derive(Clap)]
+pub struct AttachmentsVerify {
+    /// Unpublish attachments whose file is missing or doesn't match, so they
+    /// are no longer served
+    #[clap(long)]
+    pub unpublish: bool,
+}
+
+/// Moves attachments uploaded before files were stored by their contents over
+/// to being stored that way, so that attachments with the same contents share
+/// one file. Attachments whose file is missing or doesn't match are left alone.
+/// Run once after migrating; running it again does nothing
+#[derive(Clap)]
+pub struct AttachmentsDedupe {}
+
+/// Reports the users and entries whose attachments take up the most space,
+/// counted the same way as the upload quotas
+#[derive(Clap)]
+pub struct AttachmentsUsage {
+    /// How many users and entries to show
+    #[clap(short, long, default_value = "10")]
+    pub top: usize,
+}
+
+/// Removes attachments which were deleted longer ago than the retention
+/// window, along with attachments older than that which nothing uses, and
+/// their files once no other attachment has the same contents
+#[derive(Clap)]
+pub struct AttachmentsGc {
+    /// How many days deleted and unused attachments are kept for
+    #[clap(short, long, default_value = "30")]
+    pub retention_days: i64,
+
+    /// Only report what would be removed
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+/// Writes the database and every attachment to a backup archive. Safe to run
+/// while the website is up
+#[derive(Clap)]
+pub struct Backup {
+    /// Where to write the archive
+    pub archive: String,
+}
+
+/// Replaces the database and every attachment with what is in a backup
+/// archive, once it has been checked. Stop the website first
+#[derive(Clap)]
+pub struct Restore {
+    /// The archive to restore from
+    pub archive: String,
 }
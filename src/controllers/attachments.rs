@@ -1,19 +1,813 @@
-use crate::{attachments::AttachmentStorage, db::DbPool, models::Attachment};
-use rocket::{get, response::Stream, tokio::fs::File, State};
+use crate::{
+    attachments::{
+        is_resizable, AttachmentStorage, AttachmentStorageError, ByteRange,
+        LoadedAttachment, StoredFile, VARIANT_MIME_TYPE, VARIANT_WIDTHS,
+    },
+    db::{DbConn, DbPool},
+    models::{Attachment, Blob, JamEntry, ModelError},
+    template_helpers::{AttachmentContext, ParticipantOnly, UserOptional},
+};
+use diesel::Connection;
+use rocket::{
+    data::ToByteUnit,
+    delete, get,
+    http::{ContentType, Status},
+    post,
+    request::{FromRequest, Outcome},
+    response::{
+        status::Custom, Redirect, Responder, Response, Result as RocketResult,
+    },
+    tokio::{fs::File, io::AsyncWriteExt},
+    Data, Request, State,
+};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use std::io::Read;
+use thiserror::Error;
+use tokio_util::codec::{BytesCodec, FramedRead};
 
-// match stuff like udevgames.com/attachments/1/my_file.jpeg
-#[get("/attachments/<id>/<_name>")]
+/// How big of a file we're willing to take, and how much in all, managed as
+/// Rocket state.
+#[derive(Debug)]
+pub struct UploadLimits {
+    /// The largest file that may be uploaded, in bytes.
+    pub max_attachment_size: u64,
+
+    /// How many bytes of attachments each participant may upload in all.
+    pub user_quota: u64,
+
+    /// How many bytes of attachments may belong to each entry, counting
+    /// those on its updates.
+    pub jam_entry_quota: u64,
+}
+
+/// Room for the multipart boundaries and headers which surround the file
+/// itself, so that a file right at the limit isn't cut off by them.
+const MULTIPART_OVERHEAD: u64 = 64 * 1024;
+
+/// How much of the file to read when guessing what it is. Every signature
+/// `infer` knows about lives well within this.
+const SNIFF_LENGTH: u64 = 8192;
+
+/// The headers a downloader sends to check whether what they have cached is
+/// still good, or to resume a download part of the way through.
+pub struct DownloadHeaders {
+    /// The ETags of whatever copies they have.
+    if_none_match: Option<String>,
+    /// The bytes they want, if not all of them.
+    range: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for DownloadHeaders {
+    type Error = ();
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(DownloadHeaders {
+            if_none_match: req
+                .headers()
+                .get_one("If-None-Match")
+                .map(str::to_string),
+            range: req.headers().get_one("Range").map(str::to_string),
+        })
+    }
+}
+
+/// An attachment on its way to a downloader, described well enough that
+/// browsers can cache it and resume it.
+pub enum Download {
+    /// What they have cached is still good.
+    NotModified { etag: String },
+
+    /// The file, or the part of it which was asked for.
+    File {
+        file: StoredFile,
+        mime_type: String,
+        etag: String,
+    },
+
+    /// Storage serves the file itself.
+    Redirect(Redirect),
+
+    /// The part of the file which was asked for isn't there.
+    RangeNotSatisfiable { size: u64 },
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for Download {
+    fn respond_to(self, request: &'r Request<'_>) -> RocketResult<'o> {
+        match self {
+            Download::NotModified { etag } => Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", etag)
+                .ok(),
+            Download::File {
+                file,
+                mime_type,
+                etag,
+            } => {
+                let mut response = Response::build();
+                response
+                    .raw_header("ETag", etag)
+                    .raw_header("Accept-Ranges", "bytes");
+
+                if let Some(content_type) =
+                    ContentType::parse_flexible(&mime_type)
+                {
+                    response.header(content_type);
+                }
+                if let Some(modified) = file.modified {
+                    response.raw_header(
+                        "Last-Modified",
+                        modified
+                            .format("%a, %d %b %Y %H:%M:%S GMT")
+                            .to_string(),
+                    );
+                }
+                match file.range {
+                    Some((start, end)) => response
+                        .status(Status::PartialContent)
+                        .raw_header(
+                            "Content-Range",
+                            format!("bytes {}-{}/{}", start, end, file.size),
+                        )
+                        .raw_header(
+                            "Content-Length",
+                            (end - start + 1).to_string(),
+                        ),
+                    None => response
+                        .raw_header("Content-Length", file.size.to_string()),
+                };
+
+                response.streamed_body(file.reader).ok()
+            }
+            Download::Redirect(redirect) => redirect.respond_to(request),
+            Download::RangeNotSatisfiable { size } => Response::build()
+                .status(Status::RangeNotSatisfiable)
+                .raw_header("Content-Range", format!("bytes */{}", size))
+                .ok(),
+        }
+    }
+}
+
+// match stuff like udevgames.com/attachments/1/my_file.jpeg, or
+// udevgames.com/attachments/1/my_file.jpeg?w=640 for a resized variant
+#[get("/attachments/<id>/<_name>?<w>")]
 pub async fn get_attachment(
     pool: State<'_, DbPool>,
     attachment_storage: State<'_, AttachmentStorage>,
+    user_optional: UserOptional,
+    download_headers: DownloadHeaders,
     id: i32,
     _name: String,
-) -> Result<Stream<File>, super::HandlerError> {
+    w: Option<u32>,
+) -> Result<Download, super::HandlerError> {
+    let attachment = {
+        let conn = pool.get()?;
+        match Attachment::find_by_id(&conn, id)? {
+            // attachments nobody may see yet are as good as missing
+            Some(attachment)
+                if attachment.is_visible_to(
+                    &conn,
+                    user_optional.user_id(),
+                    user_optional.is_admin(),
+                )? =>
+            {
+                attachment
+            }
+            _ => return Err(super::HandlerError::NotFound),
+        }
+    };
+
+    // only the widths in a srcset are made, so that nobody can fill storage
+    // by asking for every width there is
+    let width = match w {
+        Some(width)
+            if VARIANT_WIDTHS.contains(&width)
+                && is_resizable(&attachment.mime_type) =>
+        {
+            Some(width)
+        }
+        Some(_) => return Err(super::HandlerError::NotFound),
+        None => None,
+    };
+
+    let etag = etag(&attachment, width);
+    if let Some(if_none_match) = &download_headers.if_none_match {
+        if etag_matches(if_none_match, &etag) {
+            return Ok(Download::NotModified { etag });
+        }
+    }
+
+    let range = download_headers.range.as_deref().and_then(parse_range);
+    let loaded = match width {
+        Some(width) => {
+            attachment_storage
+                .load_variant(
+                    &attachment.storage_key(),
+                    width,
+                    range,
+                    &attachment.name,
+                )
+                .await
+        }
+        None => {
+            attachment_storage
+                .load(
+                    &attachment.storage_key(),
+                    range,
+                    &attachment.mime_type,
+                    &attachment.name,
+                )
+                .await
+        }
+    };
+    let loaded = match loaded {
+        Err(AttachmentStorageError::RangeNotSatisfiable(size)) => {
+            return Ok(Download::RangeNotSatisfiable { size })
+        }
+        // there won't ever be a variant of an image too big to resize
+        Err(AttachmentStorageError::ImageTooLarge(_, _)) => {
+            return Err(super::HandlerError::NotFound)
+        }
+        loaded => loaded?,
+    };
+
+    Ok(match loaded {
+        LoadedAttachment::Stream(file) => Download::File {
+            file,
+            mime_type: match width {
+                Some(_) => VARIANT_MIME_TYPE.to_string(),
+                None => attachment.mime_type,
+            },
+            etag,
+        },
+        LoadedAttachment::Redirect(url) => {
+            Download::Redirect(Redirect::to(url))
+        }
+    })
+}
+
+/// Deletes an attachment, which only its uploader and admins may do. It stops
+/// being served straight away, but its file is only removed by `udevgames
+/// attachments gc` after the retention window.
+#[delete("/attachments/<id>")]
+pub async fn delete_attachment(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    id: i32,
+) -> Result<Status, super::HandlerError> {
     let conn = pool.get()?;
-    let attachment = match Attachment::find_published_by_id(&conn, id)? {
+    let mut attachment = match Attachment::find_by_id(&conn, id)? {
         Some(attachment) => attachment,
         None => return Err(super::HandlerError::NotFound),
     };
-    let f = attachment_storage.load(attachment.id)?;
-    Ok(Stream::from(File::from_std(f)))
+
+    if !attachment
+        .is_deletable_by(participant.user_id(), participant.is_admin())
+    {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    attachment.soft_delete(&conn)?;
+
+    Ok(Status::NoContent)
+}
+
+/// A strong ETag for an attachment, or one of its variants, from the digest
+/// of its contents. An attachment's contents never change, so this never goes
+/// stale.
+fn etag(attachment: &Attachment, width: Option<u32>) -> String {
+    let digest = match &attachment.sha256 {
+        Some(sha256) => hex::encode(sha256),
+        None => hex::encode(&attachment.md5),
+    };
+
+    match width {
+        Some(width) => format!("\"{}-{}w\"", digest, width),
+        None => format!("\"{}\"", digest),
+    }
+}
+
+/// Whether an If-None-Match header names the given ETag. Weak ETags are
+/// compared as if they were strong, which is how If-None-Match works.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+/// Parses a Range header asking for a single range of bytes. Anything else,
+/// including asking for several ranges at once, is ignored so that the whole
+/// file is sent instead, which is always allowed.
+fn parse_range(range: &str) -> Option<ByteRange> {
+    let range = range.trim().strip_prefix("bytes=")?.trim();
+    if range.contains(',') {
+        return None;
+    }
+
+    let dash = range.find('-')?;
+    let (start, end) = (range[..dash].trim(), range[dash + 1..].trim());
+
+    match (start.is_empty(), end.is_empty()) {
+        (true, false) => Some(ByteRange::Last(end.parse().ok()?)),
+        (false, true) => Some(ByteRange::From(start.parse().ok()?, None)),
+        (false, false) => Some(ByteRange::From(
+            start.parse().ok()?,
+            Some(end.parse().ok()?),
+        )),
+        (true, true) => None,
+    }
+}
+
+/// Everything that can go wrong with an upload. These are reported back as
+/// JSON rather than through the error catchers, since the editor that sent the
+/// upload wants to show the reason to the participant.
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("Expected a multipart/form-data upload with a file field")]
+    Malformed,
+
+    #[error("The file is larger than the limit of {0} bytes")]
+    TooLarge(u64),
+
+    #[error("You have used up your upload quota of {0} bytes")]
+    UserQuotaExceeded(u64),
+
+    #[error("This entry has used up its upload quota of {0} bytes")]
+    JamEntryQuotaExceeded(u64),
+
+    #[error("Failed to read the upload with error {0}")]
+    MultipartError(#[from] multer::Error),
+
+    #[error("Failed to write the upload with error {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to store the attachment with error {0}")]
+    AttachmentStorageError(#[from] crate::attachments::AttachmentStorageError),
+
+    #[error("Could not get a connection from the pool with error {0}")]
+    PoolError(#[from] diesel::r2d2::PoolError),
+
+    #[error("Failed to query the database with error {0}")]
+    DatabaseError(#[from] ModelError),
+
+    #[error("Diesel Error {0}")]
+    DieselError(#[from] diesel::result::Error),
+
+    #[error("{0}")]
+    HandlerError(#[from] super::HandlerError),
+}
+
+#[derive(Debug, Serialize)]
+struct UploadErrorContext {
+    error: String,
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for UploadError {
+    fn respond_to(self, request: &'r Request<'_>) -> RocketResult<'o> {
+        let status = match &self {
+            UploadError::Malformed => Status::BadRequest,
+            UploadError::MultipartError(_) => Status::BadRequest,
+            UploadError::TooLarge(_) => Status::PayloadTooLarge,
+            UploadError::UserQuotaExceeded(_) => Status::PayloadTooLarge,
+            UploadError::JamEntryQuotaExceeded(_) => Status::PayloadTooLarge,
+            UploadError::HandlerError(super::HandlerError::Unauthorized) => {
+                Status::Unauthorized
+            }
+            UploadError::HandlerError(super::HandlerError::Forbidden) => {
+                Status::Forbidden
+            }
+            UploadError::HandlerError(super::HandlerError::NotFound) => {
+                Status::NotFound
+            }
+            _ => Status::InternalServerError,
+        };
+
+        // don't tell the world about our internals
+        let error = if status == Status::InternalServerError {
+            print!("Internal error {:?}", self);
+            "Something went wrong storing your file".to_string()
+        } else {
+            self.to_string()
+        };
+
+        Custom(status, Json(UploadErrorContext { error })).respond_to(request)
+    }
+}
+
+/// Accepts a single file in the `file` field of a multipart form, storing it
+/// as a new attachment.
+#[post("/attachments", data = "<data>")]
+pub async fn upload_attachment(
+    pool: State<'_, DbPool>,
+    attachment_storage: State<'_, AttachmentStorage>,
+    upload_limits: State<'_, UploadLimits>,
+    participant: ParticipantOnly,
+    content_type: &ContentType,
+    data: Data,
+) -> Result<Json<AttachmentContext>, UploadError> {
+    let attachment = receive_upload(
+        &pool,
+        &attachment_storage,
+        &upload_limits,
+        &participant,
+        None,
+        content_type,
+        data,
+    )
+    .await?;
+
+    Ok(Json(AttachmentContext::from_model(&attachment)))
+}
+
+/// Stores the file in the `file` field of a multipart form as a new
+/// attachment. The body is streamed to disk as it arrives, so large uploads
+/// never sit in memory, and only kept if nothing with the same contents has
+/// been uploaded before. What the file is gets decided by looking at its
+/// contents, since whatever the browser claims can't be trusted. The
+/// attachment doesn't belong to anything until it is used, and until then only
+/// the participant who uploaded it can see it. The upload counts against the
+/// participant's quota and that of the entry it is for, if any.
+pub(super) async fn receive_upload(
+    pool: &DbPool,
+    attachment_storage: &AttachmentStorage,
+    upload_limits: &UploadLimits,
+    participant: &ParticipantOnly,
+    jam_entry: Option<&JamEntry>,
+    content_type: &ContentType,
+    data: Data,
+) -> Result<Attachment, UploadError> {
+    let boundary =
+        match content_type.params().find(|(key, _)| *key == "boundary") {
+            Some((_, boundary)) if content_type.is_form_data() => {
+                boundary.to_string()
+            }
+            _ => return Err(UploadError::Malformed),
+        };
+
+    let quota = {
+        let conn = pool.get()?;
+        remaining_quota(
+            &conn,
+            upload_limits,
+            participant.user_id(),
+            participant.is_unmetered(),
+            jam_entry,
+        )?
+    };
+
+    let max_size = upload_limits.max_attachment_size;
+    let body = FramedRead::new(
+        data.open((max_size + MULTIPART_OVERHEAD).bytes()),
+        BytesCodec::new(),
+    );
+    let mut multipart = multer::Multipart::new(body, boundary);
+
+    while let Some(mut field) = multipart.next_field().await? {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let name = sanitize_file_name(field.file_name().unwrap_or(""));
+        let temp_file = attachment_storage.temp_file()?;
+        let mut file = File::from_std(temp_file.reopen()?);
+        let mut size: u64 = 0;
+
+        while let Some(chunk) = field.chunk().await? {
+            size += chunk.len() as u64;
+            if size > max_size {
+                return Err(UploadError::TooLarge(max_size));
+            }
+            if let Some((remaining, exceeded)) = &quota {
+                if size > *remaining {
+                    return Err((*exceeded).into());
+                }
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        let mime_type = sniff_mime_type(temp_file.reopen()?)?;
+        let temp_path = temp_file.into_temp_path();
+        let (attachment, blob) = {
+            let conn = pool.get()?;
+            // checked again alongside the insert, so that uploads finishing
+            // at the same time can't each fit into what was left
+            conn.transaction::<(Attachment, Blob), UploadError, _>(|| {
+                let (attachment, blob) = Attachment::create(
+                    &conn,
+                    &temp_path,
+                    &name,
+                    &mime_type,
+                    participant.user_id(),
+                )?;
+                check_quota(
+                    &conn,
+                    upload_limits,
+                    participant.user_id(),
+                    participant.is_unmetered(),
+                    jam_entry,
+                    attachment.size as u64,
+                )?;
+                Ok((attachment, blob))
+            })?
+        };
+
+        // the file only needs storing the first time anyone uploads these
+        // contents, or if that upload hasn't managed to store it yet; storing
+        // it again is harmless, so that is what happens if in doubt
+        let is_stored = blob.ref_count > 1
+            && attachment_storage
+                .exists(&blob.key())
+                .await
+                .unwrap_or(false);
+        if !is_stored {
+            if let Err(e) = attachment_storage
+                .store(&temp_path, &blob.key(), &mime_type)
+                .await
+            {
+                // deleting gives up the reference to the blob, which another
+                // upload of the same contents may have taken in the meantime,
+                // so the blob can outlive this; that upload stores the file
+                let conn = pool.get()?;
+                attachment.delete(&conn)?;
+                return Err(e.into());
+            }
+        }
+
+        return Ok(attachment);
+    }
+
+    Err(UploadError::Malformed)
+}
+
+/// A quota an upload counts against, along with how many bytes it allows.
+#[derive(Debug, Clone, Copy)]
+enum Quota {
+    User(u64),
+    JamEntry(u64),
+}
+
+impl From<Quota> for UploadError {
+    fn from(quota: Quota) -> Self {
+        match quota {
+            Quota::User(quota) => UploadError::UserQuotaExceeded(quota),
+            Quota::JamEntry(quota) => UploadError::JamEntryQuotaExceeded(quota),
+        }
+    }
+}
+
+/// How many more bytes the user may upload, along with the quota which that is
+/// left of, or nothing when they may upload as much as they like. Whichever of
+/// their own quota and the entry's has less left is the one that counts.
+fn remaining_quota(
+    conn: &DbConn,
+    upload_limits: &UploadLimits,
+    user_id: i64,
+    is_unmetered: bool,
+    jam_entry: Option<&JamEntry>,
+) -> Result<Option<(u64, Quota)>, UploadError> {
+    if is_unmetered {
+        return Ok(None);
+    }
+
+    let user_quota = upload_limits.user_quota;
+    let used = Attachment::total_size_by_uploader(conn, user_id)?;
+    let mut quota = (user_quota.saturating_sub(used), Quota::User(user_quota));
+
+    if let Some(jam_entry) = jam_entry {
+        let jam_entry_quota = upload_limits.jam_entry_quota;
+        let used = Attachment::total_size_by_jam_entry(conn, jam_entry)?;
+        let remaining = jam_entry_quota.saturating_sub(used);
+
+        if remaining < quota.0 {
+            quota = (remaining, Quota::JamEntry(jam_entry_quota));
+        }
+    }
+
+    Ok(Some(quota))
+}
+
+/// Checks that an attachment of the given size which the user has just
+/// created fits into the quotas it counts against, now that it and anything
+/// uploaded alongside it are in the database. Erring rolls back whatever
+/// transaction created it.
+fn check_quota(
+    conn: &DbConn,
+    upload_limits: &UploadLimits,
+    user_id: i64,
+    is_unmetered: bool,
+    jam_entry: Option<&JamEntry>,
+    size: u64,
+) -> Result<(), UploadError> {
+    if is_unmetered {
+        return Ok(());
+    }
+
+    // it counts against the user as soon as it is created
+    let user_quota = upload_limits.user_quota;
+    if Attachment::total_size_by_uploader(conn, user_id)? > user_quota {
+        return Err(Quota::User(user_quota).into());
+    }
+
+    // but only belongs to the entry once it is used
+    if let Some(jam_entry) = jam_entry {
+        let jam_entry_quota = upload_limits.jam_entry_quota;
+        let used = Attachment::total_size_by_jam_entry(conn, jam_entry)?;
+        if used + size > jam_entry_quota {
+            return Err(Quota::JamEntry(jam_entry_quota).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that an attachment id sent back by the uploader, such as for a
+/// summary image, refers to an attachment which actually exists and which the
+/// user may use.
+pub(super) fn existing_attachment_id(
+    conn: &DbConn,
+    attachment_id: Option<i32>,
+    user_id: i64,
+    is_admin: bool,
+) -> Result<Option<i32>, super::HandlerError> {
+    match attachment_id {
+        Some(attachment_id) => {
+            match Attachment::find_by_id(conn, attachment_id)? {
+                Some(attachment)
+                    if attachment.is_usable_by(user_id, is_admin) =>
+                {
+                    Ok(Some(attachment.id))
+                }
+                Some(_) => Err(super::HandlerError::Forbidden),
+                None => Err(super::HandlerError::BadRequest),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Guesses the MIME type from the magic bytes at the start of the file,
+/// falling back to a generic binary type when nothing matches.
+fn sniff_mime_type(file: std::fs::File) -> Result<String, std::io::Error> {
+    let mut head = Vec::new();
+    file.take(SNIFF_LENGTH).read_to_end(&mut head)?;
+
+    Ok(match infer::get(&head) {
+        Some(kind) => kind.mime_type().to_string(),
+        None => "application/octet-stream".to_string(),
+    })
+}
+
+/// Attachment names end up in urls, so keep only the last path component of
+/// whatever the browser sent and replace anything unusual in it.
+fn sanitize_file_name(file_name: &str) -> String {
+    let base_name = file_name.rsplit(|c| c == '/' || c == '\\').next();
+    let sanitized: String = base_name
+        .unwrap_or("")
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    let sanitized = sanitized.trim_start_matches('.');
+
+    if sanitized.is_empty() {
+        "upload".to_string()
+    } else {
+        sanitized.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_quota, etag_matches, parse_range, remaining_quota,
+        sanitize_file_name, UploadError, UploadLimits,
+    };
+    use crate::attachments::ByteRange;
+    use crate::db::get_test_pool;
+    use crate::models::{
+        fixtures::{upload, user},
+        AttachmentOwner, Jam, JamEntry,
+    };
+
+    const UPLOAD_LIMITS: UploadLimits = UploadLimits {
+        max_attachment_size: 100,
+        user_quota: 20,
+        jam_entry_quota: 10,
+    };
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"xyz\", \"abc\"", "\"abc\""));
+        assert!(etag_matches("W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"xyz\"", "\"abc\""));
+        assert!(!etag_matches("abc", "\"abc\""));
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            Some(ByteRange::From(0, Some(499))),
+            parse_range("bytes=0-499")
+        );
+        assert_eq!(Some(ByteRange::From(500, None)), parse_range("bytes=500-"));
+        assert_eq!(Some(ByteRange::Last(500)), parse_range("bytes=-500"));
+        assert_eq!(None, parse_range("bytes=0-1,5-6"));
+        assert_eq!(None, parse_range("bytes=-"));
+        assert_eq!(None, parse_range("bytes=a-b"));
+        assert_eq!(None, parse_range("items=0-1"));
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!("banner.png", sanitize_file_name("banner.png"));
+        assert_eq!("my_game.zip", sanitize_file_name("my game.zip"));
+        assert_eq!("passwd", sanitize_file_name("../../etc/passwd"));
+        assert_eq!("shot.jpg", sanitize_file_name("C:\\Users\\me\\shot.jpg"));
+        assert_eq!("htaccess", sanitize_file_name(".htaccess"));
+        assert_eq!("upload", sanitize_file_name(""));
+        assert_eq!("upload", sanitize_file_name("uploads/"));
+    }
+
+    #[test]
+    fn test_remaining_quota() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        user(&conn, 1);
+        let jam = Jam::create(&conn).unwrap();
+        let jam_entry = JamEntry::create(&conn, jam.id, 1).unwrap();
+
+        let remaining = |jam_entry: Option<&JamEntry>| {
+            remaining_quota(&conn, &UPLOAD_LIMITS, 1, false, jam_entry)
+                .unwrap()
+                .map(|(remaining, _)| remaining)
+        };
+        assert_eq!(Some(20), remaining(None));
+        assert_eq!(Some(10), remaining(Some(&jam_entry)));
+
+        let mut deleted = upload(&conn, 1, "deleted");
+        let mut used = upload(&conn, 1, "used");
+        used.claim(&conn, AttachmentOwner::JamEntry(jam_entry.id))
+            .unwrap();
+        assert_eq!(Some(9), remaining(None));
+        assert_eq!(Some(6), remaining(Some(&jam_entry)));
+
+        // deleting gives the room back
+        deleted.soft_delete(&conn).unwrap();
+        assert_eq!(Some(16), remaining(None));
+
+        upload(&conn, 1, "a lot more than what is left");
+        assert_eq!(Some(0), remaining(Some(&jam_entry)));
+
+        // those who are unmetered, which includes admins, have no quota
+        assert!(remaining_quota(&conn, &UPLOAD_LIMITS, 1, true, None)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_check_quota() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        user(&conn, 1);
+        let jam = Jam::create(&conn).unwrap();
+        let jam_entry = JamEntry::create(&conn, jam.id, 1).unwrap();
+
+        // what was just uploaded already counts against the user
+        upload(&conn, 1, "0123456789");
+        assert!(check_quota(&conn, &UPLOAD_LIMITS, 1, false, None, 10).is_ok());
+        upload(&conn, 1, "0123456789");
+        assert!(check_quota(&conn, &UPLOAD_LIMITS, 1, false, None, 10).is_ok());
+        upload(&conn, 1, "0");
+        assert!(matches!(
+            check_quota(&conn, &UPLOAD_LIMITS, 1, false, None, 1),
+            Err(UploadError::UserQuotaExceeded(20))
+        ));
+        assert!(check_quota(&conn, &UPLOAD_LIMITS, 1, true, None, 1).is_ok());
+
+        // but not against the entry until it is used
+        user(&conn, 2);
+        upload(&conn, 2, "0123456789");
+        assert!(check_quota(
+            &conn,
+            &UPLOAD_LIMITS,
+            2,
+            false,
+            Some(&jam_entry),
+            10
+        )
+        .is_ok());
+        let mut used = upload(&conn, 2, "0");
+        used.claim(&conn, AttachmentOwner::JamEntry(jam_entry.id))
+            .unwrap();
+        assert!(matches!(
+            check_quota(&conn, &UPLOAD_LIMITS, 2, false, Some(&jam_entry), 10),
+            Err(UploadError::JamEntryQuotaExceeded(10))
+        ));
+    }
 }
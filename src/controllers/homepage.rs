@@ -12,15 +12,13 @@ pub fn homepage(
 ) -> Result<Template, super::HandlerError> {
     let conn = pool.get()?;
     let should_show_all_jams =
-        user.is_admin() && show_all_jams.unwrap_or(false);
+        super::jams::should_show_all_jams(&user, show_all_jams);
     // load the first three approved jams
     let mut jams = Vec::new();
     for j in Jam::find_all(&conn, !should_show_all_jams, 0, 3)? {
         jams.push(JamContext::from_model(&conn, &j, false)?);
     }
 
-    println!("{:?}", jams);
-
     #[derive(Debug, Serialize)]
     struct Context {
         auth: UserOptionalContext,
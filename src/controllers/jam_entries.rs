@@ -1,7 +1,309 @@
-// CREATE   /jams/:jam_id/entries               -> jam_entry_id     USERS ONLY
-// UPDATE   /jams/:jam_id/entries/:jam_entry_id -> Result<()>       ADMIN/OWNER ONLY
-// marking a jam as published is admin-only.
-// GET      /jams/:jam_id/:jam_slug/entries     -> Vec<JamEntries>  All when admin,
-// GET      /jams/:jam_id/:jam_slug/:jam_entry_id/:jam_entry_slug   otherwise only
-//                                              -> Jam              published
+use diesel::Connection;
+use rocket::{
+    get, post,
+    request::{Form, FromForm},
+    response::Redirect,
+    uri, State,
+};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use crate::{
+    db::{DbConn, DbPool},
+    models::{slugify, ApprovalState, Jam, JamEntry, JamEntryUpdate, Release},
+    template_helpers::{
+        Breadcrumb, Breadcrumbs, BreadcrumbsContext, JamContext,
+        JamEntryContext, JamEntryUpdateContext, ParticipantOnly,
+        ParticipantOnlyContext, ReleaseContext, UserOptional,
+        UserOptionalContext,
+    },
+};
+
+// CREATE   /jams/:jam_id/entries                       USERS ONLY
+// GET      /jams/:jam_id/entries/:jam_entry_id/edit    ADMIN/OWNER ONLY
+// UPDATE   /jams/:jam_id/entries/:jam_entry_id         ADMIN/OWNER ONLY
+// SUBMIT   /jams/:jam_id/entries/:jam_entry_id/submit  OWNER ONLY
+// marking an entry as approved is admin-only.
+// GET      /jams/:jam_id/:jam_slug/:jam_entry_id/:jam_entry_slug
+//          -> JamEntry     published, or ADMIN/OWNER
 // DELETE   /jams/:jam_id/entries/:jam_entry_id -> Result<()>       ADMIN ONLY
+
+/// Creates a new draft entry in a jam for the current user and immediately
+/// redirects to its edit page.
+#[post("/jams/<jam_id>/entries")]
+pub async fn create_jam_entry(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let jam = match Jam::find_by_id(&conn, jam_id)? {
+        Some(jam) => jam,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    if !jam.is_accepting_entries(chrono::Utc::now().naive_utc()) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    let jam_entry = JamEntry::create(&conn, jam.id, participant.user_id())?;
+    Ok(Redirect::to(uri!(edit_jam_entry: jam.id, jam_entry.id)))
+}
+
+#[derive(Debug, Serialize)]
+struct ShowJamEntryContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    jam_entry_updates: Vec<JamEntryUpdateContext>,
+    releases: Vec<ReleaseContext>,
+    /// Where the newest release which can be played in the browser is played
+    /// from.
+    play_url: Option<String>,
+    is_owner: bool,
+    /// Whether the user may add and change releases.
+    editable: bool,
+}
+
+/// The public page for an entry, with its description and releases followed by
+/// its updates in the order they were written. Entries which aren't approved, or are in a
+/// jam which isn't, are only visible to their owner and to admins, who also
+/// see updates that haven't been approved yet.
+#[get("/jams/<jam_id>/<jam_slug>/<jam_entry_id>/<jam_entry_slug>", rank = 2)]
+pub async fn show_jam_entry(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    jam_id: i32,
+    jam_slug: String,
+    jam_entry_id: i32,
+    jam_entry_slug: String,
+) -> Result<super::TemplateOrRedirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+    let is_owner = match user.user_id() {
+        Some(user_id) => jam_entry.is_owned_by(user_id),
+        None => false,
+    };
+    let is_public = jam.approval_state == ApprovalState::Approved
+        && jam_entry.approval_state == ApprovalState::Approved;
+
+    if !is_public && !is_owner && !user.is_admin() {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    if jam.slug != jam_slug || jam_entry.slug != jam_entry_slug {
+        return Ok(super::TemplateOrRedirect::Redirect(Redirect::permanent(
+            uri!(
+                show_jam_entry: jam.id,
+                jam.slug.as_str(),
+                jam_entry.id,
+                jam_entry.slug.as_str()
+            ),
+        )));
+    }
+
+    let approved_only = !is_owner && !user.is_admin();
+    let mut jam_entry_updates = Vec::new();
+    for jam_entry_update in JamEntryUpdate::find_by_jam_entry_id(
+        &conn,
+        jam_entry.id,
+        approved_only,
+    )? {
+        jam_entry_updates.push(JamEntryUpdateContext::from_model(
+            &conn,
+            &jam_entry_update,
+            true,
+        )?);
+    }
+
+    let mut releases = Vec::new();
+    for release in Release::find_by_jam_entry_id(&conn, jam_entry.id)? {
+        releases.push(ReleaseContext::from_model(&conn, &release, true)?);
+    }
+
+    let context = ShowJamEntryContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::Jam {
+                id: jam.id,
+                slug: jam.slug.clone(),
+                title: jam.title.clone(),
+            },
+            Breadcrumb::JamEntry {
+                jam_id: jam.id,
+                jam_slug: jam.slug.clone(),
+                id: jam_entry.id,
+                slug: jam_entry.slug.clone(),
+                title: jam_entry.title.clone(),
+            },
+        ])
+        .to_context(),
+        title: jam_entry.title.clone(),
+        jam: JamContext::from_model(&conn, &jam, false)?,
+        jam_entry: JamEntryContext::from_model(&conn, &jam_entry, true)?,
+        jam_entry_updates,
+        play_url: releases
+            .iter()
+            .find_map(ReleaseContext::play_url)
+            .map(str::to_string),
+        releases,
+        is_owner,
+        editable: user.is_admin() || (is_owner && jam_entry.is_editable()),
+    };
+
+    Ok(super::TemplateOrRedirect::Template(Template::render(
+        "jam_entry",
+        &context,
+    )))
+}
+
+#[derive(Debug, Serialize)]
+struct EditJamEntryContext {
+    auth: ParticipantOnlyContext,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    editable: bool,
+}
+
+/// Renders out a form that the participant can use to edit their entry. Once
+/// an entry has been submitted the form is still shown, but read-only.
+#[get("/jams/<jam_id>/entries/<jam_entry_id>/edit")]
+pub async fn edit_jam_entry(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !jam_entry.is_owned_by(participant.user_id()) && !participant.is_admin()
+    {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    edit_jam_entry_template(&conn, &participant, &jam, &jam_entry)
+}
+
+#[derive(Debug, FromForm)]
+pub struct JamEntryFormData {
+    title: String,
+    summary: String,
+    // set by the uploader, blank when there is no summary image
+    summary_attachment_id: Option<i32>,
+    // rich_text_id is already set, not changing that through web calls
+    rich_text_content: String,
+}
+
+/// Saves changes to a draft entry. The slug follows the title.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>",
+    data = "<jam_entry_form_data>"
+)]
+pub async fn update_jam_entry(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_form_data: Form<JamEntryFormData>,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, mut jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !may_edit(&participant, &jam_entry) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    // do operations in a transaction so that all the updates roll back on
+    // failure
+    conn.transaction::<(), super::HandlerError, _>(|| {
+        let mut rich_text = jam_entry.load_rich_text(&conn)?;
+
+        jam_entry.title = jam_entry_form_data.title.clone();
+        jam_entry.slug = slugify(&jam_entry_form_data.title);
+        jam_entry.summary = jam_entry_form_data.summary.clone();
+        jam_entry.summary_attachment_id =
+            super::attachments::existing_attachment_id(
+                &conn,
+                jam_entry_form_data.summary_attachment_id,
+                participant.user_id(),
+                participant.is_admin(),
+            )?;
+        rich_text.content = jam_entry_form_data.rich_text_content.clone();
+
+        jam_entry.update(&conn)?;
+        rich_text.update(&conn)?;
+        Ok(())
+    })?;
+
+    edit_jam_entry_template(&conn, &participant, &jam, &jam_entry)
+}
+
+/// Submits a draft entry for approval by an admin, after which it can no
+/// longer be edited by the participant.
+#[post("/jams/<jam_id>/entries/<jam_entry_id>/submit")]
+pub async fn submit_jam_entry(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, mut jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !jam_entry.is_owned_by(participant.user_id()) || !jam_entry.is_editable()
+    {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    jam_entry.submit(&conn)?;
+
+    Ok(Redirect::to(uri!(edit_jam_entry: jam.id, jam_entry.id)))
+}
+
+/// Admins may always edit an entry, whereas its owner may only edit it while
+/// it is a draft.
+pub(super) fn may_edit(
+    participant: &ParticipantOnly,
+    jam_entry: &JamEntry,
+) -> bool {
+    participant.is_admin()
+        || (jam_entry.is_owned_by(participant.user_id())
+            && jam_entry.is_editable())
+}
+
+/// Finds an entry along with the jam it belongs to, treating an entry that is
+/// addressed through the wrong jam as missing.
+pub(super) fn find_jam_and_entry(
+    conn: &DbConn,
+    jam_id: i32,
+    jam_entry_id: i32,
+) -> Result<(Jam, JamEntry), super::HandlerError> {
+    let jam_entry = match JamEntry::find_by_id(conn, jam_entry_id)? {
+        Some(jam_entry) if jam_entry.jam_id == jam_id => jam_entry,
+        _ => return Err(super::HandlerError::NotFound),
+    };
+    let jam = jam_entry.load_jam(conn)?;
+
+    Ok((jam, jam_entry))
+}
+
+fn edit_jam_entry_template(
+    conn: &DbConn,
+    participant: &ParticipantOnly,
+    jam: &Jam,
+    jam_entry: &JamEntry,
+) -> Result<Template, super::HandlerError> {
+    let context = EditJamEntryContext {
+        auth: participant.to_context(),
+        jam: JamContext::from_model(conn, jam, false)?,
+        jam_entry: JamEntryContext::from_model(conn, jam_entry, false)?,
+        editable: may_edit(participant, jam_entry),
+    };
+
+    Ok(Template::render("edit_jam_entry", &context))
+}
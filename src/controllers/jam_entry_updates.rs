@@ -0,0 +1,287 @@
+use diesel::Connection;
+use rocket::{
+    get, post,
+    request::{Form, FromForm},
+    response::Redirect,
+    uri, State,
+};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use super::jam_entries::find_jam_and_entry;
+use crate::{
+    db::{DbConn, DbPool},
+    models::{slugify, Jam, JamEntry, JamEntryUpdate, ModelError},
+    template_helpers::{
+        JamContext, JamEntryContext, JamEntryUpdateContext, ParticipantOnly,
+        ParticipantOnlyContext,
+    },
+};
+
+// CREATE   /jams/:jam_id/entries/:jam_entry_id/updates               OWNER ONLY
+// GET      /jams/:jam_id/entries/:jam_entry_id/updates/:id/edit      OWNER ONLY
+// UPDATE   /jams/:jam_id/entries/:jam_entry_id/updates/:id           OWNER ONLY
+// SUBMIT   /jams/:jam_id/entries/:jam_entry_id/updates/:id/submit    OWNER ONLY
+// updates are read on the entry's public page.
+
+#[derive(Debug, FromForm)]
+pub struct NewJamEntryUpdateFormData {
+    /// When present and non-empty the update links out to this instead of
+    /// having rich text of its own.
+    external_content_url: Option<String>,
+}
+
+/// Creates a new draft update on an entry and immediately redirects to its
+/// edit page.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/updates",
+    data = "<new_jam_entry_update_form_data>"
+)]
+pub async fn create_jam_entry_update(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    new_jam_entry_update_form_data: Form<NewJamEntryUpdateFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !jam_entry.is_owned_by(participant.user_id()) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    let external_content_url = new_jam_entry_update_form_data
+        .external_content_url
+        .as_deref()
+        .map(str::trim)
+        .filter(|url| !url.is_empty());
+
+    let jam_entry_update = match external_content_url {
+        Some(url) => JamEntryUpdate::create_with_external_content(
+            &conn,
+            jam_entry.id,
+            url,
+        ),
+        None => JamEntryUpdate::create_with_rich_text(&conn, jam_entry.id),
+    }
+    .map_err(bad_request_on_invalid_content)?;
+
+    Ok(Redirect::to(uri!(
+        edit_jam_entry_update: jam.id,
+        jam_entry.id,
+        jam_entry_update.id
+    )))
+}
+
+#[derive(Debug, Serialize)]
+struct EditJamEntryUpdateContext {
+    auth: ParticipantOnlyContext,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    jam_entry_update: JamEntryUpdateContext,
+    editable: bool,
+}
+
+/// Renders out a form that the participant can use to edit their update. Once
+/// an update has been submitted the form is still shown, but read-only.
+#[get(
+    "/jams/<jam_id>/entries/<jam_entry_id>/updates/<jam_entry_update_id>/edit"
+)]
+pub async fn edit_jam_entry_update(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, jam_entry_update) = find_jam_entry_and_update(
+        &conn,
+        jam_id,
+        jam_entry_id,
+        jam_entry_update_id,
+    )?;
+
+    if !jam_entry.is_owned_by(participant.user_id()) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    edit_jam_entry_update_template(
+        &conn,
+        &participant,
+        &jam,
+        &jam_entry,
+        &jam_entry_update,
+    )
+}
+
+#[derive(Debug, FromForm)]
+pub struct JamEntryUpdateFormData {
+    title: String,
+    summary: String,
+    // only one of these is used, depending on what kind of update this is
+    rich_text_content: Option<String>,
+    external_content_url: Option<String>,
+}
+
+/// Saves changes to a draft update. The slug follows the title, and the kind
+/// of content the update has is fixed when it's created.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/updates/<jam_entry_update_id>",
+    data = "<jam_entry_update_form_data>"
+)]
+pub async fn update_jam_entry_update(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+    jam_entry_update_form_data: Form<JamEntryUpdateFormData>,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, mut jam_entry_update) = find_jam_entry_and_update(
+        &conn,
+        jam_id,
+        jam_entry_id,
+        jam_entry_update_id,
+    )?;
+
+    if !jam_entry.is_owned_by(participant.user_id())
+        || !jam_entry_update.is_editable()
+    {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    // do operations in a transaction so that all the updates roll back on
+    // failure
+    conn.transaction::<(), super::HandlerError, _>(|| {
+        jam_entry_update.title = jam_entry_update_form_data.title.clone();
+        jam_entry_update.slug = slugify(&jam_entry_update_form_data.title);
+        jam_entry_update.summary = jam_entry_update_form_data.summary.clone();
+
+        match jam_entry_update.load_rich_text(&conn)? {
+            Some(mut rich_text) => {
+                rich_text.content = jam_entry_update_form_data
+                    .rich_text_content
+                    .clone()
+                    .unwrap_or_default();
+                rich_text.update(&conn)?;
+            }
+            None => {
+                jam_entry_update.external_content_url =
+                    jam_entry_update_form_data
+                        .external_content_url
+                        .as_deref()
+                        .map(|url| url.trim().to_string());
+            }
+        }
+
+        jam_entry_update
+            .update(&conn)
+            .map_err(bad_request_on_invalid_content)?;
+        Ok(())
+    })?;
+
+    edit_jam_entry_update_template(
+        &conn,
+        &participant,
+        &jam,
+        &jam_entry,
+        &jam_entry_update,
+    )
+}
+
+/// Submits a draft update for approval, after which it can no longer be
+/// edited by the participant.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/updates/<jam_entry_update_id>/submit"
+)]
+pub async fn submit_jam_entry_update(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, mut jam_entry_update) = find_jam_entry_and_update(
+        &conn,
+        jam_id,
+        jam_entry_id,
+        jam_entry_update_id,
+    )?;
+
+    if !jam_entry.is_owned_by(participant.user_id())
+        || !jam_entry_update.is_editable()
+    {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    jam_entry_update.submit(&conn)?;
+
+    Ok(Redirect::to(uri!(
+        edit_jam_entry_update: jam.id,
+        jam_entry.id,
+        jam_entry_update.id
+    )))
+}
+
+/// Finds an update along with the entry and jam it belongs to, treating an
+/// update that is addressed through the wrong entry as missing.
+fn find_jam_entry_and_update(
+    conn: &DbConn,
+    jam_id: i32,
+    jam_entry_id: i32,
+    jam_entry_update_id: i32,
+) -> Result<(Jam, JamEntry, JamEntryUpdate), super::HandlerError> {
+    let (jam, jam_entry) = find_jam_and_entry(conn, jam_id, jam_entry_id)?;
+    let jam_entry_update =
+        match JamEntryUpdate::find_by_id(conn, jam_entry_update_id)? {
+            Some(jam_entry_update)
+                if jam_entry_update.jam_entry_id == jam_entry.id =>
+            {
+                jam_entry_update
+            }
+            _ => return Err(super::HandlerError::NotFound),
+        };
+
+    Ok((jam, jam_entry, jam_entry_update))
+}
+
+/// Content the participant got wrong, such as a link that isn't to a web page,
+/// is their mistake rather than ours.
+fn bad_request_on_invalid_content(e: ModelError) -> super::HandlerError {
+    match e {
+        ModelError::InvalidJamEntryUpdateContent
+        | ModelError::InvalidExternalContentUrl(_) => {
+            super::HandlerError::BadRequest
+        }
+        e => e.into(),
+    }
+}
+
+fn edit_jam_entry_update_template(
+    conn: &DbConn,
+    participant: &ParticipantOnly,
+    jam: &Jam,
+    jam_entry: &JamEntry,
+    jam_entry_update: &JamEntryUpdate,
+) -> Result<Template, super::HandlerError> {
+    let editable = jam_entry.is_owned_by(participant.user_id())
+        && jam_entry_update.is_editable();
+
+    let context = EditJamEntryUpdateContext {
+        auth: participant.to_context(),
+        jam: JamContext::from_model(conn, jam, false)?,
+        jam_entry: JamEntryContext::from_model(conn, jam_entry, false)?,
+        jam_entry_update: JamEntryUpdateContext::from_model(
+            conn,
+            jam_entry_update,
+            false,
+        )?,
+        editable,
+    };
+
+    Ok(Template::render("edit_jam_entry_update", &context))
+}
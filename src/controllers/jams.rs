@@ -10,8 +10,11 @@ use serde::Serialize;
 
 use crate::{
     db::DbPool,
-    models::ApprovalState,
-    template_helpers::{AdminOnly, JamContext},
+    models::{ApprovalState, JamEntry},
+    template_helpers::{
+        AdminOnly, Breadcrumb, Breadcrumbs, BreadcrumbsContext, JamContext,
+        JamEntryContext, PaginationContext, UserOptional, UserOptionalContext,
+    },
 };
 use crate::{
     models::{Jam, RichText},
@@ -30,6 +33,148 @@ use crate::{
 // CREATE   /jams/:jam_id/attachments                   create an attachment for this jam
 //                                  -> Result<Attachment>
 
+/// How many jams are shown on each page of the archive.
+const JAMS_PAGE_SIZE: i64 = 10;
+
+/// Admins may ask to see jams which aren't approved, nobody else may.
+pub fn should_show_all_jams(
+    user: &UserOptional,
+    show_all_jams: Option<bool>,
+) -> bool {
+    user.is_admin() && show_all_jams.unwrap_or(false)
+}
+
+#[derive(Debug, Serialize)]
+struct ListJamsContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jams: Vec<JamContext>,
+    pagination: PaginationContext,
+    showing_all_jams: bool,
+}
+
+/// The archive of every jam, newest first, a page at a time.
+#[get("/jams?<page>&<show_all_jams>")]
+pub async fn list_jams(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    page: Option<i64>,
+    show_all_jams: Option<bool>,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let should_show_all_jams = should_show_all_jams(&user, show_all_jams);
+    let total = Jam::count(&conn, !should_show_all_jams)?;
+    // anything past the last page is the last page, which also keeps a huge
+    // page number from overflowing once it is turned into an offset
+    let page = page
+        .unwrap_or(1)
+        .max(1)
+        .min(PaginationContext::page_count(JAMS_PAGE_SIZE, total));
+
+    let mut jams = Vec::new();
+    for j in
+        Jam::find_all(&conn, !should_show_all_jams, page - 1, JAMS_PAGE_SIZE)?
+    {
+        jams.push(JamContext::from_model(&conn, &j, false)?);
+    }
+
+    let context = ListJamsContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+        ])
+        .to_context(),
+        title: "Jams".to_string(),
+        jams,
+        pagination: PaginationContext::new(page, JAMS_PAGE_SIZE, total),
+        showing_all_jams: should_show_all_jams,
+    };
+
+    Ok(Template::render("jams", &context))
+}
+
+#[derive(Debug, Serialize)]
+struct ShowJamContext {
+    auth: UserOptionalContext,
+    breadcrumbs: BreadcrumbsContext,
+    title: String,
+    jam: JamContext,
+    jam_entries: Vec<JamEntryContext>,
+    my_jam_entries: Vec<JamEntryContext>,
+}
+
+/// The public page for a jam, showing its rules and the approved entries. Jams
+/// which haven't been approved yet are only visible to admins. Ranked below
+/// the other `/jams/<jam_id>/...` routes so that a slug can never shadow them.
+#[get("/jams/<jam_id>/<jam_slug>", rank = 2)]
+pub async fn show_jam(
+    pool: State<'_, DbPool>,
+    user: UserOptional,
+    jam_id: i32,
+    jam_slug: String,
+) -> Result<super::TemplateOrRedirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let jam = match Jam::find_by_id(&conn, jam_id)? {
+        Some(jam) => jam,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    if jam.approval_state != ApprovalState::Approved && !user.is_admin() {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    // send people with a stale link, such as after a title change, to where
+    // the jam lives now
+    if jam.slug != jam_slug {
+        return Ok(super::TemplateOrRedirect::Redirect(Redirect::permanent(
+            uri!(show_jam: jam.id, jam.slug.as_str()),
+        )));
+    }
+
+    let mut jam_entries = Vec::new();
+    for jam_entry in JamEntry::find_by_jam_id(&conn, jam.id, true)? {
+        jam_entries
+            .push(JamEntryContext::from_model(&conn, &jam_entry, false)?);
+    }
+
+    // participants can find their way back to their own entries, whatever
+    // state those are in
+    let mut my_jam_entries = Vec::new();
+    if let Some(user_id) = user.user_id() {
+        for jam_entry in JamEntry::find_by_jam_id(&conn, jam.id, false)? {
+            if jam_entry.is_owned_by(user_id) {
+                my_jam_entries.push(JamEntryContext::from_model(
+                    &conn, &jam_entry, false,
+                )?);
+            }
+        }
+    }
+
+    let context = ShowJamContext {
+        auth: user.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Jams,
+            Breadcrumb::Jam {
+                id: jam.id,
+                slug: jam.slug.clone(),
+                title: jam.title.clone(),
+            },
+        ])
+        .to_context(),
+        title: jam.title.clone(),
+        jam: JamContext::from_model(&conn, &jam, true)?,
+        jam_entries,
+        my_jam_entries,
+    };
+
+    Ok(super::TemplateOrRedirect::Template(Template::render(
+        "jam", &context,
+    )))
+}
+
 /// Creates a new blank jam and immediately redirects to its edit page.
 #[post("/jams")]
 pub async fn create_jam(
@@ -79,7 +224,8 @@ pub struct JamFormData {
     title: String,
     slug: String,
     summary: String,
-    // summary_attachment_id to be set by ajax
+    // set by the uploader, blank when there is no summary image
+    summary_attachment_id: Option<i32>,
     // rich_text_id is already set, not changing that through web calls
     rich_text_content: String,
     start_date: String,
@@ -113,6 +259,13 @@ pub async fn update_jam(
             jam.title = jam_form_data.title.clone();
             jam.slug = jam_form_data.slug.clone();
             jam.summary = jam_form_data.summary.clone();
+            jam.summary_attachment_id =
+                super::attachments::existing_attachment_id(
+                    &conn,
+                    jam_form_data.summary_attachment_id,
+                    admin_only.user_id(),
+                    true,
+                )?;
             jam.start_date = parse_date(&jam_form_data.start_date)?;
             jam.end_date = parse_date(&jam_form_data.end_date)?;
             jam.approval_state =
@@ -135,7 +288,7 @@ pub async fn update_jam(
 fn parse_date(date: &str) -> Result<NaiveDateTime, DTParseError> {
     NaiveDateTime::parse_from_str(
         &format!("{} 00:00:00", date),
-        "%Y-%m-%d %H:%M:%S"
+        "%Y-%m-%d %H:%M:%S",
     )
 }
 
@@ -2,14 +2,30 @@ pub mod attachments;
 pub mod gh_oauth;
 pub mod homepage;
 pub mod jam_entries;
+pub mod jam_entry_updates;
 pub mod jams;
+pub mod moderation;
+pub mod releases;
+pub mod rich_texts;
+pub mod web_builds;
 
 use rocket::{
-    http::Status, response::Responder, response::Result as RocketResult,
+    http::Status,
+    response::Result as RocketResult,
+    response::{Redirect, Responder},
     Request,
 };
+use rocket_contrib::templates::Template;
 use thiserror::Error;
 
+/// Pages which live at a canonical url, such as one including a slug, either
+/// render or send the browser along to where they really live.
+#[derive(rocket::Responder)]
+pub enum TemplateOrRedirect {
+    Template(Template),
+    Redirect(Redirect),
+}
+
 /// Unified error type for most (all?) handlers. Puts all the annoying
 /// boilerplate of derives into one spot with a single implementation of
 /// Responder to map these back onto error codes that ought to go back and hit
@@ -23,6 +39,15 @@ pub enum HandlerError {
     #[error("The resource was not found")]
     NotFound,
 
+    #[error("The request was not understood")]
+    BadRequest,
+
+    #[error("You must be logged in to do that")]
+    Unauthorized,
+
+    #[error("You are not allowed to do that")]
+    Forbidden,
+
     #[error("Could not get a connection from the pool with error {0}")]
     PoolError(#[from] diesel::r2d2::PoolError),
 
@@ -32,6 +57,9 @@ pub enum HandlerError {
     #[error("Failed to store/retrieve attachment with error {0}")]
     AttachmentStorageError(#[from] crate::attachments::AttachmentStorageError),
 
+    #[error("Failed to extract web build with error {0}")]
+    WebBuildError(#[from] crate::web_builds::WebBuildError),
+
     #[error("HTTP Error {0}")]
     HttpError(#[from] reqwest::Error),
 
@@ -52,6 +80,7 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for HandlerError {
                 Status::InternalServerError
             }
             HandlerError::DatabaseError(_) => Status::InternalServerError,
+            HandlerError::WebBuildError(_) => Status::InternalServerError,
             HandlerError::PoolError(_) => Status::InternalServerError,
             HandlerError::HttpError(_) => Status::InternalServerError,
             HandlerError::ParseError(_) => Status::InternalServerError,
@@ -60,6 +89,9 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for HandlerError {
             }
             HandlerError::DieselError(_) => Status::InternalServerError,
             HandlerError::NotFound => Status::NotFound,
+            HandlerError::BadRequest => Status::BadRequest,
+            HandlerError::Unauthorized => Status::Unauthorized,
+            HandlerError::Forbidden => Status::Forbidden,
         };
 
         if r == Status::InternalServerError {
@@ -0,0 +1,228 @@
+use rocket::{
+    get, post,
+    request::{Form, FromForm},
+    response::Redirect,
+    uri, State,
+};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use crate::{
+    db::DbPool,
+    models::{Jam, JamEntry, JamEntryUpdate, ModelError},
+    template_helpers::{
+        AdminOnly, AdminOnlyContext, Breadcrumb, Breadcrumbs,
+        BreadcrumbsContext, JamContext, JamEntryContext, JamEntryUpdateContext,
+    },
+};
+
+// GET      /moderation                                   ADMIN ONLY
+// APPROVE  /moderation/jams/:jam_id/approve              ADMIN ONLY
+// REJECT   /moderation/jams/:jam_id/reject               ADMIN ONLY
+// APPROVE  /moderation/jam_entries/:id/approve           ADMIN ONLY
+// REJECT   /moderation/jam_entries/:id/reject            ADMIN ONLY
+// APPROVE  /moderation/jam_entry_updates/:id/approve     ADMIN ONLY
+// REJECT   /moderation/jam_entry_updates/:id/reject      ADMIN ONLY
+
+/// A submitted entry, along with the jam it was entered into so the admin has
+/// some idea of what they're looking at.
+#[derive(Debug, Serialize)]
+struct SubmittedJamEntryContext {
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+}
+
+/// A submitted update, along with the entry it is an update on.
+#[derive(Debug, Serialize)]
+struct SubmittedJamEntryUpdateContext {
+    jam_entry: JamEntryContext,
+    jam_entry_update: JamEntryUpdateContext,
+}
+
+#[derive(Debug, Serialize)]
+struct ModerationContext {
+    auth: AdminOnlyContext,
+    breadcrumbs: BreadcrumbsContext,
+    jams: Vec<JamContext>,
+    jam_entries: Vec<SubmittedJamEntryContext>,
+    jam_entry_updates: Vec<SubmittedJamEntryUpdateContext>,
+}
+
+/// Lists everything that has been submitted and is waiting on an admin to
+/// approve or reject it.
+#[get("/moderation")]
+pub async fn moderation(
+    pool: State<'_, DbPool>,
+    admin_only: AdminOnly,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+
+    let mut jams = Vec::new();
+    for jam in Jam::find_submitted(&conn)? {
+        jams.push(JamContext::from_model(&conn, &jam, true)?);
+    }
+
+    let mut jam_entries = Vec::new();
+    for jam_entry in JamEntry::find_submitted(&conn)? {
+        jam_entries.push(SubmittedJamEntryContext {
+            jam: JamContext::from_model(
+                &conn,
+                &jam_entry.load_jam(&conn)?,
+                false,
+            )?,
+            jam_entry: JamEntryContext::from_model(&conn, &jam_entry, true)?,
+        });
+    }
+
+    let mut jam_entry_updates = Vec::new();
+    for jam_entry_update in JamEntryUpdate::find_submitted(&conn)? {
+        let jam_entry = jam_entry_update.load_jam_entry(&conn)?;
+        jam_entry_updates.push(SubmittedJamEntryUpdateContext {
+            jam_entry: JamEntryContext::from_model(&conn, &jam_entry, false)?,
+            jam_entry_update: JamEntryUpdateContext::from_model(
+                &conn,
+                &jam_entry_update,
+                true,
+            )?,
+        });
+    }
+
+    let context = ModerationContext {
+        auth: admin_only.to_context(),
+        breadcrumbs: Breadcrumbs::from_crumbs(vec![
+            Breadcrumb::Home,
+            Breadcrumb::Moderation,
+        ])
+        .to_context(),
+        jams,
+        jam_entries,
+        jam_entry_updates,
+    };
+
+    Ok(Template::render("moderation", &context))
+}
+
+#[derive(Debug, FromForm)]
+pub struct RejectionFormData {
+    reason: String,
+}
+
+#[post("/moderation/jams/<jam_id>/approve")]
+pub async fn approve_jam(
+    pool: State<'_, DbPool>,
+    _admin_only: AdminOnly,
+    jam_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam = find(Jam::find_by_id(&conn, jam_id))?;
+
+    jam.approve(&conn).map_err(moderation_error)?;
+
+    Ok(Redirect::to(uri!(moderation)))
+}
+
+#[post("/moderation/jams/<jam_id>/reject", data = "<rejection_form_data>")]
+pub async fn reject_jam(
+    pool: State<'_, DbPool>,
+    _admin_only: AdminOnly,
+    jam_id: i32,
+    rejection_form_data: Form<RejectionFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam = find(Jam::find_by_id(&conn, jam_id))?;
+
+    jam.reject(&conn, &rejection_form_data.reason)
+        .map_err(moderation_error)?;
+
+    Ok(Redirect::to(uri!(moderation)))
+}
+
+#[post("/moderation/jam_entries/<jam_entry_id>/approve")]
+pub async fn approve_jam_entry(
+    pool: State<'_, DbPool>,
+    _admin_only: AdminOnly,
+    jam_entry_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam_entry = find(JamEntry::find_by_id(&conn, jam_entry_id))?;
+
+    jam_entry.approve(&conn).map_err(moderation_error)?;
+
+    Ok(Redirect::to(uri!(moderation)))
+}
+
+#[post(
+    "/moderation/jam_entries/<jam_entry_id>/reject",
+    data = "<rejection_form_data>"
+)]
+pub async fn reject_jam_entry(
+    pool: State<'_, DbPool>,
+    _admin_only: AdminOnly,
+    jam_entry_id: i32,
+    rejection_form_data: Form<RejectionFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam_entry = find(JamEntry::find_by_id(&conn, jam_entry_id))?;
+
+    jam_entry
+        .reject(&conn, &rejection_form_data.reason)
+        .map_err(moderation_error)?;
+
+    Ok(Redirect::to(uri!(moderation)))
+}
+
+#[post("/moderation/jam_entry_updates/<jam_entry_update_id>/approve")]
+pub async fn approve_jam_entry_update(
+    pool: State<'_, DbPool>,
+    _admin_only: AdminOnly,
+    jam_entry_update_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam_entry_update =
+        find(JamEntryUpdate::find_by_id(&conn, jam_entry_update_id))?;
+
+    jam_entry_update.approve(&conn).map_err(moderation_error)?;
+
+    Ok(Redirect::to(uri!(moderation)))
+}
+
+#[post(
+    "/moderation/jam_entry_updates/<jam_entry_update_id>/reject",
+    data = "<rejection_form_data>"
+)]
+pub async fn reject_jam_entry_update(
+    pool: State<'_, DbPool>,
+    _admin_only: AdminOnly,
+    jam_entry_update_id: i32,
+    rejection_form_data: Form<RejectionFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let mut jam_entry_update =
+        find(JamEntryUpdate::find_by_id(&conn, jam_entry_update_id))?;
+
+    jam_entry_update
+        .reject(&conn, &rejection_form_data.reason)
+        .map_err(moderation_error)?;
+
+    Ok(Redirect::to(uri!(moderation)))
+}
+
+/// Treats a missing item as a 404.
+fn find<T>(
+    result: Result<Option<T>, ModelError>,
+) -> Result<T, super::HandlerError> {
+    match result? {
+        Some(item) => Ok(item),
+        None => Err(super::HandlerError::NotFound),
+    }
+}
+
+/// Approving something twice, or rejecting without a reason, is the admin's
+/// mistake rather than ours.
+fn moderation_error(e: ModelError) -> super::HandlerError {
+    match e {
+        ModelError::InvalidApprovalStateTransition(_, _)
+        | ModelError::MissingRejectionReason => super::HandlerError::BadRequest,
+        e => e.into(),
+    }
+}
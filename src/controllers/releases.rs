@@ -0,0 +1,422 @@
+use diesel::Connection;
+use rocket::{
+    get, post,
+    request::{Form, FromForm},
+    response::Redirect,
+    uri, State,
+};
+use rocket_contrib::templates::Template;
+use serde::Serialize;
+
+use std::{
+    io::{Error as IOError, ErrorKind},
+    path::Path,
+};
+
+use super::jam_entries::{find_jam_and_entry, may_edit};
+use crate::{
+    attachments::AttachmentStorage,
+    db::{DbConn, DbPool},
+    models::{
+        Architecture, Attachment, Jam, JamEntry, ModelError, Platform, Release,
+        ReleaseArtifact, WebBuild,
+    },
+    template_helpers::{
+        JamContext, JamEntryContext, ParticipantOnly, ParticipantOnlyContext,
+        ReleaseContext,
+    },
+    web_builds::{ExtractedWebBuild, WebBuildError, WebBuildStorage},
+};
+
+// CREATE   /jams/:jam_id/entries/:jam_entry_id/releases                ADMIN/OWNER ONLY
+// GET      /jams/:jam_id/entries/:jam_entry_id/releases/:id/edit       ADMIN/OWNER ONLY
+// UPDATE   /jams/:jam_id/entries/:jam_entry_id/releases/:id            ADMIN/OWNER ONLY
+// DELETE   /jams/:jam_id/entries/:jam_entry_id/releases/:id/delete     ADMIN/OWNER ONLY
+// CREATE   /jams/:jam_id/entries/:jam_entry_id/releases/:id/artifacts  ADMIN/OWNER ONLY
+// DELETE   /jams/:jam_id/entries/:jam_entry_id/releases/:id/artifacts/:artifact_id/delete
+//                                                                      ADMIN/OWNER ONLY
+// releases are reviewed along with their entry, so like the entry they can
+// only be changed by its owner while it is a draft. they are listed for
+// download on the entry's public page. builds for the web which are zip
+// archives are extracted when they're added, and the newest is played there.
+
+/// What a web build has to be uploaded as to be extracted and played, rather
+/// than only downloaded.
+const WEB_BUILD_MIME_TYPE: &str = "application/zip";
+
+#[derive(Debug, FromForm)]
+pub struct NewReleaseFormData {
+    version: String,
+}
+
+/// Creates a new release of an entry, with no builds yet, and immediately
+/// redirects to its edit page.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/releases",
+    data = "<new_release_form_data>"
+)]
+pub async fn create_release(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    new_release_form_data: Form<NewReleaseFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry) = find_jam_and_entry(&conn, jam_id, jam_entry_id)?;
+
+    if !may_edit(&participant, &jam_entry) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    let release =
+        Release::create(&conn, jam_entry.id, &new_release_form_data.version)
+            .map_err(bad_request_on_invalid_version)?;
+
+    Ok(Redirect::to(
+        uri!(edit_release: jam.id, jam_entry.id, release.id),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+struct EditReleaseContext {
+    auth: ParticipantOnlyContext,
+    jam: JamContext,
+    jam_entry: JamEntryContext,
+    release: ReleaseContext,
+    editable: bool,
+}
+
+/// Renders out a form that the participant can use to edit a release and its
+/// builds. Once the entry has been submitted the form is still shown, but
+/// read-only.
+#[get("/jams/<jam_id>/entries/<jam_entry_id>/releases/<release_id>/edit")]
+pub async fn edit_release(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    release_id: i32,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, release) =
+        find_jam_entry_and_release(&conn, jam_id, jam_entry_id, release_id)?;
+
+    if !jam_entry.is_owned_by(participant.user_id()) && !participant.is_admin()
+    {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    edit_release_template(&conn, &participant, &jam, &jam_entry, &release)
+}
+
+#[derive(Debug, FromForm)]
+pub struct ReleaseFormData {
+    version: String,
+    // rich_text_id is already set, not changing that through web calls
+    rich_text_content: String,
+}
+
+/// Saves changes to the version and changelog of a release.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/releases/<release_id>",
+    data = "<release_form_data>"
+)]
+pub async fn update_release(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    release_id: i32,
+    release_form_data: Form<ReleaseFormData>,
+) -> Result<Template, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, mut release) =
+        find_jam_entry_and_release(&conn, jam_id, jam_entry_id, release_id)?;
+
+    if !may_edit(&participant, &jam_entry) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    // do operations in a transaction so that all the updates roll back on
+    // failure
+    conn.transaction::<(), super::HandlerError, _>(|| {
+        let mut rich_text = release.load_rich_text(&conn)?;
+
+        release.version = release_form_data.version.clone();
+        rich_text.content = release_form_data.rich_text_content.clone();
+
+        release
+            .update(&conn)
+            .map_err(bad_request_on_invalid_version)?;
+        rich_text.update(&conn)?;
+        Ok(())
+    })?;
+
+    edit_release_template(&conn, &participant, &jam, &jam_entry, &release)
+}
+
+/// Deletes a release along with its changelog. Its builds stay around as
+/// attachments of the entry until they are garbage collected.
+#[post("/jams/<jam_id>/entries/<jam_entry_id>/releases/<release_id>/delete")]
+pub async fn delete_release(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    release_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, release) =
+        find_jam_entry_and_release(&conn, jam_id, jam_entry_id, release_id)?;
+
+    if !may_edit(&participant, &jam_entry) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    release.delete(&conn)?;
+
+    Ok(Redirect::to(uri!(
+        super::jam_entries::show_jam_entry: jam.id,
+        jam.slug.as_str(),
+        jam_entry.id,
+        jam_entry.slug.as_str()
+    )))
+}
+
+#[derive(Debug, FromForm)]
+pub struct ReleaseArtifactFormData {
+    // set by the uploader
+    attachment_id: i32,
+    platform: String,
+    architecture: String,
+}
+
+/// Adds a build, which has already been uploaded, to a release. A zip archive
+/// for the web is extracted as well, so that it can be played on the entry's
+/// page.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/releases/<release_id>/artifacts",
+    data = "<release_artifact_form_data>"
+)]
+pub async fn create_release_artifact(
+    pool: State<'_, DbPool>,
+    attachment_storage: State<'_, AttachmentStorage>,
+    web_build_storage: State<'_, WebBuildStorage>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    release_id: i32,
+    release_artifact_form_data: Form<ReleaseArtifactFormData>,
+) -> Result<Redirect, super::HandlerError> {
+    let (jam, jam_entry, release, mut attachment) = {
+        let conn = pool.get()?;
+        let (jam, jam_entry, release) = find_jam_entry_and_release(
+            &conn,
+            jam_id,
+            jam_entry_id,
+            release_id,
+        )?;
+
+        if !may_edit(&participant, &jam_entry) {
+            return Err(super::HandlerError::Forbidden);
+        }
+
+        let attachment = match Attachment::find_by_id(
+            &conn,
+            release_artifact_form_data.attachment_id,
+        )? {
+            Some(attachment)
+                if attachment.is_usable_by(
+                    participant.user_id(),
+                    participant.is_admin(),
+                ) =>
+            {
+                attachment
+            }
+            Some(_) => return Err(super::HandlerError::Forbidden),
+            None => return Err(super::HandlerError::BadRequest),
+        };
+
+        (jam, jam_entry, release, attachment)
+    };
+
+    let platform = release_artifact_form_data
+        .platform
+        .parse::<Platform>()
+        .map_err(|_| super::HandlerError::BadRequest)?;
+    let architecture = release_artifact_form_data
+        .architecture
+        .parse::<Architecture>()
+        .map_err(|_| super::HandlerError::BadRequest)?;
+
+    let web_build = if platform == Platform::Web
+        && attachment.mime_type == WEB_BUILD_MIME_TYPE
+    {
+        Some(
+            extract_web_build(
+                &pool,
+                &attachment_storage,
+                &web_build_storage,
+                &attachment,
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+
+    let conn = pool.get()?;
+    conn.transaction::<(), super::HandlerError, _>(|| {
+        let release_artifact = ReleaseArtifact::create(
+            &conn,
+            &release,
+            &mut attachment,
+            platform,
+            architecture,
+        )?;
+
+        if let Some((directory, extracted)) = &web_build {
+            WebBuild::create(
+                &conn,
+                release_artifact.id,
+                directory,
+                &extracted.entry_point,
+                extracted.size as i64,
+            )?;
+        }
+        Ok(())
+    })?;
+
+    Ok(Redirect::to(
+        uri!(edit_release: jam.id, jam_entry.id, release.id),
+    ))
+}
+
+/// Removes a build from a release. The attachment stays around as one of the
+/// entry's until it is garbage collected.
+#[post(
+    "/jams/<jam_id>/entries/<jam_entry_id>/releases/<release_id>/artifacts/<release_artifact_id>/delete"
+)]
+pub async fn delete_release_artifact(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    jam_id: i32,
+    jam_entry_id: i32,
+    release_id: i32,
+    release_artifact_id: i32,
+) -> Result<Redirect, super::HandlerError> {
+    let conn = pool.get()?;
+    let (jam, jam_entry, release) =
+        find_jam_entry_and_release(&conn, jam_id, jam_entry_id, release_id)?;
+
+    if !may_edit(&participant, &jam_entry) {
+        return Err(super::HandlerError::Forbidden);
+    }
+
+    match ReleaseArtifact::find_by_id(&conn, release_artifact_id)? {
+        Some(release_artifact) if release_artifact.release_id == release.id => {
+            release_artifact.delete(&conn)?
+        }
+        _ => return Err(super::HandlerError::NotFound),
+    }
+
+    Ok(Redirect::to(
+        uri!(edit_release: jam.id, jam_entry.id, release.id),
+    ))
+}
+
+/// Finds a release along with the entry and jam it belongs to, treating a
+/// release that is addressed through the wrong entry as missing.
+fn find_jam_entry_and_release(
+    conn: &DbConn,
+    jam_id: i32,
+    jam_entry_id: i32,
+    release_id: i32,
+) -> Result<(Jam, JamEntry, Release), super::HandlerError> {
+    let (jam, jam_entry) = find_jam_and_entry(conn, jam_id, jam_entry_id)?;
+    let release = match Release::find_by_id(conn, release_id)? {
+        Some(release) if release.jam_entry_id == jam_entry.id => release,
+        _ => return Err(super::HandlerError::NotFound),
+    };
+
+    Ok((jam, jam_entry, release))
+}
+
+/// Extracts a web build into the directory named for its archive, unless the
+/// same archive has been extracted there already. An archive which can't be
+/// extracted safely is the participant's mistake rather than ours.
+async fn extract_web_build(
+    pool: &DbPool,
+    attachment_storage: &AttachmentStorage,
+    web_build_storage: &WebBuildStorage,
+    attachment: &Attachment,
+) -> Result<(String, ExtractedWebBuild), super::HandlerError> {
+    // only attachments kept under their sha256 have one to name it for
+    let directory = match &attachment.sha256 {
+        Some(the_sha256) => hex::encode(the_sha256),
+        None => return Err(super::HandlerError::BadRequest),
+    };
+
+    {
+        let conn = pool.get()?;
+        for web_build in WebBuild::find_by_directory(&conn, &directory)? {
+            let entry_point = Path::new(&web_build.entry_point);
+            if web_build_storage.file(&directory, entry_point).is_some() {
+                let extracted = ExtractedWebBuild {
+                    entry_point: web_build.entry_point,
+                    size: web_build.size as u64,
+                };
+                return Ok((directory, extracted));
+            }
+        }
+    }
+
+    let archive = attachment_storage.fetch(&attachment.storage_key()).await?;
+
+    // extracting takes a while, so keep it off the threads serving requests
+    let storage = web_build_storage.clone();
+    let the_directory = directory.clone();
+    let extracted = tokio::task::spawn_blocking(move || {
+        storage.extract(archive.path(), &the_directory)
+    })
+    .await
+    .map_err(|e| WebBuildError::from(IOError::new(ErrorKind::Other, e)))?;
+
+    match extracted {
+        Ok(extracted) => Ok((directory, extracted)),
+        Err(e) if e.is_invalid_archive() => {
+            Err(super::HandlerError::BadRequest)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A blank or repeated version is the participant's mistake rather than ours.
+fn bad_request_on_invalid_version(e: ModelError) -> super::HandlerError {
+    match e {
+        ModelError::MissingReleaseVersion
+        | ModelError::DuplicateReleaseVersion(_) => {
+            super::HandlerError::BadRequest
+        }
+        e => e.into(),
+    }
+}
+
+fn edit_release_template(
+    conn: &DbConn,
+    participant: &ParticipantOnly,
+    jam: &Jam,
+    jam_entry: &JamEntry,
+    release: &Release,
+) -> Result<Template, super::HandlerError> {
+    let context = EditReleaseContext {
+        auth: participant.to_context(),
+        jam: JamContext::from_model(conn, jam, false)?,
+        jam_entry: JamEntryContext::from_model(conn, jam_entry, false)?,
+        release: ReleaseContext::from_model(conn, release, false)?,
+        editable: may_edit(participant, jam_entry),
+    };
+
+    Ok(Template::render("edit_release", &context))
+}
@@ -0,0 +1,143 @@
+use rocket::{delete, http::ContentType, post, Data, State};
+use rocket_contrib::json::Json;
+
+use super::attachments::{receive_upload, UploadError, UploadLimits};
+use super::jam_entries::may_edit;
+use crate::{
+    attachments::AttachmentStorage,
+    db::{DbConn, DbPool},
+    models::{
+        AttachmentOwner, Jam, JamEntry, JamEntryUpdate, ModelError, Release,
+        RichText,
+    },
+    template_helpers::{AttachmentContext, ParticipantOnly},
+};
+
+// CREATE   /rich_texts/:rich_text_id/attachments                   EDITOR ONLY
+// DELETE   /rich_texts/:rich_text_id/attachments/:attachment_id    EDITOR ONLY
+// the editor of a rich text is whoever may edit the thing it belongs to.
+
+/// Uploads a file and attaches it to a rich text, so that it can be referred
+/// to from the markdown as `attachment:<id>`.
+#[post("/rich_texts/<rich_text_id>/attachments", data = "<data>")]
+pub async fn upload_rich_text_attachment(
+    pool: State<'_, DbPool>,
+    attachment_storage: State<'_, AttachmentStorage>,
+    upload_limits: State<'_, UploadLimits>,
+    participant: ParticipantOnly,
+    content_type: &ContentType,
+    data: Data,
+    rich_text_id: i32,
+) -> Result<Json<AttachmentContext>, UploadError> {
+    let (rich_text, jam_entry) = {
+        let conn = pool.get()?;
+        let rich_text =
+            find_editable_rich_text(&conn, &participant, rich_text_id)?;
+        let jam_entry = find_owning_jam_entry(&conn, &rich_text)?;
+        (rich_text, jam_entry)
+    };
+
+    let mut attachment = receive_upload(
+        &pool,
+        &attachment_storage,
+        &upload_limits,
+        &participant,
+        jam_entry.as_ref(),
+        content_type,
+        data,
+    )
+    .await?;
+
+    let conn = pool.get()?;
+    rich_text.attach(&conn, &mut attachment)?;
+
+    Ok(Json(AttachmentContext::from_model(&attachment)))
+}
+
+/// Detaches an attachment from a rich text. Any references to it left in the
+/// markdown stop resolving.
+#[delete("/rich_texts/<rich_text_id>/attachments/<attachment_id>")]
+pub async fn detach_rich_text_attachment(
+    pool: State<'_, DbPool>,
+    participant: ParticipantOnly,
+    rich_text_id: i32,
+    attachment_id: i32,
+) -> Result<Json<Vec<AttachmentContext>>, super::HandlerError> {
+    let conn = pool.get()?;
+    let rich_text = find_editable_rich_text(&conn, &participant, rich_text_id)?;
+
+    rich_text.detach(&conn, attachment_id)?;
+
+    let attachments = rich_text
+        .attachments(&conn)?
+        .iter()
+        .map(AttachmentContext::from_model)
+        .collect();
+
+    Ok(Json(attachments))
+}
+
+/// Finds a rich text, so long as the user may edit whatever it belongs to.
+/// Only admins edit jams, whereas entries, their updates and their releases'
+/// changelogs belong to their participant while they are drafts.
+fn find_editable_rich_text(
+    conn: &DbConn,
+    participant: &ParticipantOnly,
+    rich_text_id: i32,
+) -> Result<RichText, super::HandlerError> {
+    let rich_text = match RichText::find_by_id(conn, rich_text_id)? {
+        Some(rich_text) => rich_text,
+        None => return Err(super::HandlerError::NotFound),
+    };
+
+    let editable = if Jam::find_by_rich_text_id(conn, rich_text.id)?.is_some() {
+        participant.is_admin()
+    } else if let Some(jam_entry) =
+        JamEntry::find_by_rich_text_id(conn, rich_text.id)?
+    {
+        may_edit(participant, &jam_entry)
+    } else if let Some(jam_entry_update) =
+        JamEntryUpdate::find_by_rich_text_id(conn, rich_text.id)?
+    {
+        let jam_entry = jam_entry_update.load_jam_entry(conn)?;
+        jam_entry.is_owned_by(participant.user_id())
+            && jam_entry_update.is_editable()
+    } else if let Some(release) =
+        Release::find_by_rich_text_id(conn, rich_text.id)?
+    {
+        match JamEntry::find_by_id(conn, release.jam_entry_id)? {
+            Some(jam_entry) => may_edit(participant, &jam_entry),
+            None => false,
+        }
+    } else {
+        false
+    };
+
+    if editable {
+        Ok(rich_text)
+    } else {
+        Err(super::HandlerError::Forbidden)
+    }
+}
+
+/// Finds the entry whose quota uploads to a rich text count against, which is
+/// the entry it describes or the entry whose update or changelog it is.
+fn find_owning_jam_entry(
+    conn: &DbConn,
+    rich_text: &RichText,
+) -> Result<Option<JamEntry>, ModelError> {
+    match rich_text.owner(conn)? {
+        Some(AttachmentOwner::JamEntry(jam_entry_id)) => {
+            JamEntry::find_by_id(conn, jam_entry_id)
+        }
+        Some(AttachmentOwner::JamEntryUpdate(jam_entry_update_id)) => {
+            match JamEntryUpdate::find_by_id(conn, jam_entry_update_id)? {
+                Some(jam_entry_update) => {
+                    Ok(Some(jam_entry_update.load_jam_entry(conn)?))
+                }
+                None => Ok(None),
+            }
+        }
+        Some(AttachmentOwner::Jam(_)) | None => Ok(None),
+    }
+}
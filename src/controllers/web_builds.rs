@@ -0,0 +1,96 @@
+use crate::{
+    db::DbPool,
+    models::WebBuild,
+    web_builds::{content_type_of, WebBuildError, WebBuildStorage},
+};
+use rocket::{
+    get,
+    response::{Responder, Response, Result as RocketResult},
+    tokio::fs::File,
+    Request, State,
+};
+use std::path::PathBuf;
+
+// GET      /play/:directory/:path..    anyone, once the entry is approved
+// builds are addressed by the sha256 of their archive. they are played under
+// the same rule as downloading the archive would be for someone who isn't
+// signed in, since the sandbox doesn't say who is playing. their files are
+// served into a sandbox of their own, so that a game can't act as whoever is
+// playing it.
+
+/// Keeps a build's pages in an opaque origin of their own, able to run
+/// scripts and WebAssembly and to load anything from within the build, but
+/// unable to touch the rest of the site, submit forms, or send anything
+/// elsewhere. Only the site itself may frame them.
+const WEB_BUILD_CSP: &str = "sandbox allow-scripts allow-pointer-lock; \
+    default-src 'self' data: blob:; \
+    script-src 'self' 'unsafe-inline' 'unsafe-eval' blob:; \
+    style-src 'self' 'unsafe-inline'; \
+    frame-ancestors 'self'; \
+    form-action 'none'; \
+    base-uri 'self'";
+
+/// A file out of a web build, on its way to the iframe playing it.
+pub struct PlayedFile {
+    file: File,
+    mime_type: &'static str,
+    encoding: Option<&'static str>,
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for PlayedFile {
+    fn respond_to(self, _request: &'r Request<'_>) -> RocketResult<'o> {
+        let mut response = Response::build();
+        response
+            .raw_header("Content-Type", self.mime_type)
+            .raw_header("Content-Security-Policy", WEB_BUILD_CSP)
+            // the sandboxed page has no origin, so even its requests back to
+            // us are cross-origin, and game loaders fetch their data
+            .raw_header("Access-Control-Allow-Origin", "*")
+            // a directory's contents never change, as it is named for them
+            .raw_header("Cache-Control", "public, max-age=31536000, immutable");
+
+        if let Some(encoding) = self.encoding {
+            response.raw_header("Content-Encoding", encoding);
+        }
+
+        response.streamed_body(self.file).ok()
+    }
+}
+
+// match stuff like udevgames.com/play/<sha256>/index.html, along with
+// whatever else in the build it loads
+#[get("/play/<directory>/<path..>")]
+pub async fn play_web_build(
+    pool: State<'_, DbPool>,
+    web_build_storage: State<'_, WebBuildStorage>,
+    directory: String,
+    path: PathBuf,
+) -> Result<PlayedFile, super::HandlerError> {
+    let playable = {
+        let conn = pool.get()?;
+        let mut playable = false;
+        for web_build in WebBuild::find_by_directory(&conn, &directory)? {
+            if web_build.is_playable(&conn)? {
+                playable = true;
+                break;
+            }
+        }
+        playable
+    };
+
+    if !playable {
+        return Err(super::HandlerError::NotFound);
+    }
+
+    let file = match web_build_storage.file(&directory, &path) {
+        Some(file) => file,
+        None => return Err(super::HandlerError::NotFound),
+    };
+    let (mime_type, encoding) = content_type_of(&path);
+
+    Ok(PlayedFile {
+        file: File::open(file).await.map_err(WebBuildError::from)?,
+        mime_type,
+        encoding,
+    })
+}
@@ -28,3 +28,18 @@ pub fn migrate_db(manager: &DbPool) {
     embedded_migrations::run_with_output(&conn, &mut std::io::stdout())
         .unwrap();
 }
+
+/// Gets a pool for a new, migrated database which only lives in memory, for
+/// tests which need one.
+#[cfg(test)]
+pub fn get_test_pool() -> DbPool {
+    // every connection to :memory: is a database of its own, so the pool can
+    // only ever have the one
+    let pool = diesel::r2d2::Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::new(":memory:"))
+        .unwrap();
+    embedded_migrations::run(&pool.get().unwrap()).unwrap();
+
+    pool
+}
@@ -1,4 +1,5 @@
-use rocket::catch;
+use crate::template_helpers::Refusal;
+use rocket::{catch, Request};
 use rocket_contrib::templates::Template;
 use serde::Serialize;
 
@@ -17,6 +18,14 @@ impl ErrorContext {
     }
 }
 
+#[catch(400)]
+pub fn bad_request() -> Template {
+    Template::render(
+        "error_page",
+        &ErrorContext::new(400, "The request could not be understood."),
+    )
+}
+
 #[catch(404)]
 pub fn not_found() -> Template {
     Template::render("error_page", &ErrorContext::new(404, "Page not found."))
@@ -31,7 +40,14 @@ pub fn not_authorized() -> Template {
 }
 
 #[catch(403)]
-pub fn forbidden() -> Template {
+pub fn forbidden(req: &Request<'_>) -> Template {
+    if Refusal::of(req) == Refusal::Banned {
+        return Template::render(
+            "banned",
+            &ErrorContext::new(403, "Your account has been banned."),
+        );
+    }
+
     Template::render(
         "error_page",
         &ErrorContext::new(403, "You cannot view this page."),
@@ -0,0 +1,76 @@
+//! A small client for the parts of the Github API that are used outside of the
+//! login workflow, such as refreshing our cache of who users are.
+
+use crate::controllers::gh_oauth::gh_client;
+use reqwest::{Client as ReqwestClient, StatusCode};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Where the Github API lives, unless configured otherwise with
+/// `GH_API_BASE_URL`.
+pub const DEFAULT_GH_API_BASE_URL: &str = "https://api.github.com";
+
+#[derive(Debug, Error)]
+pub enum GhApiError {
+    #[error("Failed to talk to Github with error {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Github responded {0} to {1}")]
+    UnexpectedStatus(u16, String),
+}
+
+/// A user as Github describes them. Only the fields cached in
+/// [`crate::models::GhUserRecord`] are kept.
+#[derive(Debug, Deserialize)]
+pub struct GhUser {
+    pub id: i64,
+    pub login: String,
+    pub avatar_url: String,
+    pub html_url: String,
+}
+
+/// Talks to the Github API. The base url can be pointed somewhere else, such
+/// as a local stub when testing.
+pub struct GhApi {
+    client: ReqwestClient,
+    base_url: String,
+    /// Requests without a token are heavily rate limited, which syncing every
+    /// user can run into.
+    token: Option<String>,
+}
+
+impl GhApi {
+    pub fn new(base_url: &str, token: Option<String>) -> GhApi {
+        GhApi {
+            client: gh_client(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    /// Fetches a user by their id, which unlike their login never changes.
+    /// Users who have deleted their account are `None`.
+    pub async fn user_by_id(
+        &self,
+        user_id: i64,
+    ) -> Result<Option<GhUser>, GhApiError> {
+        let url = format!("{}/user/{}", self.base_url, user_id);
+        let mut request = self
+            .client
+            .get(&url)
+            .header("Accept", "application/vnd.github.v3+json");
+
+        if let Some(token) = &self.token {
+            request =
+                request.header("Authorization", format!("token {}", token));
+        }
+
+        let response = request.send().await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.json().await?)),
+            status => Err(GhApiError::UnexpectedStatus(status.as_u16(), url)),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Jams written out as files, so that next season's jam can be prepared ahead
+//! of time and its description kept under version control. A jam file is the
+//! jam's description in markdown, preceded by TOML front matter between `+++`
+//! lines:
+//!
+//! ```text
+//! +++
+//! title = "uDevGames 2021"
+//! slug = "udevgames-2021"
+//! summary = "Make a Mac game in a month"
+//! start_date = "2021-10-01"
+//! end_date = "2021-10-31"
+//! attachments = [12, 13]
+//! +++
+//! # The Rules
+//!
+//! ![banner](attachment:12)
+//! ```
+//!
+//! Only `title`, `start_date`, and `end_date` are required. The slug follows
+//! the title when left out. `attachments` lists what is attached to the
+//! description, so that `attachment:` links keep working when the file is
+//! imported back into the same site.
+
+use crate::{
+    db::DbConn,
+    models::{slugify, Jam, ModelError, NewJam},
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum JamFileError {
+    #[error("Expected the file to start with front matter between +++ lines")]
+    MissingFrontMatter,
+
+    #[error("Could not read the front matter with error {0}")]
+    InvalidFrontMatter(#[from] toml::de::Error),
+
+    #[error("Expected a date like 2021-10-31, but got {0}")]
+    InvalidDate(String),
+
+    #[error("The jam ends before it starts")]
+    EndsBeforeStart,
+}
+
+/// A jam as it is written in a file.
+#[derive(Debug, PartialEq)]
+pub struct JamFile {
+    pub title: String,
+    pub slug: String,
+    pub summary: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Ids of the attachments on the description.
+    pub attachment_ids: Vec<i32>,
+    /// The description, in markdown.
+    pub description: String,
+}
+
+/// The front matter, as it is read and written.
+#[derive(Debug, Deserialize, Serialize)]
+struct FrontMatter {
+    title: String,
+    slug: Option<String>,
+    #[serde(default)]
+    summary: String,
+    start_date: String,
+    end_date: String,
+    #[serde(default)]
+    attachments: Vec<i32>,
+}
+
+impl JamFile {
+    /// Reads a jam file.
+    pub fn parse(s: &str) -> Result<JamFile, JamFileError> {
+        let (front_matter, description) =
+            split_front_matter(s).ok_or(JamFileError::MissingFrontMatter)?;
+        let front_matter: FrontMatter = toml::from_str(front_matter)?;
+
+        let start_date = parse_jam_date(&front_matter.start_date)?;
+        let end_date = parse_jam_date(&front_matter.end_date)?;
+        if end_date < start_date {
+            return Err(JamFileError::EndsBeforeStart);
+        }
+
+        let slug = match front_matter.slug {
+            Some(slug) => slug,
+            None => slugify(&front_matter.title),
+        };
+
+        Ok(JamFile {
+            title: front_matter.title,
+            slug,
+            summary: front_matter.summary,
+            start_date,
+            end_date,
+            attachment_ids: front_matter.attachments,
+            description: description.to_string(),
+        })
+    }
+
+    /// Writes out an existing jam.
+    pub fn from_jam(conn: &DbConn, jam: &Jam) -> Result<JamFile, ModelError> {
+        let rich_text = jam.load_rich_text(conn)?;
+
+        Ok(JamFile {
+            title: jam.title.clone(),
+            slug: jam.slug.clone(),
+            summary: jam.summary.clone(),
+            start_date: jam.start_date.date(),
+            end_date: jam.end_date.date(),
+            attachment_ids: rich_text
+                .attachments(conn)?
+                .iter()
+                .map(|attachment| attachment.id)
+                .collect(),
+            description: rich_text.content,
+        })
+    }
+
+    /// What's needed to create a jam from this file.
+    pub fn to_new_jam(&self) -> NewJam {
+        NewJam {
+            title: self.title.clone(),
+            slug: self.slug.clone(),
+            summary: self.summary.clone(),
+            rich_text_content: self.description.clone(),
+            start_date: self.start_date.and_hms(0, 0, 0),
+            end_date: self.end_date.and_hms(0, 0, 0),
+        }
+    }
+}
+
+impl fmt::Display for JamFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let front_matter = toml::to_string(&FrontMatter {
+            title: self.title.clone(),
+            slug: Some(self.slug.clone()),
+            summary: self.summary.clone(),
+            start_date: self.start_date.format("%Y-%m-%d").to_string(),
+            end_date: self.end_date.format("%Y-%m-%d").to_string(),
+            attachments: self.attachment_ids.clone(),
+        })
+        .map_err(|_| fmt::Error)?;
+
+        write!(f, "+++\n{}+++\n{}", front_matter, self.description)
+    }
+}
+
+/// Reads a date like `2021-10-31`, which is how jam dates are written both
+/// in jam files and on the command line.
+pub fn parse_jam_date(date: &str) -> Result<NaiveDate, JamFileError> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| JamFileError::InvalidDate(date.to_string()))
+}
+
+/// As [`parse_jam_date`], but at the start of that day, which is how jam
+/// dates are stored.
+pub fn parse_jam_date_time(date: &str) -> Result<NaiveDateTime, JamFileError> {
+    Ok(parse_jam_date(date)?.and_hms(0, 0, 0))
+}
+
+/// Splits a file into its front matter and everything after it, so long as
+/// it starts with a `+++` line and has another to close the front matter.
+fn split_front_matter(s: &str) -> Option<(&str, &str)> {
+    let s = s.strip_prefix("+++")?;
+    let s = s.strip_prefix("\r\n").or_else(|| s.strip_prefix('\n'))?;
+    let mut start = 0;
+
+    loop {
+        let end = match s[start..].find('\n') {
+            Some(i) => start + i + 1,
+            None => s.len(),
+        };
+
+        if s[start..end].trim_end() == "+++" {
+            return Some((&s[..start], &s[end..]));
+        }
+
+        if end == s.len() {
+            return None;
+        }
+
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jam_file_round_trip() {
+        let jam_file = JamFile {
+            title: "uDevGames 2021".to_string(),
+            slug: "udevgames-2021".to_string(),
+            summary: "Make a \"Mac\" game".to_string(),
+            start_date: NaiveDate::from_ymd(2021, 10, 1),
+            end_date: NaiveDate::from_ymd(2021, 10, 31),
+            attachment_ids: vec![12],
+            description: "# Rules\n\n+++\nHave fun\n".to_string(),
+        };
+
+        assert_eq!(jam_file, JamFile::parse(&jam_file.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_jam_file_defaults() {
+        let jam_file = JamFile::parse(
+            "+++\r\n\
+            title = \"Spring Jam!\"\r\n\
+            start_date = \"2021-03-01\"\r\n\
+            end_date = \"2021-03-07\"\r\n\
+            +++\r\n\
+            Rules",
+        )
+        .unwrap();
+
+        assert_eq!("spring-jam", jam_file.slug);
+        assert_eq!("", jam_file.summary);
+        assert!(jam_file.attachment_ids.is_empty());
+        assert_eq!("Rules", jam_file.description);
+    }
+
+    #[test]
+    fn test_jam_file_errors() {
+        assert!(matches!(
+            JamFile::parse("title = \"No front matter\""),
+            Err(JamFileError::MissingFrontMatter)
+        ));
+        assert!(matches!(
+            JamFile::parse("+++\ntitle = \"Unclosed\"\n"),
+            Err(JamFileError::MissingFrontMatter)
+        ));
+        assert!(matches!(
+            JamFile::parse("+++\ntitle = \"No dates\"\n+++\n"),
+            Err(JamFileError::InvalidFrontMatter(_))
+        ));
+        assert!(matches!(
+            JamFile::parse(
+                "+++\ntitle = \"Bad\"\nstart_date = \"2021-3-1\"\n\
+                end_date = \"March\"\n+++\n"
+            ),
+            Err(JamFileError::InvalidDate(_))
+        ));
+        assert!(matches!(
+            JamFile::parse(
+                "+++\ntitle = \"Backwards\"\nstart_date = \"2021-03-07\"\n\
+                end_date = \"2021-03-01\"\n+++\n"
+            ),
+            Err(JamFileError::EndsBeforeStart)
+        ));
+    }
+}
@@ -6,46 +6,91 @@ extern crate diesel;
 extern crate diesel_migrations;
 
 mod attachments;
+mod backup;
 mod cliopts;
 mod controllers;
 mod db;
 mod error_handlers;
+mod github;
+mod jam_files;
 mod migrate;
 mod models;
 mod schema;
 mod serve;
 mod template_helpers;
+mod web_builds;
 
 use crate::attachments::AttachmentStorage;
 use crate::cliopts::{Opts, SubCommand};
-use crate::controllers::gh_oauth::GhCredentials;
+use crate::controllers::{attachments::UploadLimits, gh_oauth::GhCredentials};
 use crate::db::{get_pool, DbConn, DbPool};
+use crate::github::{GhApi, DEFAULT_GH_API_BASE_URL};
 use crate::models::{GhUserRecord, ModelError};
+use crate::web_builds::WebBuildStorage;
 use clap::Clap;
+use diesel::Connection;
 use dotenv::dotenv;
 use std::convert::TryFrom;
 use std::env;
 use std::num::ParseIntError;
 use std::path::PathBuf;
 
+/// Uploads larger than this are refused unless configured otherwise. 32 MiB is
+/// enough for a reasonable game build.
+const DEFAULT_MAX_ATTACHMENT_SIZE: u64 = 32 * 1024 * 1024;
+
+/// How much each participant may upload in all unless configured otherwise,
+/// which is a few builds of a few games.
+const DEFAULT_USER_ATTACHMENT_QUOTA: u64 = 512 * 1024 * 1024;
+
+/// How much may be uploaded for each entry unless configured otherwise, which
+/// is a few builds along with their screenshots.
+const DEFAULT_JAM_ENTRY_ATTACHMENT_QUOTA: u64 = 256 * 1024 * 1024;
+
+/// How large a web build may be once extracted unless configured otherwise,
+/// which leaves room for assets that compress well.
+const DEFAULT_MAX_WEB_BUILD_SIZE: u64 = 256 * 1024 * 1024;
+
 #[rocket::main]
 async fn main() {
     // load config from a .env file, really only applicable for development
     dotenv().ok();
 
     let database_path = expect_env_string("APP_DATABASE_PATH");
-    let db_pool = get_pool(&database_path);
     let opts = Opts::parse();
 
+    // restoring replaces the database file out from under anything which has
+    // it open, so it has to happen before the pool opens any connections
+    if let SubCommand::Restore(restore) = &opts.subcmd {
+        restore_subcmd(&database_path, restore);
+        return;
+    }
+
+    let db_pool = get_pool(&database_path);
+
     match opts.subcmd {
         SubCommand::Migrate(_) => {
             println!("Migrating the database at {}", database_path);
             crate::db::migrate_db(&db_pool);
+            report_unrecognized_permissions(&db_pool);
         }
         SubCommand::Serve(_) => {
-            let attachment_storage = AttachmentStorage::new(PathBuf::from(
-                expect_env_string("UDEVGAMES_ATTACHMENT_STORAGE"),
-            ));
+            let attachment_storage = attachment_storage_from_env();
+            let web_build_storage = web_build_storage_from_env();
+            let upload_limits = UploadLimits {
+                max_attachment_size: env_u64_or(
+                    "UDEVGAMES_MAX_ATTACHMENT_SIZE",
+                    DEFAULT_MAX_ATTACHMENT_SIZE,
+                ),
+                user_quota: env_u64_or(
+                    "UDEVGAMES_USER_ATTACHMENT_QUOTA",
+                    DEFAULT_USER_ATTACHMENT_QUOTA,
+                ),
+                jam_entry_quota: env_u64_or(
+                    "UDEVGAMES_JAM_ENTRY_ATTACHMENT_QUOTA",
+                    DEFAULT_JAM_ENTRY_ATTACHMENT_QUOTA,
+                ),
+            };
             let gh_credentials = GhCredentials {
                 client_id: expect_env_string("GH_CLIENT_ID"),
                 client_secret: expect_env_string("GH_CLIENT_SECRET"),
@@ -59,13 +104,445 @@ async fn main() {
                 db_pool,
                 gh_credentials,
                 attachment_storage,
+                web_build_storage,
+                upload_limits,
             )
             .await;
         }
         SubCommand::Permission(subcmd) => perms_subcmd(&db_pool, subcmd),
+        SubCommand::User(subcmd) => user_subcmd(&db_pool, subcmd).await,
+        SubCommand::Jam(subcmd) => jam_subcmd(&db_pool, subcmd),
+        SubCommand::Attachments(subcmd) => {
+            attachments_subcmd(&db_pool, subcmd).await
+        }
+        SubCommand::Backup(backup) => backup_subcmd(&database_path, backup),
+        SubCommand::Restore(_) => {
+            unreachable!("restores are handled before the pool is opened")
+        }
     }
 }
 
+async fn attachments_subcmd(
+    pool: &DbPool,
+    subcmd: crate::cliopts::Attachments,
+) {
+    use crate::attachments::{is_variant_key_of, AttachmentStorageError};
+    use crate::cliopts::AttachmentsSubCommand;
+    use crate::models::{Attachment, Blob, WebBuild};
+    use std::collections::{HashMap, HashSet};
+
+    let conn = pool
+        .get()
+        .expect("Could not get a connection from the pool");
+    let attachment_storage = attachment_storage_from_env();
+
+    match subcmd.subcmd {
+        AttachmentsSubCommand::Verify(verify) => {
+            let mut attachments =
+                Attachment::find_all(&conn).expect("Could not query db");
+            let mut problems = 0;
+            // attachments with the same contents share a file, which only
+            // needs reading once
+            let mut digests_by_key = HashMap::new();
+
+            for attachment in attachments.iter_mut() {
+                let key = attachment.storage_key();
+                let digests = match digests_by_key.get(&key) {
+                    Some(digests) => *digests,
+                    None => {
+                        let digests = match attachment_storage
+                            .digests(&key)
+                            .await
+                        {
+                            Ok(digests) => Some(digests),
+                            Err(AttachmentStorageError::NotFound(_)) => None,
+                            Err(e) => panic!(
+                                "Could not read attachment {}: {}",
+                                attachment.id, e
+                            ),
+                        };
+                        digests_by_key.insert(key, digests);
+                        digests
+                    }
+                };
+
+                match digests {
+                    None => println!(
+                        "missing {} {}: no file in storage",
+                        attachment.id, attachment.name
+                    ),
+                    Some(digests) if digests.md5[..] != attachment.md5[..] => {
+                        println!(
+                            "mismatch {} {}: expected md5 {} but found {}",
+                            attachment.id,
+                            attachment.name,
+                            hex::encode(&attachment.md5),
+                            hex::encode(digests.md5)
+                        )
+                    }
+                    Some(digests) => match &attachment.sha256 {
+                        Some(sha256) if digests.sha256[..] != sha256[..] => {
+                            println!(
+                                "mismatch {} {}: expected sha256 {} but \
+                                found {}",
+                                attachment.id,
+                                attachment.name,
+                                hex::encode(sha256),
+                                hex::encode(digests.sha256)
+                            )
+                        }
+                        Some(_) => continue,
+                        None => {
+                            // uploaded before sha256s were recorded, and the
+                            // md5 says the file is still what was uploaded
+                            attachment
+                                .set_sha256(&conn, &digests.sha256)
+                                .expect("Could not record sha256");
+                            println!(
+                                "recorded sha256 {} {}",
+                                attachment.id, attachment.name
+                            );
+                            continue;
+                        }
+                    },
+                }
+                problems += 1;
+
+                if verify.unpublish && attachment.published {
+                    attachment
+                        .set_published(&conn, false)
+                        .expect("Could not unpublish attachment");
+                    println!("unpublished {}", attachment.id);
+                }
+            }
+
+            let storage_keys = attachments
+                .iter()
+                .map(|attachment| attachment.storage_key())
+                .collect::<HashSet<String>>();
+            for file_name in attachment_storage
+                .file_names()
+                .await
+                .expect("Could not list attachment storage")
+            {
+                // resized variants live next to their originals
+                let is_variant = storage_keys
+                    .iter()
+                    .any(|key| is_variant_key_of(&file_name, key));
+                if !storage_keys.contains(&file_name) && !is_variant {
+                    println!("orphan {}: no attachment in the db", file_name);
+                    problems += 1;
+                }
+            }
+
+            println!(
+                "{} attachments verified, {} problems",
+                attachments.len(),
+                problems
+            );
+            if problems > 0 {
+                std::process::exit(1);
+            }
+        }
+        AttachmentsSubCommand::Dedupe(_) => {
+            let mut attachments =
+                Attachment::find_all(&conn).expect("Could not query db");
+            let mut moved = 0;
+            let mut skipped = 0;
+
+            for attachment in attachments
+                .iter_mut()
+                .filter(|attachment| attachment.blob_id.is_none())
+            {
+                let old_key = attachment.storage_key();
+                let digests = match attachment_storage.digests(&old_key).await {
+                    Ok(digests) => digests,
+                    Err(AttachmentStorageError::NotFound(_)) => {
+                        println!(
+                            "missing {} {}: no file in storage",
+                            attachment.id, attachment.name
+                        );
+                        skipped += 1;
+                        continue;
+                    }
+                    Err(e) => panic!(
+                        "Could not read attachment {}: {}",
+                        attachment.id, e
+                    ),
+                };
+
+                let is_intact = digests.md5[..] == attachment.md5[..]
+                    && attachment.sha256.as_ref().map_or(true, |sha256| {
+                        digests.sha256[..] == sha256[..]
+                    });
+                if !is_intact {
+                    println!(
+                        "mismatch {} {}: see attachments verify",
+                        attachment.id, attachment.name
+                    );
+                    skipped += 1;
+                    continue;
+                }
+
+                // the file is in place before anything points at it, and the
+                // old one is only removed once nothing does
+                let new_key = hex::encode(digests.sha256);
+                let is_new = Blob::find_by_sha256(&conn, &digests.sha256)
+                    .expect("Could not query db")
+                    .is_none();
+                if is_new {
+                    attachment_storage
+                        .copy(&old_key, &new_key, &attachment.mime_type)
+                        .await
+                        .unwrap_or_else(|e| {
+                            panic!(
+                                "Could not copy attachment {}: {}",
+                                attachment.id, e
+                            )
+                        });
+                }
+                attachment
+                    .assign_blob(&conn, &digests)
+                    .expect("Could not record blob");
+                attachment_storage
+                    .delete(&old_key)
+                    .await
+                    .expect("Could not delete attachment file");
+
+                println!(
+                    "moved {} {} to {}",
+                    attachment.id, attachment.name, new_key
+                );
+                moved += 1;
+            }
+
+            println!("{} attachments moved, {} skipped", moved, skipped);
+            if skipped > 0 {
+                std::process::exit(1);
+            }
+        }
+        AttachmentsSubCommand::Usage(usage) => {
+            use crate::models::{JamEntry, JamEntryUpdate};
+
+            let attachments =
+                Attachment::find_all(&conn).expect("Could not query db");
+
+            // bytes and attachment counts, by uploader and by entry
+            let mut by_user: HashMap<i64, (u64, usize)> = HashMap::new();
+            let mut by_jam_entry: HashMap<i32, (u64, usize)> = HashMap::new();
+            let mut jam_entry_ids_by_update: HashMap<i32, Option<i32>> =
+                HashMap::new();
+
+            // counted the same way as the quotas, which don't count deleted
+            // attachments
+            for attachment in attachments
+                .iter()
+                .filter(|attachment| attachment.deleted_at.is_none())
+            {
+                let size = attachment.size as u64;
+
+                if let Some(user_id) = attachment.uploader_user_id {
+                    let used = by_user.entry(user_id).or_insert((0, 0));
+                    *used = (used.0 + size, used.1 + 1);
+                }
+
+                let jam_entry_id = match attachment.jam_entry_update_id {
+                    Some(update_id) => *jam_entry_ids_by_update
+                        .entry(update_id)
+                        .or_insert_with(|| {
+                            JamEntryUpdate::find_by_id(&conn, update_id)
+                                .expect("Could not query db")
+                                .map(|update| update.jam_entry_id)
+                        }),
+                    None => attachment.jam_entry_id,
+                };
+                if let Some(jam_entry_id) = jam_entry_id {
+                    let used =
+                        by_jam_entry.entry(jam_entry_id).or_insert((0, 0));
+                    *used = (used.0 + size, used.1 + 1);
+                }
+            }
+
+            let mut by_user = by_user.into_iter().collect::<Vec<_>>();
+            by_user.sort_by(|a, b| b.1.cmp(&a.1));
+            println!("Top users:");
+            for (user_id, (bytes, count)) in by_user.iter().take(usage.top) {
+                let login = GhUserRecord::find_by_id(&conn, *user_id)
+                    .expect("Could not query db")
+                    .map_or("(deleted)".to_string(), |user| {
+                        format!("@{}", user.login)
+                    });
+                println!(
+                    "{} {} {} bytes in {} attachments",
+                    user_id, login, bytes, count
+                );
+            }
+
+            let mut by_jam_entry = by_jam_entry.into_iter().collect::<Vec<_>>();
+            by_jam_entry.sort_by(|a, b| b.1.cmp(&a.1));
+            println!("Top entries:");
+            for (jam_entry_id, (bytes, count)) in
+                by_jam_entry.iter().take(usage.top)
+            {
+                let title = JamEntry::find_by_id(&conn, *jam_entry_id)
+                    .expect("Could not query db")
+                    .map_or("(deleted)".to_string(), |jam_entry| {
+                        jam_entry.title
+                    });
+                println!(
+                    "{} {} {} bytes in {} attachments",
+                    jam_entry_id, title, bytes, count
+                );
+            }
+        }
+        AttachmentsSubCommand::Gc(gc) => {
+            let cutoff = chrono::offset::Utc::now().naive_utc()
+                - chrono::Duration::days(gc.retention_days);
+            let attachments =
+                Attachment::find_all(&conn).expect("Could not query db");
+            let mut removed = 0;
+            let mut files_removed = 0;
+
+            for attachment in attachments {
+                let is_collectable = attachment
+                    .is_collectable(&conn, cutoff)
+                    .expect("Could not query db");
+                if !is_collectable {
+                    continue;
+                }
+
+                let reason = match attachment.deleted_at {
+                    Some(_) => "deleted",
+                    None => "unused",
+                };
+                if gc.dry_run {
+                    println!(
+                        "would remove {} {}: {}",
+                        attachment.id, attachment.name, reason
+                    );
+                    removed += 1;
+                    continue;
+                }
+
+                // checked again alongside the delete, in case the site used
+                // it since
+                let unused_key = conn
+                    .transaction::<Option<Option<String>>, ModelError, _>(
+                        || {
+                            if !attachment.is_collectable(&conn, cutoff)? {
+                                return Ok(None);
+                            }
+                            Ok(Some(attachment.delete(&conn)?))
+                        },
+                    )
+                    .expect("Could not delete row");
+                let unused_key = match unused_key {
+                    Some(unused_key) => unused_key,
+                    None => continue,
+                };
+
+                // only once the database no longer refers to it, and not if
+                // an upload with the same contents has come along since
+                let is_reuploaded = match &attachment.sha256 {
+                    Some(the_sha256) if attachment.blob_id.is_some() => {
+                        Blob::find_by_sha256(&conn, the_sha256)
+                            .expect("Could not query db")
+                            .is_some()
+                    }
+                    _ => false,
+                };
+                if let Some(key) = unused_key.filter(|_| !is_reuploaded) {
+                    attachment_storage
+                        .delete(&key)
+                        .await
+                        .expect("Could not delete attachment file");
+                    files_removed += 1;
+                }
+                println!(
+                    "removed {} {}: {}",
+                    attachment.id, attachment.name, reason
+                );
+                removed += 1;
+            }
+
+            // extracted web builds go once nothing plays them, which includes
+            // those whose attachments were just removed
+            let web_build_storage = web_build_storage_from_env();
+            let played = WebBuild::find_all_directories(&conn)
+                .expect("Could not query db")
+                .into_iter()
+                .collect::<HashSet<String>>();
+            let mut web_builds_removed = 0;
+
+            for directory in web_build_storage
+                .directories()
+                .expect("Could not list web builds")
+            {
+                if played.contains(&directory) {
+                    continue;
+                }
+
+                if gc.dry_run {
+                    println!("would remove web build {}", directory);
+                } else {
+                    web_build_storage
+                        .delete(&directory)
+                        .expect("Could not delete web build");
+                    println!("removed web build {}", directory);
+                }
+                web_builds_removed += 1;
+            }
+
+            if gc.dry_run {
+                println!(
+                    "{} attachments and {} web builds would be removed",
+                    removed, web_builds_removed
+                );
+            } else {
+                println!(
+                    "{} attachments removed, {} files removed, {} web builds \
+                    removed",
+                    removed, files_removed, web_builds_removed
+                );
+            }
+        }
+    }
+}
+
+fn backup_subcmd(database_path: &str, backup: crate::cliopts::Backup) {
+    let attachment_dir = expect_local_attachment_storage();
+
+    let manifest = crate::backup::backup(
+        &PathBuf::from(database_path),
+        &attachment_dir,
+        &PathBuf::from(&backup.archive),
+    )
+    .unwrap_or_else(|e| panic!("Could not back up the site: {}", e));
+
+    println!(
+        "Backed up the database and {} attachments to {}",
+        manifest.attachments.len(),
+        backup.archive
+    );
+}
+
+fn restore_subcmd(database_path: &str, restore: &crate::cliopts::Restore) {
+    let attachment_dir = expect_local_attachment_storage();
+
+    let manifest = crate::backup::restore(
+        &PathBuf::from(&restore.archive),
+        &PathBuf::from(database_path),
+        &attachment_dir,
+    )
+    .unwrap_or_else(|e| panic!("Could not restore {}: {}", restore.archive, e));
+
+    println!(
+        "Restored the database and {} attachments from a backup taken {}",
+        manifest.attachments.len(),
+        manifest.created_at
+    );
+}
+
 fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
     use crate::cliopts::PermissionSubCommand;
     use crate::models::Permission;
@@ -82,8 +559,8 @@ fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
                         prefixed with @?",
                 )
                 .uid(&conn)
-                .expect("Could not query database");
-            Permission::grant_permission(&conn, uid, &grant.permission)
+                .unwrap_or_else(|e| panic!("Could not find user: {}", e));
+            Permission::grant_permission(&conn, uid, grant.permission)
                 .expect("Could not grant permission");
             println!("Permission granted!");
         }
@@ -94,9 +571,9 @@ fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
                         prefixed with @?",
                 )
                 .uid(&conn)
-                .expect("Could not query database");
+                .unwrap_or_else(|e| panic!("Could not find user: {}", e));
             let r =
-                Permission::revoke_permission(&conn, uid, &revoke.permission)
+                Permission::revoke_permission(&conn, uid, revoke.permission)
                     .expect("Could not revoke permission");
             println!("Revoked {} permissions", r);
         }
@@ -108,7 +585,7 @@ fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
                             prefixed with @?",
                     )
                     .uid(&conn)
-                    .expect("Could not query database");
+                    .unwrap_or_else(|e| panic!("Could not find user: {}", e));
                 let perms = Permission::find_by_gh_user_id(&conn, uid)
                     .expect("Could not query db");
 
@@ -122,7 +599,7 @@ fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
                 }
             } else if show.permission.is_some() {
                 let perm = show.permission.unwrap();
-                let perms = Permission::find_by_name(&conn, &perm)
+                let perms = Permission::find_by_name(&conn, perm)
                     .expect("Could not query db");
 
                 if perms.len() > 0 {
@@ -142,6 +619,458 @@ fn perms_subcmd(pool: &DbPool, subcmd: crate::cliopts::Permission) {
     }
 }
 
+async fn user_subcmd(pool: &DbPool, subcmd: crate::cliopts::User) {
+    use crate::cliopts::UserSubCommand;
+
+    let conn = pool
+        .get()
+        .expect("Could not get a connection from the pool");
+
+    match subcmd.subcmd {
+        UserSubCommand::List(list) => {
+            use crate::models::Permission;
+
+            let with_permission: Option<Vec<i64>> = list.permission.map(|p| {
+                Permission::find_by_name(&conn, p)
+                    .expect("Could not query db")
+                    .iter()
+                    .map(|perm| perm.gh_user_id)
+                    .collect()
+            });
+            let login = list.login.map(|l| l.to_lowercase());
+
+            let users = GhUserRecord::find_all(&conn)
+                .expect("Could not query db")
+                .into_iter()
+                .filter(|u| match &with_permission {
+                    Some(uids) => uids.contains(&u.id),
+                    None => true,
+                })
+                .filter(|u| match &login {
+                    Some(login) => u.login.to_lowercase().contains(login),
+                    None => true,
+                })
+                .collect::<Vec<GhUserRecord>>();
+
+            for user in &users {
+                let perms = Permission::find_by_gh_user_id(&conn, user.id)
+                    .expect("Could not query db")
+                    .iter()
+                    .map(|perm| perm.name.to_string())
+                    .collect::<Vec<String>>();
+                println!("{} @{} {}", user.id, user.login, perms.join(", "));
+            }
+            println!("{} users", users.len());
+        }
+        UserSubCommand::Show(show) => {
+            use crate::models::{Attachment, JamEntry, Permission};
+
+            let user = UserIdentity::try_from(show.user)
+                .expect(
+                    "Could not infer user; is your login \
+                        prefixed with @?",
+                )
+                .find(&conn)
+                .unwrap_or_else(|e| panic!("Could not find user: {}", e))
+                .expect("No such user");
+
+            println!("User {} @{}", user.id, user.login);
+            println!("  Profile: {}", user.html_url);
+            println!("  Avatar: {}", user.avatar_url);
+
+            let perms = Permission::find_by_gh_user_id(&conn, user.id)
+                .expect("Could not query db");
+            println!("Permissions");
+            for perm in &perms {
+                println!("  {}", perm.name);
+            }
+
+            let jam_entries =
+                JamEntry::find_by_submitter_user_id(&conn, user.id)
+                    .expect("Could not query db");
+            println!("Entries");
+            for jam_entry in &jam_entries {
+                let jam =
+                    jam_entry.load_jam(&conn).expect("Could not query db");
+                println!(
+                    "  {} {:?} in jam {} {:?}, {}",
+                    jam_entry.id,
+                    jam_entry.title,
+                    jam.id,
+                    jam.title,
+                    jam_entry.approval_state.to_human_str()
+                );
+            }
+
+            let attachments =
+                Attachment::find_by_uploader_user_id(&conn, user.id)
+                    .expect("Could not query db");
+            println!("Attachments");
+            for attachment in &attachments {
+                println!(
+                    "  {} {} {} {} bytes{}",
+                    attachment.id,
+                    attachment.name,
+                    attachment.mime_type,
+                    attachment.size,
+                    if attachment.deleted_at.is_some() {
+                        ", deleted"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        UserSubCommand::Delete(delete) => {
+            use crate::models::{Attachment, JamEntry};
+
+            let user = UserIdentity::try_from(delete.user)
+                .expect(
+                    "Could not infer user; is your login \
+                        prefixed with @?",
+                )
+                .find(&conn)
+                .unwrap_or_else(|e| panic!("Could not find user: {}", e))
+                .expect("No such user");
+
+            if delete.purge {
+                let attachment_storage = attachment_storage_from_env();
+                let (orphans, unused_keys) =
+                    user.purge(&conn).expect("Could not purge user");
+
+                // only once the database no longer refers to them
+                for key in &unused_keys {
+                    attachment_storage
+                        .delete(key)
+                        .await
+                        .expect("Could not delete attachment file");
+                }
+
+                println!(
+                    "Deleted user {} @{} along with {} attachments",
+                    user.id,
+                    user.login,
+                    orphans.len()
+                );
+            } else {
+                let jam_entries =
+                    JamEntry::find_by_submitter_user_id(&conn, user.id)
+                        .expect("Could not query db");
+
+                if jam_entries.len() > 0 {
+                    panic!(
+                        "User {} has {} entries; pass --purge to delete them \
+                            as well",
+                        user.id,
+                        jam_entries.len()
+                    );
+                }
+
+                let attachments =
+                    Attachment::find_by_uploader_user_id(&conn, user.id)
+                        .expect("Could not query db");
+
+                if attachments.len() > 0 {
+                    panic!(
+                        "User {} has uploaded {} attachments; pass --purge to \
+                            delete them as well",
+                        user.id,
+                        attachments.len()
+                    );
+                }
+
+                user.delete(&conn).expect("Could not delete user");
+                println!("Deleted user {} @{}", user.id, user.login);
+            }
+        }
+        UserSubCommand::Sync(sync) => {
+            let gh_api = GhApi::new(
+                &env::var("GH_API_BASE_URL")
+                    .unwrap_or(DEFAULT_GH_API_BASE_URL.to_string()),
+                env::var("GH_API_TOKEN").ok().filter(|t| !t.is_empty()),
+            );
+
+            let uids: Vec<i64> = if sync.all {
+                GhUserRecord::find_all(&conn)
+                    .expect("Could not query db")
+                    .iter()
+                    .map(|u| u.id)
+                    .collect()
+            } else if sync.user.is_some() {
+                let identity = UserIdentity::try_from(sync.user.unwrap())
+                    .expect(
+                        "Could not infer user; is your login \
+                            prefixed with @?",
+                    );
+
+                match identity {
+                    // everyone who has the login, which is the point
+                    UserIdentity::Login(login) => {
+                        let users =
+                            GhUserRecord::find_all_by_login(&conn, &login)
+                                .expect("Could not query db");
+                        if users.is_empty() {
+                            panic!("No such user @{}", login);
+                        }
+                        users.iter().map(|u| u.id).collect()
+                    }
+                    UserIdentity::Id(id) => vec![id],
+                }
+            } else {
+                panic!("Please supply either a user or --all to sync");
+            };
+
+            let mut failures = 0;
+            for uid in &uids {
+                if !sync_user(&conn, &gh_api, *uid).await {
+                    failures += 1;
+                }
+            }
+
+            // a renamed user's old login can since have been taken by someone
+            // else who has also logged in, so syncing whoever shares a login
+            // usually clears the collision up
+            let collisions = GhUserRecord::find_login_collisions(&conn)
+                .expect("Could not query db");
+            for user in collisions.iter().flatten() {
+                if !uids.contains(&user.id)
+                    && !sync_user(&conn, &gh_api, user.id).await
+                {
+                    failures += 1;
+                }
+            }
+
+            for collision in GhUserRecord::find_login_collisions(&conn)
+                .expect("Could not query db")
+            {
+                let ids: Vec<i64> = collision.iter().map(|u| u.id).collect();
+                println!(
+                    "Users {:?} still share the login @{}. Any of them which \
+                        no longer exist on Github may need to be deleted.",
+                    ids, collision[0].login
+                );
+            }
+
+            if failures > 0 {
+                println!("{} users could not be synced", failures);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn jam_subcmd(pool: &DbPool, subcmd: crate::cliopts::Jam) {
+    use crate::cliopts::JamSubCommand;
+    use crate::jam_files::{parse_jam_date_time, JamFile};
+    use crate::models::{slugify, ApprovalState, Attachment, Jam, NewJam};
+
+    let conn = pool
+        .get()
+        .expect("Could not get a connection from the pool");
+
+    match subcmd.subcmd {
+        JamSubCommand::Create(create) => {
+            let rich_text_content = match create.description_file {
+                Some(path) => std::fs::read_to_string(&path)
+                    .expect("Could not read the description file"),
+                None => "".to_string(),
+            };
+            let start_date = parse_jam_date_time(&create.start)
+                .unwrap_or_else(|e| panic!("{}", e));
+            let end_date = parse_jam_date_time(&create.end)
+                .unwrap_or_else(|e| panic!("{}", e));
+
+            if end_date < start_date {
+                panic!("The jam ends before it starts");
+            }
+
+            let slug = match create.slug {
+                Some(slug) => slug,
+                None => slugify(&create.title),
+            };
+
+            let jam = Jam::create_from(
+                &conn,
+                &NewJam {
+                    title: create.title,
+                    slug,
+                    summary: create.summary,
+                    rich_text_content,
+                    start_date,
+                    end_date,
+                },
+            )
+            .expect("Could not create jam");
+
+            println!("Created jam {} {}", jam.id, jam.slug);
+        }
+        JamSubCommand::List(list) => {
+            let state = list.state.map(|s| {
+                ApprovalState::from_human_str(&s)
+                    .unwrap_or_else(|e| panic!("{}", e))
+            });
+            let count = Jam::count(&conn, false).expect("Could not query db");
+            let jams = Jam::find_all(&conn, false, 0, count)
+                .expect("Could not query db");
+
+            for jam in jams {
+                if state.is_some() && state != Some(jam.approval_state) {
+                    continue;
+                }
+
+                println!(
+                    "{} {} {} to {} {} {:?}",
+                    jam.id,
+                    jam.approval_state.to_human_str(),
+                    jam.start_date.format("%Y-%m-%d"),
+                    jam.end_date.format("%Y-%m-%d"),
+                    jam.slug,
+                    jam.title
+                );
+            }
+        }
+        JamSubCommand::SetState(set_state) => {
+            let state = ApprovalState::from_human_str(&set_state.state)
+                .unwrap_or_else(|e| panic!("{}", e));
+            let mut jam = Jam::find_by_id(&conn, set_state.jam)
+                .expect("Could not query db")
+                .expect("No such jam");
+
+            jam.set_approval_state(&conn, state, set_state.reason.as_deref())
+                .unwrap_or_else(|e| panic!("Could not set state: {}", e));
+
+            println!("Jam {} is {}", jam.id, state.to_human_str());
+        }
+        JamSubCommand::Import(import) => {
+            let jam_file = JamFile::parse(
+                &std::fs::read_to_string(&import.file)
+                    .expect("Could not read the jam file"),
+            )
+            .unwrap_or_else(|e| panic!("Could not read the jam file: {}", e));
+
+            let jam = conn
+                .transaction::<Jam, ModelError, _>(|| {
+                    let jam = match import.jam {
+                        Some(jam_id) => {
+                            let mut jam = Jam::find_by_id(&conn, jam_id)?
+                                .ok_or(ModelError::NotFound)?;
+                            let mut rich_text = jam.load_rich_text(&conn)?;
+
+                            jam.title = jam_file.title.clone();
+                            jam.slug = jam_file.slug.clone();
+                            jam.summary = jam_file.summary.clone();
+                            jam.start_date =
+                                jam_file.start_date.and_hms(0, 0, 0);
+                            jam.end_date = jam_file.end_date.and_hms(0, 0, 0);
+                            rich_text.content = jam_file.description.clone();
+
+                            jam.update(&conn)?;
+                            rich_text.update(&conn)?;
+                            jam
+                        }
+                        None => {
+                            Jam::create_from(&conn, &jam_file.to_new_jam())?
+                        }
+                    };
+
+                    let rich_text = jam.load_rich_text(&conn)?;
+                    for attachment_id in &jam_file.attachment_ids {
+                        match Attachment::find_by_id(&conn, *attachment_id)? {
+                            Some(mut attachment) => {
+                                rich_text.attach(&conn, &mut attachment)?
+                            }
+                            None => println!(
+                                "Attachment {} does not exist here, so \
+                                    links to it will not work",
+                                attachment_id
+                            ),
+                        }
+                    }
+
+                    Ok(jam)
+                })
+                .unwrap_or_else(|e| panic!("Could not import jam: {}", e));
+
+            println!("Imported jam {} {}", jam.id, jam.slug);
+        }
+        JamSubCommand::Export(export) => {
+            let jam = Jam::find_by_id(&conn, export.jam)
+                .expect("Could not query db")
+                .expect("No such jam");
+            let jam_file =
+                JamFile::from_jam(&conn, &jam).expect("Could not query db");
+
+            match export.output {
+                Some(path) => std::fs::write(&path, jam_file.to_string())
+                    .expect("Could not write the jam file"),
+                None => print!("{}", jam_file),
+            }
+        }
+    }
+}
+
+/// Refreshes one user's cached details from Github, returning whether Github
+/// could be asked. Failing to ask is reported rather than fatal, so that one
+/// bad response doesn't stop everyone else being synced.
+async fn sync_user(conn: &DbConn, gh_api: &GhApi, uid: i64) -> bool {
+    let cached =
+        GhUserRecord::find_by_id(conn, uid).expect("Could not query db");
+    let gh_user = match gh_api.user_by_id(uid).await {
+        Ok(gh_user) => gh_user,
+        Err(e) => {
+            println!("Could not sync user {}: {}", uid, e);
+            return false;
+        }
+    };
+
+    match gh_user {
+        None => println!("User {} no longer exists on Github", uid),
+        Some(gh_user) => {
+            GhUserRecord::find_and_update(
+                conn,
+                gh_user.id,
+                &gh_user.login,
+                &gh_user.avatar_url,
+                &gh_user.html_url,
+            )
+            .expect("Could not update user");
+
+            match cached {
+                Some(cached) if cached.login != gh_user.login => println!(
+                    "User {} was @{} and is now @{}",
+                    uid, cached.login, gh_user.login
+                ),
+                _ => println!("User {} is @{}", uid, gh_user.login),
+            }
+        }
+    }
+
+    true
+}
+
+/// Grants made before permission names were checked are set aside by the
+/// migrations when they don't name a known permission. Nothing is done with
+/// them, so let whoever is migrating know they're there.
+fn report_unrecognized_permissions(pool: &DbPool) {
+    use crate::models::UnrecognizedPermission;
+
+    let conn = pool
+        .get()
+        .expect("Could not get a connection from the pool");
+    let perms =
+        UnrecognizedPermission::find_all(&conn).expect("Could not query db");
+
+    if perms.len() > 0 {
+        println!(
+            "These permissions were not recognized and are not in effect. \
+            Grant any that were meant again by their proper name."
+        );
+        for perm in perms {
+            println!("  User {} had {:?}", perm.gh_user_id, perm.name);
+        }
+    }
+}
+
 /// A user can be known either by id or by login. This enum abstracts over the
 /// two.
 enum UserIdentity {
@@ -153,15 +1082,14 @@ enum UserIdentity {
 }
 
 impl UserIdentity {
-    /// The uid for this identity.
+    /// The uid for this identity. A login which more than one cached user has
+    /// is an error, since there is no telling which of them was meant.
     fn uid(&self, conn: &DbConn) -> Result<i64, ModelError> {
         match self {
             UserIdentity::Id(id) => Ok(*id),
-            UserIdentity::Login(_) => Ok(self
-                .find(conn)
-                .expect("Could not query db")
-                .expect("No such user")
-                .id),
+            UserIdentity::Login(_) => {
+                Ok(self.find(conn)?.expect("No such user").id)
+            }
         }
     }
 
@@ -191,6 +1119,80 @@ impl TryFrom<String> for UserIdentity {
     }
 }
 
+/// Picks the attachment storage backend named by
+/// `UDEVGAMES_STORAGE_BACKEND`, which is the local directory unless it says
+/// otherwise.
+fn attachment_storage_from_env() -> AttachmentStorage {
+    use crate::attachments::{S3Config, S3Storage};
+
+    match env::var("UDEVGAMES_STORAGE_BACKEND").as_deref() {
+        Ok("local") | Err(_) => AttachmentStorage::local(PathBuf::from(
+            expect_env_string("UDEVGAMES_ATTACHMENT_STORAGE"),
+        )),
+        Ok("s3") => {
+            let config = S3Config {
+                endpoint: expect_env_string("UDEVGAMES_S3_ENDPOINT"),
+                region: env::var("UDEVGAMES_S3_REGION")
+                    .unwrap_or_else(|_| "us-east-1".to_string()),
+                bucket: expect_env_string("UDEVGAMES_S3_BUCKET"),
+                access_key: expect_env_string("UDEVGAMES_S3_ACCESS_KEY"),
+                secret_key: expect_env_string("UDEVGAMES_S3_SECRET_KEY"),
+                prefix: env::var("UDEVGAMES_S3_PREFIX").unwrap_or_default(),
+                presign_seconds: env::var("UDEVGAMES_S3_PRESIGN_SECONDS")
+                    .ok()
+                    .map(|string| {
+                        string.parse().expect(
+                            format!(
+                                "Expected u32 in env var \
+                                UDEVGAMES_S3_PRESIGN_SECONDS, but {} cannot \
+                                parse as a u32",
+                                string
+                            )
+                            .as_str(),
+                        )
+                    }),
+            };
+            let backend = S3Storage::new(config).unwrap_or_else(|e| {
+                panic!("Could not set up the object store: {}", e)
+            });
+
+            // uploads can't be renamed into a bucket, so stage them wherever
+            // the system keeps temporary files
+            AttachmentStorage::new(backend, env::temp_dir())
+        }
+        Ok(other) => panic!(
+            "Expected local or s3 in env var UDEVGAMES_STORAGE_BACKEND, but \
+            found {}",
+            other
+        ),
+    }
+}
+
+/// Web builds are always extracted locally, whichever backend attachments are
+/// kept in, since their files are served one at a time.
+fn web_build_storage_from_env() -> WebBuildStorage {
+    WebBuildStorage::new(
+        PathBuf::from(expect_env_string("UDEVGAMES_WEB_BUILD_STORAGE")),
+        env_u64_or("UDEVGAMES_MAX_WEB_BUILD_SIZE", DEFAULT_MAX_WEB_BUILD_SIZE),
+    )
+}
+
+/// Backups and restores work on the attachment directory itself, so they
+/// only make sense when attachments are stored locally.
+fn expect_local_attachment_storage() -> PathBuf {
+    match env::var("UDEVGAMES_STORAGE_BACKEND").as_deref() {
+        Ok("local") | Err(_) => {
+            PathBuf::from(expect_env_string("UDEVGAMES_ATTACHMENT_STORAGE"))
+        }
+        Ok(other) => panic!(
+            "Backups only cover locally stored attachments, but \
+            UDEVGAMES_STORAGE_BACKEND is {}; back up the bucket with the \
+            object store's own tools",
+            other
+        ),
+    }
+}
+
 fn expect_env_string(var: &str) -> String {
     env::var(var).expect(
         format!("Please provide {} as an environment var or in a .env", var)
@@ -209,3 +1211,16 @@ fn expect_env_u16(var: &str) -> u16 {
         .as_str(),
     )
 }
+
+fn env_u64_or(var: &str, default: u64) -> u64 {
+    match env::var(var) {
+        Ok(string) => string.parse().expect(
+            format!(
+                "Expected u64 in env var {}, but {} cannot parse as a u64",
+                var, string
+            )
+            .as_str(),
+        ),
+        Err(_) => default,
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
-    attachments::AttachmentStorage,
+    attachments::{digest_file, AttachmentStorageError, Digests},
     db::DbConn,
-    models::{last_insert_rowid, r_to_opt, ModelError},
+    models::{
+        last_insert_rowid, r_to_opt, ApprovalState, Blob, Jam, JamEntry,
+        JamEntryUpdate, ModelError,
+    },
 };
-use diesel::result::Error as DieselError;
-use std::path::PathBuf;
+use chrono::NaiveDateTime;
+use std::path::Path;
 
-/// An attachment, which is a file on disk.
+/// An attachment, which is a named file that can be downloaded. The file
+/// itself is a [`Blob`], which may be shared with other attachments.
 #[derive(Debug, Queryable)]
 pub struct Attachment {
     /// Unique id of this attachment.
@@ -25,93 +29,954 @@ pub struct Attachment {
     /// The MD5 of the file. If this differs from what is on disk, we may be
     /// experiencing bitrot or an attack.
     pub md5: Vec<u8>,
+
+    /// The SHA-256 of the file, which is what people downloading it are shown
+    /// so they can check they got what was uploaded. Attachments from before
+    /// this was recorded don't have one until it is backfilled.
+    pub sha256: Option<Vec<u8>>,
+
+    /// The blob which is this attachment's file. Attachments uploaded before
+    /// blobs existed don't have one until `attachments dedupe` moves them
+    /// over, and until then their file is stored under their id.
+    pub blob_id: Option<i32>,
+
+    /// The user who uploaded this attachment. Attachments uploaded before
+    /// this was recorded don't have one.
+    pub uploader_user_id: Option<i64>,
+
+    /// The jam this attachment belongs to, if it belongs to a jam.
+    pub jam_id: Option<i32>,
+
+    /// The entry this attachment belongs to, if it belongs to an entry.
+    pub jam_entry_id: Option<i32>,
+
+    /// The update this attachment belongs to, if it belongs to an update.
+    pub jam_entry_update_id: Option<i32>,
+
+    /// The length of the file in bytes, which counts against upload quotas.
+    pub size: i64,
+
+    /// When this attachment was uploaded.
+    pub created_at: NaiveDateTime,
+
+    /// When this attachment was deleted. A deleted attachment is gone as far
+    /// as the site is concerned, but its file stays in storage until `udevgames
+    /// attachments gc` runs after the retention window.
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// What an attachment belongs to, whose approval decides whether it may be
+/// downloaded by anyone other than its uploader and admins. An attachment
+/// belongs to whatever first uses it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachmentOwner {
+    Jam(i32),
+    JamEntry(i32),
+    JamEntryUpdate(i32),
 }
 
 impl Attachment {
-    /// Create a new attachment from a temporary file. Copies it to a permanent
-    /// storage location and md5's it.
+    /// Create a new attachment from a file, digesting it and taking a
+    /// reference to the blob with its contents. The file isn't stored
+    /// anywhere yet; if the blob which comes back is new, that is up to the
+    /// caller.
     pub fn create(
         conn: &DbConn,
-        attachment_storage: &AttachmentStorage,
-        the_file: impl AsRef<PathBuf>,
+        the_file: impl AsRef<Path>,
         the_name: &str,
         the_mime_type: &str,
-    ) -> Result<Attachment, ModelError> {
+        the_uploader_user_id: i64,
+    ) -> Result<(Attachment, Blob), ModelError> {
         use crate::schema::attachments::dsl::{
-            attachments, id, md5, mime_type, name,
+            attachments, blob_id, created_at, id, md5, mime_type, name, sha256,
+            size, uploader_user_id,
         };
         use diesel::prelude::*;
 
-        let the_file = the_file.as_ref();
+        let the_file = the_file.as_ref().to_path_buf();
 
         if !the_file.exists() {
-            return Err(ModelError::FileNotFoundError(the_file.clone()));
+            return Err(ModelError::FileNotFoundError(the_file));
         }
 
-        let attachment =
-            // transaction so last_insert_rowid doesn't do anything untoward
-            conn.transaction::<Attachment, DieselError, _>(|| {
-                diesel::insert_into(attachments)
-                    .values((
-                        name.eq(the_name), mime_type.eq(the_mime_type),
-                        md5.eq(vec![]) // how to insert binary data?
-                    ))
-                    .execute(conn)?;
-                let rowid = diesel::select(last_insert_rowid)
-                    .get_result::<i32>(conn)?;
-                Ok(
-                    attachments
-                        .filter(id.eq(rowid))
-                        .limit(1)
-                        .first::<Attachment>(conn)?
-                )
-            })?;
-
-        let mut stored_attachment =
-            attachment_storage.store(&the_file, attachment.id)?;
-
-        diesel::update(attachments)
-            .set(md5.eq(stored_attachment.get_or_compute_md5()?.to_vec()))
-            .execute(conn)?;
+        let digests =
+            digest_file(&the_file).map_err(AttachmentStorageError::from)?;
+
+        // transaction so last_insert_rowid doesn't do anything untoward
+        conn.transaction::<(Attachment, Blob), ModelError, _>(|| {
+            let blob = Blob::reference(conn, &digests)?;
+
+            diesel::insert_into(attachments)
+                .values((
+                    name.eq(the_name),
+                    mime_type.eq(the_mime_type),
+                    md5.eq(digests.md5.to_vec()),
+                    sha256.eq(Some(digests.sha256.to_vec())),
+                    blob_id.eq(Some(blob.id)),
+                    uploader_user_id.eq(Some(the_uploader_user_id)),
+                    size.eq(digests.size as i64),
+                    created_at.eq(chrono::offset::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+            let attachment = attachments
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<Attachment>(conn)?;
 
-        Ok(attachment)
+            Ok((attachment, blob))
+        })
     }
 
-    /// Finds an attachment by its id, if it exists.
+    /// Finds an attachment by its id, if it exists and hasn't been deleted.
     pub fn find_by_id(
         conn: &DbConn,
         attachment_id: i32,
     ) -> Result<Option<Attachment>, ModelError> {
-        use crate::schema::attachments::dsl::{attachments, id};
+        use crate::schema::attachments::dsl::{attachments, deleted_at, id};
         use diesel::prelude::*;
 
         let attachment = attachments
             .filter(id.eq(attachment_id))
+            .filter(deleted_at.is_null())
             .limit(1)
             .first::<Attachment>(conn);
 
         r_to_opt(attachment)
     }
 
-    /// Finds an attachment by its id, if it exists, and only if it is
-    /// published.
-    pub fn find_published_by_id(
+    /// Finds every attachment, in the order they were uploaded, including
+    /// those which have been deleted but not yet collected.
+    pub fn find_all(conn: &DbConn) -> Result<Vec<Attachment>, ModelError> {
+        use crate::schema::attachments::dsl::{attachments, id};
+        use diesel::prelude::*;
+
+        Ok(attachments.order(id.asc()).load::<Attachment>(conn)?)
+    }
+
+    /// Finds every attachment the given user uploaded, in the order they were
+    /// uploaded, including those which have been deleted but not yet
+    /// collected.
+    pub fn find_by_uploader_user_id(
         conn: &DbConn,
-        attachment_id: i32,
-    ) -> Result<Option<Attachment>, ModelError> {
-        use crate::schema::attachments::dsl::{attachments, id, published};
+        the_uploader_user_id: i64,
+    ) -> Result<Vec<Attachment>, ModelError> {
+        use crate::schema::attachments::dsl::{
+            attachments, id, uploader_user_id,
+        };
         use diesel::prelude::*;
 
-        let attachment = attachments
-            .filter(id.eq(attachment_id))
-            .filter(published.eq(true))
-            .limit(1)
-            .first::<Attachment>(conn);
+        Ok(attachments
+            .filter(uploader_user_id.eq(the_uploader_user_id))
+            .order(id.asc())
+            .load::<Attachment>(conn)?)
+    }
 
-        r_to_opt(attachment)
+    /// How many bytes of attachments the given user has uploaded and not
+    /// deleted.
+    pub fn total_size_by_uploader(
+        conn: &DbConn,
+        the_uploader_user_id: i64,
+    ) -> Result<u64, ModelError> {
+        use crate::schema::attachments::dsl::{
+            attachments, deleted_at, size, uploader_user_id,
+        };
+        use diesel::prelude::*;
+
+        let sizes = attachments
+            .filter(uploader_user_id.eq(the_uploader_user_id))
+            .filter(deleted_at.is_null())
+            .select(size)
+            .load::<i64>(conn)?;
+
+        Ok(sizes.iter().map(|s| *s as u64).sum())
+    }
+
+    /// How many bytes of attachments belong to the given entry, either
+    /// directly or through its updates, not counting deleted ones.
+    pub fn total_size_by_jam_entry(
+        conn: &DbConn,
+        jam_entry: &JamEntry,
+    ) -> Result<u64, ModelError> {
+        use crate::schema::attachments::dsl::{
+            attachments, deleted_at, jam_entry_id, jam_entry_update_id, size,
+        };
+        use diesel::prelude::*;
+
+        let jam_entry_update_ids =
+            JamEntryUpdate::find_by_jam_entry_id(conn, jam_entry.id, false)?
+                .iter()
+                .map(|jam_entry_update| jam_entry_update.id)
+                .collect::<Vec<i32>>();
+
+        let sizes = attachments
+            .filter(
+                jam_entry_id
+                    .eq(jam_entry.id)
+                    .or(jam_entry_update_id.eq_any(jam_entry_update_ids)),
+            )
+            .filter(deleted_at.is_null())
+            .select(size)
+            .load::<i64>(conn)?;
+
+        Ok(sizes.iter().map(|s| *s as u64).sum())
+    }
+
+    /// Publishes or unpublishes this attachment.
+    pub fn set_published(
+        &mut self,
+        conn: &DbConn,
+        is_published: bool,
+    ) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{attachments, published};
+        use diesel::prelude::*;
+
+        diesel::update(attachments.find(self.id))
+            .set(published.eq(is_published))
+            .execute(conn)?;
+        self.published = is_published;
+
+        Ok(())
+    }
+
+    /// Deletes this attachment as far as the site is concerned. Its row and
+    /// file are kept until `udevgames attachments gc` collects them, so that
+    /// a mistake can still be undone by hand in the meantime.
+    pub fn soft_delete(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{attachments, deleted_at};
+        use diesel::prelude::*;
+
+        let now = chrono::offset::Utc::now().naive_utc();
+        diesel::update(attachments.find(self.id))
+            .set(deleted_at.eq(Some(now)))
+            .execute(conn)?;
+        self.deleted_at = Some(now);
+
+        Ok(())
+    }
+
+    /// Whether this attachment is ready to be collected as of the given
+    /// cutoff, which is the end of the retention window: either it was
+    /// deleted before then, or it was uploaded before then and nothing uses
+    /// it.
+    pub fn is_collectable(
+        &self,
+        conn: &DbConn,
+        cutoff: NaiveDateTime,
+    ) -> Result<bool, ModelError> {
+        match self.deleted_at {
+            Some(the_deleted_at) => Ok(the_deleted_at < cutoff),
+            None => Ok(self.created_at < cutoff && !self.is_referenced(conn)?),
+        }
+    }
+
+    /// Records the SHA-256 of an attachment which was uploaded before it was
+    /// recorded.
+    pub fn set_sha256(
+        &mut self,
+        conn: &DbConn,
+        the_sha256: &[u8],
+    ) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{attachments, sha256};
+        use diesel::prelude::*;
+
+        diesel::update(attachments.find(self.id))
+            .set(sha256.eq(Some(the_sha256)))
+            .execute(conn)?;
+        self.sha256 = Some(the_sha256.to_vec());
+
+        Ok(())
+    }
+
+    /// Points an attachment uploaded before blobs existed at the blob with
+    /// its contents, given digests of the file stored under its id. The file
+    /// needs to be stored under the blob's key first if the blob which comes
+    /// back is new, and the old one removed once this has committed.
+    pub fn assign_blob(
+        &mut self,
+        conn: &DbConn,
+        digests: &Digests,
+    ) -> Result<Blob, ModelError> {
+        use crate::schema::attachments::dsl::{
+            attachments, blob_id, sha256, size,
+        };
+        use diesel::prelude::*;
+
+        let blob = conn.transaction::<Blob, ModelError, _>(|| {
+            let blob = Blob::reference(conn, digests)?;
+
+            diesel::update(attachments.find(self.id))
+                .set((
+                    sha256.eq(Some(digests.sha256.to_vec())),
+                    blob_id.eq(Some(blob.id)),
+                    size.eq(digests.size as i64),
+                ))
+                .execute(conn)?;
+
+            Ok(blob)
+        })?;
+        self.sha256 = Some(digests.sha256.to_vec());
+        self.blob_id = Some(blob.id);
+
+        Ok(blob)
+    }
+
+    /// Forgets who uploaded this attachment, so that their user can be
+    /// deleted while something else still uses it. It no longer counts
+    /// against anyone's quota.
+    pub fn clear_uploader(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{attachments, uploader_user_id};
+        use diesel::prelude::*;
+
+        diesel::update(attachments.find(self.id))
+            .set(uploader_user_id.eq::<Option<i64>>(None))
+            .execute(conn)?;
+        self.uploader_user_id = None;
+
+        Ok(())
+    }
+
+    /// What this attachment belongs to, if anything has used it yet.
+    pub fn owner(&self) -> Option<AttachmentOwner> {
+        match (self.jam_id, self.jam_entry_id, self.jam_entry_update_id) {
+            (Some(the_jam_id), _, _) => Some(AttachmentOwner::Jam(the_jam_id)),
+            (_, Some(the_jam_entry_id), _) => {
+                Some(AttachmentOwner::JamEntry(the_jam_entry_id))
+            }
+            (_, _, Some(the_jam_entry_update_id)) => {
+                Some(AttachmentOwner::JamEntryUpdate(the_jam_entry_update_id))
+            }
+            _ => None,
+        }
+    }
+
+    /// Records what this attachment belongs to, unless it already belongs to
+    /// something. Whatever uses an attachment first keeps it, so that reusing
+    /// an image elsewhere doesn't change who may see it.
+    pub fn claim(
+        &mut self,
+        conn: &DbConn,
+        the_owner: AttachmentOwner,
+    ) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{
+            attachments, jam_entry_id, jam_entry_update_id, jam_id,
+        };
+        use diesel::prelude::*;
+
+        if self.owner().is_some() {
+            return Ok(());
+        }
+
+        let (the_jam_id, the_jam_entry_id, the_jam_entry_update_id) =
+            match the_owner {
+                AttachmentOwner::Jam(owner_id) => (Some(owner_id), None, None),
+                AttachmentOwner::JamEntry(owner_id) => {
+                    (None, Some(owner_id), None)
+                }
+                AttachmentOwner::JamEntryUpdate(owner_id) => {
+                    (None, None, Some(owner_id))
+                }
+            };
+
+        diesel::update(attachments.find(self.id))
+            .set((
+                jam_id.eq(the_jam_id),
+                jam_entry_id.eq(the_jam_entry_id),
+                jam_entry_update_id.eq(the_jam_entry_update_id),
+            ))
+            .execute(conn)?;
+        self.jam_id = the_jam_id;
+        self.jam_entry_id = the_jam_entry_id;
+        self.jam_entry_update_id = the_jam_entry_update_id;
+
+        Ok(())
+    }
+
+    /// Makes every attachment belonging to the given owner belong to nothing,
+    /// so that the owner can be deleted. They are only visible to their
+    /// uploaders and admins afterwards.
+    pub fn disown_all(
+        conn: &DbConn,
+        the_owner: AttachmentOwner,
+    ) -> Result<(), ModelError> {
+        use crate::schema::attachments::dsl::{
+            attachments, jam_entry_id, jam_entry_update_id, jam_id,
+        };
+        use diesel::prelude::*;
+
+        match the_owner {
+            AttachmentOwner::Jam(owner_id) => {
+                diesel::update(attachments.filter(jam_id.eq(owner_id)))
+                    .set(jam_id.eq::<Option<i32>>(None))
+                    .execute(conn)?
+            }
+            AttachmentOwner::JamEntry(owner_id) => {
+                diesel::update(attachments.filter(jam_entry_id.eq(owner_id)))
+                    .set(jam_entry_id.eq::<Option<i32>>(None))
+                    .execute(conn)?
+            }
+            AttachmentOwner::JamEntryUpdate(owner_id) => diesel::update(
+                attachments.filter(jam_entry_update_id.eq(owner_id)),
+            )
+            .set(jam_entry_update_id.eq::<Option<i32>>(None))
+            .execute(conn)?,
+        };
+
+        Ok(())
+    }
+
+    /// Whether the given user uploaded this attachment.
+    pub fn is_uploaded_by(&self, user_id: i64) -> bool {
+        self.uploader_user_id == Some(user_id)
+    }
+
+    /// Whether the given user may delete this attachment, which only its
+    /// uploader and admins may.
+    pub fn is_deletable_by(&self, user_id: i64, is_admin: bool) -> bool {
+        is_admin || self.is_uploaded_by(user_id)
+    }
+
+    /// Whether the given user may use this attachment in something they are
+    /// editing, such as for a summary image. Only its uploader may, besides
+    /// admins, and anyone may use attachments from before uploaders were
+    /// recorded.
+    pub fn is_usable_by(&self, user_id: i64, is_admin: bool) -> bool {
+        is_admin
+            || self.uploader_user_id.is_none()
+            || self.is_uploaded_by(user_id)
+    }
+
+    /// Whether this attachment may be downloaded by the given user, or by
+    /// anyone when nobody is logged in. Its uploader, whoever submitted the
+    /// entry it belongs to, and admins may always download it. Everyone else
+    /// may only once it is published and what it belongs to is approved;
+    /// attachments which don't belong to anything yet are only visible to
+    /// whoever uploaded them, unless they are from before uploaders were
+    /// recorded.
+    pub fn is_visible_to(
+        &self,
+        conn: &DbConn,
+        user_id: Option<i64>,
+        is_admin: bool,
+    ) -> Result<bool, ModelError> {
+        if is_admin || user_id.map_or(false, |u| self.is_uploaded_by(u)) {
+            return Ok(true);
+        }
+
+        let (jam_entry, approved) = match self.owner() {
+            Some(AttachmentOwner::Jam(the_jam_id)) => (
+                None,
+                Jam::find_by_id(conn, the_jam_id)?.map_or(false, |jam| {
+                    jam.approval_state == ApprovalState::Approved
+                }),
+            ),
+            Some(AttachmentOwner::JamEntry(the_jam_entry_id)) => {
+                match JamEntry::find_by_id(conn, the_jam_entry_id)? {
+                    Some(jam_entry) => {
+                        let approved =
+                            jam_entry.approval_state == ApprovalState::Approved;
+                        (Some(jam_entry), approved)
+                    }
+                    None => (None, false),
+                }
+            }
+            Some(AttachmentOwner::JamEntryUpdate(the_jam_entry_update_id)) => {
+                match JamEntryUpdate::find_by_id(conn, the_jam_entry_update_id)?
+                {
+                    Some(jam_entry_update) => {
+                        let jam_entry =
+                            jam_entry_update.load_jam_entry(conn)?;
+                        let approved = jam_entry_update.approval_state
+                            == ApprovalState::Approved
+                            && jam_entry.approval_state
+                                == ApprovalState::Approved;
+                        (Some(jam_entry), approved)
+                    }
+                    None => (None, false),
+                }
+            }
+            None => (None, self.uploader_user_id.is_none()),
+        };
+
+        if let (Some(jam_entry), Some(user_id)) = (&jam_entry, user_id) {
+            if jam_entry.is_owned_by(user_id) {
+                return Ok(true);
+            }
+        }
+
+        Ok(self.published && approved)
+    }
+
+    /// What the attachment's file is stored under, which is the key of its
+    /// blob or, for an attachment uploaded before blobs existed, its id.
+    pub fn storage_key(&self) -> String {
+        match (self.blob_id, &self.sha256) {
+            (Some(_), Some(the_sha256)) => hex::encode(the_sha256),
+            _ => self.id.to_string(),
+        }
+    }
+
+    /// Whether anything still uses this attachment, whether as the summary
+    /// image of a jam or entry, attached to a rich text, or as a build in a
+    /// release.
+    pub fn is_referenced(&self, conn: &DbConn) -> Result<bool, ModelError> {
+        use crate::schema::{
+            jam_entries, jams, release_artifacts, rich_text_attachments,
+        };
+        use diesel::prelude::*;
+
+        let jam_count = jams::table
+            .filter(jams::summary_attachment_id.eq(self.id))
+            .count()
+            .get_result::<i64>(conn)?;
+        let jam_entry_count = jam_entries::table
+            .filter(jam_entries::summary_attachment_id.eq(self.id))
+            .count()
+            .get_result::<i64>(conn)?;
+        let rich_text_count = rich_text_attachments::table
+            .filter(rich_text_attachments::attachment_id.eq(self.id))
+            .count()
+            .get_result::<i64>(conn)?;
+
+        let release_artifact_count = release_artifacts::table
+            .filter(release_artifacts::attachment_id.eq(self.id))
+            .count()
+            .get_result::<i64>(conn)?;
+
+        Ok(jam_count
+            + jam_entry_count
+            + rich_text_count
+            + release_artifact_count
+            > 0)
+    }
+
+    /// Deletes the record of this attachment, along with anything still
+    /// using it, giving up its reference to its blob. The file is left in
+    /// storage; if nothing else has the same contents, the key it is stored
+    /// under is returned so that it can be removed with
+    /// [`crate::attachments::AttachmentStorage::delete`] once whatever
+    /// transaction this is part of has committed.
+    pub fn delete(&self, conn: &DbConn) -> Result<Option<String>, ModelError> {
+        use crate::schema::attachments::dsl::attachments;
+        use crate::schema::{
+            jam_entries, jams, release_artifacts, rich_text_attachments,
+            web_builds,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<Option<String>, ModelError, _>(|| {
+            diesel::update(
+                jams::table.filter(jams::summary_attachment_id.eq(self.id)),
+            )
+            .set(jams::summary_attachment_id.eq::<Option<i32>>(None))
+            .execute(conn)?;
+            diesel::update(
+                jam_entries::table
+                    .filter(jam_entries::summary_attachment_id.eq(self.id)),
+            )
+            .set(jam_entries::summary_attachment_id.eq::<Option<i32>>(None))
+            .execute(conn)?;
+            diesel::delete(
+                rich_text_attachments::table
+                    .filter(rich_text_attachments::attachment_id.eq(self.id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                web_builds::table.filter(
+                    web_builds::release_artifact_id.eq_any(
+                        release_artifacts::table
+                            .select(release_artifacts::id)
+                            .filter(
+                                release_artifacts::attachment_id.eq(self.id),
+                            ),
+                    ),
+                ),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                release_artifacts::table
+                    .filter(release_artifacts::attachment_id.eq(self.id)),
+            )
+            .execute(conn)?;
+            diesel::delete(attachments.find(self.id)).execute(conn)?;
+
+            match self.blob_id {
+                Some(the_blob_id) => {
+                    Ok(Blob::release(conn, the_blob_id)?.map(|blob| blob.key()))
+                }
+                None => Ok(Some(self.storage_key())),
+            }
+        })
     }
 
     pub fn url(&self) -> String {
         format!("/attachments/{}/{}", self.id, self.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Attachment, AttachmentOwner};
+    use crate::attachments::Digests;
+    use crate::db::{get_test_pool, DbConn};
+    use crate::models::{
+        fixtures::{attachment, upload, user},
+        ApprovalState, Architecture, Blob, Jam, JamEntry, JamEntryUpdate,
+        Platform, Release, ReleaseArtifact,
+    };
+
+    #[test]
+    fn test_owner() {
+        let mut attachment = attachment(1, "shot.png", Some(7));
+        assert_eq!(None, attachment.owner());
+
+        attachment.jam_entry_update_id = Some(3);
+        assert_eq!(
+            Some(AttachmentOwner::JamEntryUpdate(3)),
+            attachment.owner()
+        );
+
+        attachment.jam_entry_id = Some(2);
+        assert_eq!(Some(AttachmentOwner::JamEntry(2)), attachment.owner());
+    }
+
+    #[test]
+    fn test_is_usable_by() {
+        let attachment = attachment(1, "shot.png", Some(7));
+        assert!(attachment.is_usable_by(7, false));
+        assert!(!attachment.is_usable_by(8, false));
+        assert!(attachment.is_usable_by(8, true));
+
+        // from before uploaders were recorded, so nobody can tell
+        assert!(self::attachment(1, "shot.png", None).is_usable_by(8, false));
+    }
+
+    /// Inserts an attachment the way they were before blobs existed, without
+    /// a SHA-256, blob or size.
+    fn insert_legacy_attachment(conn: &DbConn, the_name: &str) -> Attachment {
+        use crate::schema::attachments::dsl::{
+            attachments, created_at, id, md5, mime_type, name,
+        };
+        use diesel::prelude::*;
+
+        diesel::insert_into(attachments)
+            .values((
+                name.eq(the_name),
+                mime_type.eq("application/zip"),
+                md5.eq(vec![1; 16]),
+                created_at
+                    .eq(chrono::NaiveDate::from_ymd(2020, 10, 11)
+                        .and_hms(0, 0, 0)),
+            ))
+            .execute(conn)
+            .unwrap();
+        attachments
+            .order(id.desc())
+            .first::<Attachment>(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_assign_blob() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let digests = Digests {
+            md5: [1; 16],
+            sha256: [2; 32],
+            size: 1234,
+        };
+
+        let mut first = insert_legacy_attachment(&conn, "first.zip");
+        assert_eq!(0, first.size);
+        let blob = first.assign_blob(&conn, &digests).unwrap();
+        assert_eq!(1, blob.ref_count);
+        assert_eq!(1234, blob.size);
+        assert_eq!(Some(blob.id), first.blob_id);
+
+        // dedupe is the only thing which fills in the size of old attachments
+        let first = Attachment::find_by_id(&conn, first.id).unwrap().unwrap();
+        assert_eq!(Some(blob.id), first.blob_id);
+        assert_eq!(Some(vec![2; 32]), first.sha256);
+        assert_eq!(1234, first.size);
+        assert_eq!(None, first.uploader_user_id);
+
+        // the same contents again are the same blob
+        let mut second = insert_legacy_attachment(&conn, "second.zip");
+        let same_blob = second.assign_blob(&conn, &digests).unwrap();
+        assert_eq!(blob.id, same_blob.id);
+        assert_eq!(2, same_blob.ref_count);
+        assert_eq!(
+            2,
+            Blob::find_by_id(&conn, blob.id).unwrap().unwrap().ref_count
+        );
+    }
+
+    /// Whether an attachment is visible to nobody in particular, to someone
+    /// with nothing to do with it, and to an admin.
+    fn visible_to_others(conn: &DbConn, attachment: &Attachment) -> [bool; 3] {
+        [
+            attachment.is_visible_to(conn, None, false).unwrap(),
+            attachment.is_visible_to(conn, Some(9), false).unwrap(),
+            attachment.is_visible_to(conn, Some(9), true).unwrap(),
+        ]
+    }
+
+    /// An entry by user 1, with an attachment uploaded by user 2.
+    fn entry_attachment(conn: &DbConn) -> (JamEntry, Attachment) {
+        user(conn, 1);
+        user(conn, 2);
+        let jam = Jam::create(conn).unwrap();
+        let jam_entry = JamEntry::create(conn, jam.id, 1).unwrap();
+        let mut attachment = upload(conn, 2, "entry");
+        attachment
+            .claim(conn, AttachmentOwner::JamEntry(jam_entry.id))
+            .unwrap();
+
+        (jam_entry, attachment)
+    }
+
+    #[test]
+    fn test_is_visible_to_draft_entry() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let (_, attachment) = entry_attachment(&conn);
+
+        assert_eq!([false, false, true], visible_to_others(&conn, &attachment));
+        // the entry's owner and the uploader
+        assert!(attachment.is_visible_to(&conn, Some(1), false).unwrap());
+        assert!(attachment.is_visible_to(&conn, Some(2), false).unwrap());
+    }
+
+    #[test]
+    fn test_is_visible_to_approved_entry() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let (mut jam_entry, mut attachment) = entry_attachment(&conn);
+        jam_entry.submit(&conn).unwrap();
+        assert_eq!([false, false, true], visible_to_others(&conn, &attachment));
+
+        jam_entry.approve(&conn).unwrap();
+        assert_eq!([true, true, true], visible_to_others(&conn, &attachment));
+
+        attachment.set_published(&conn, false).unwrap();
+        assert_eq!([false, false, true], visible_to_others(&conn, &attachment));
+        assert!(attachment.is_visible_to(&conn, Some(1), false).unwrap());
+    }
+
+    #[test]
+    fn test_is_visible_to_jam_entry_update() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let (mut jam_entry, _) = entry_attachment(&conn);
+        let mut jam_entry_update =
+            JamEntryUpdate::create_with_rich_text(&conn, jam_entry.id).unwrap();
+        let mut attachment = upload(&conn, 1, "update");
+        attachment
+            .claim(&conn, AttachmentOwner::JamEntryUpdate(jam_entry_update.id))
+            .unwrap();
+
+        // an approved update on an entry which isn't
+        jam_entry_update.submit(&conn).unwrap();
+        jam_entry_update.approve(&conn).unwrap();
+        assert_eq!([false, false, true], visible_to_others(&conn, &attachment));
+        assert!(attachment.is_visible_to(&conn, Some(1), false).unwrap());
+
+        // and an approved entry whose update isn't
+        let mut other_update =
+            JamEntryUpdate::create_with_rich_text(&conn, jam_entry.id).unwrap();
+        let mut other_attachment = upload(&conn, 1, "other update");
+        other_attachment
+            .claim(&conn, AttachmentOwner::JamEntryUpdate(other_update.id))
+            .unwrap();
+        jam_entry.submit(&conn).unwrap();
+        jam_entry.approve(&conn).unwrap();
+        assert_eq!(
+            [false, false, true],
+            visible_to_others(&conn, &other_attachment)
+        );
+
+        // both approved
+        assert_eq!([true, true, true], visible_to_others(&conn, &attachment));
+        other_update.submit(&conn).unwrap();
+        other_update.approve(&conn).unwrap();
+        assert_eq!(
+            [true, true, true],
+            visible_to_others(&conn, &other_attachment)
+        );
+    }
+
+    #[test]
+    fn test_is_visible_to_jam() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        user(&conn, 2);
+        let mut jam = Jam::create(&conn).unwrap();
+        let mut attachment = upload(&conn, 2, "jam");
+        attachment
+            .claim(&conn, AttachmentOwner::Jam(jam.id))
+            .unwrap();
+
+        jam.set_approval_state(&conn, ApprovalState::Submitted, None)
+            .unwrap();
+        assert_eq!([false, false, true], visible_to_others(&conn, &attachment));
+        assert!(attachment.is_visible_to(&conn, Some(2), false).unwrap());
+
+        jam.set_approval_state(&conn, ApprovalState::Approved, None)
+            .unwrap();
+        assert_eq!([true, true, true], visible_to_others(&conn, &attachment));
+    }
+
+    #[test]
+    fn test_is_visible_to_nothing() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+
+        // from before uploaders were recorded, so it is as public as it ever
+        // was
+        let mut legacy = insert_legacy_attachment(&conn, "legacy.zip");
+        assert_eq!([true, true, true], visible_to_others(&conn, &legacy));
+        legacy.set_published(&conn, false).unwrap();
+        assert_eq!([false, false, true], visible_to_others(&conn, &legacy));
+
+        // uploaded but not used by anything yet
+        user(&conn, 2);
+        let unused = upload(&conn, 2, "unused");
+        assert_eq!([false, false, true], visible_to_others(&conn, &unused));
+        assert!(unused.is_visible_to(&conn, Some(2), false).unwrap());
+    }
+
+    #[test]
+    fn test_total_size_by_uploader() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        user(&conn, 1);
+        user(&conn, 2);
+
+        upload(&conn, 1, "first");
+        // counted again, even though the file is only stored once
+        upload(&conn, 1, "first");
+        let mut deleted = upload(&conn, 1, "deleted");
+        upload(&conn, 2, "someone else's");
+        assert_eq!(17, Attachment::total_size_by_uploader(&conn, 1).unwrap());
+
+        deleted.soft_delete(&conn).unwrap();
+        assert_eq!(10, Attachment::total_size_by_uploader(&conn, 1).unwrap());
+        assert_eq!(0, Attachment::total_size_by_uploader(&conn, 3).unwrap());
+    }
+
+    #[test]
+    fn test_total_size_by_jam_entry() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let (jam_entry, _) = entry_attachment(&conn);
+        let mut other_entry =
+            JamEntry::create(&conn, jam_entry.jam_id, 2).unwrap();
+        assert_eq!(
+            5,
+            Attachment::total_size_by_jam_entry(&conn, &jam_entry).unwrap()
+        );
+
+        let jam_entry_update =
+            JamEntryUpdate::create_with_rich_text(&conn, jam_entry.id).unwrap();
+        let mut update_attachment = upload(&conn, 1, "update");
+        update_attachment
+            .claim(&conn, AttachmentOwner::JamEntryUpdate(jam_entry_update.id))
+            .unwrap();
+        let mut deleted = upload(&conn, 1, "deleted");
+        deleted
+            .claim(&conn, AttachmentOwner::JamEntry(jam_entry.id))
+            .unwrap();
+        deleted.soft_delete(&conn).unwrap();
+        assert_eq!(
+            11,
+            Attachment::total_size_by_jam_entry(&conn, &jam_entry).unwrap()
+        );
+
+        // used by another entry too, but only counted against the first
+        other_entry.summary_attachment_id = Some(update_attachment.id);
+        other_entry.update(&conn).unwrap();
+        assert_eq!(
+            11,
+            Attachment::total_size_by_jam_entry(&conn, &jam_entry).unwrap()
+        );
+        assert_eq!(
+            0,
+            Attachment::total_size_by_jam_entry(&conn, &other_entry).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_soft_delete() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        user(&conn, 2);
+        let mut attachment = upload(&conn, 2, "deleted");
+        assert_eq!(7, Attachment::total_size_by_uploader(&conn, 2).unwrap());
+
+        attachment.soft_delete(&conn).unwrap();
+        assert!(attachment.deleted_at.is_some());
+        assert!(Attachment::find_by_id(&conn, attachment.id)
+            .unwrap()
+            .is_none());
+        assert_eq!(0, Attachment::total_size_by_uploader(&conn, 2).unwrap());
+
+        // kept until it is collected
+        let kept = Attachment::find_all(&conn).unwrap();
+        assert_eq!(
+            vec![attachment.id],
+            kept.iter().map(|a| a.id).collect::<Vec<i32>>()
+        );
+        assert_eq!(attachment.deleted_at, kept[0].deleted_at);
+    }
+
+    #[test]
+    fn test_is_collectable() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let now = chrono::offset::Utc::now().naive_utc();
+        let before = now - chrono::Duration::days(1);
+        let after = now + chrono::Duration::days(1);
+        user(&conn, 1);
+        user(&conn, 2);
+
+        // deleted, which only counts once the cutoff has passed
+        let mut deleted = upload(&conn, 2, "deleted");
+        deleted.soft_delete(&conn).unwrap();
+        assert!(!deleted.is_collectable(&conn, before).unwrap());
+        assert!(deleted.is_collectable(&conn, after).unwrap());
+
+        // never deleted, and nothing uses it
+        let unused = upload(&conn, 2, "unused");
+        assert!(!unused.is_collectable(&conn, before).unwrap());
+        assert!(unused.is_collectable(&conn, after).unwrap());
+
+        // never deleted, but used
+        let jam = Jam::create(&conn).unwrap();
+        let mut jam_entry = JamEntry::create(&conn, jam.id, 1).unwrap();
+        let summary = upload(&conn, 1, "summary");
+        jam_entry.summary_attachment_id = Some(summary.id);
+        jam_entry.update(&conn).unwrap();
+        assert!(!summary.is_collectable(&conn, after).unwrap());
+
+        let mut attached = upload(&conn, 1, "attached");
+        jam_entry
+            .load_rich_text(&conn)
+            .unwrap()
+            .attach(&conn, &mut attached)
+            .unwrap();
+        assert!(!attached.is_collectable(&conn, after).unwrap());
+
+        let release = Release::create(&conn, jam_entry.id, "1.0").unwrap();
+        let mut build = upload(&conn, 1, "build");
+        ReleaseArtifact::create(
+            &conn,
+            &release,
+            &mut build,
+            Platform::MacOs,
+            Architecture::Universal,
+        )
+        .unwrap();
+        assert!(!build.is_collectable(&conn, after).unwrap());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    attachments::Digests,
+    db::DbConn,
+    models::{last_insert_rowid, r_to_opt, ModelError},
+};
+
+/// The contents of a file, stored once no matter how many attachments have
+/// those same contents. Game builds get uploaded over and over, so this adds
+/// up.
+#[derive(Debug, Queryable)]
+pub struct Blob {
+    /// Unique id of this blob.
+    pub id: i32,
+
+    /// The SHA-256 of the file, which is also what it is stored under.
+    pub sha256: Vec<u8>,
+
+    /// The MD5 of the file.
+    pub md5: Vec<u8>,
+
+    /// The length of the file in bytes.
+    pub size: i64,
+
+    /// How many attachments are this blob. When the last of them is deleted,
+    /// so is the blob.
+    pub ref_count: i32,
+}
+
+impl Blob {
+    /// Takes a reference to the blob with the given digests, creating it if
+    /// nothing had those contents before. A blob which comes back with a
+    /// `ref_count` of one is new, and its file still needs to be stored.
+    pub fn reference(
+        conn: &DbConn,
+        digests: &Digests,
+    ) -> Result<Blob, ModelError> {
+        use crate::schema::blobs::dsl::{
+            blobs, id, md5, ref_count, sha256, size,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<Blob, ModelError, _>(|| {
+            let blob_id = match Blob::find_by_sha256(conn, &digests.sha256)? {
+                Some(blob) => {
+                    diesel::update(blobs.find(blob.id))
+                        .set(ref_count.eq(ref_count + 1))
+                        .execute(conn)?;
+                    blob.id
+                }
+                None => {
+                    diesel::insert_into(blobs)
+                        .values((
+                            sha256.eq(digests.sha256.to_vec()),
+                            md5.eq(digests.md5.to_vec()),
+                            size.eq(digests.size as i64),
+                            ref_count.eq(1),
+                        ))
+                        .execute(conn)?;
+                    diesel::select(last_insert_rowid).get_result::<i32>(conn)?
+                }
+            };
+
+            Ok(blobs.filter(id.eq(blob_id)).first::<Blob>(conn)?)
+        })
+    }
+
+    /// Gives up a reference to a blob. When that was the last one the blob
+    /// is deleted and returned, so that its file can be removed from storage
+    /// once whatever transaction this is part of has committed.
+    pub fn release(
+        conn: &DbConn,
+        blob_id: i32,
+    ) -> Result<Option<Blob>, ModelError> {
+        use crate::schema::blobs::dsl::{blobs, ref_count};
+        use diesel::prelude::*;
+
+        conn.transaction::<Option<Blob>, ModelError, _>(|| {
+            diesel::update(blobs.find(blob_id))
+                .set(ref_count.eq(ref_count - 1))
+                .execute(conn)?;
+
+            match Blob::find_by_id(conn, blob_id)? {
+                Some(blob) if blob.ref_count <= 0 => {
+                    diesel::delete(blobs.find(blob_id)).execute(conn)?;
+                    Ok(Some(blob))
+                }
+                _ => Ok(None),
+            }
+        })
+    }
+
+    /// Finds a blob by its id, if it exists.
+    pub fn find_by_id(
+        conn: &DbConn,
+        blob_id: i32,
+    ) -> Result<Option<Blob>, ModelError> {
+        use crate::schema::blobs::dsl::{blobs, id};
+        use diesel::prelude::*;
+
+        let blob = blobs.filter(id.eq(blob_id)).limit(1).first::<Blob>(conn);
+
+        r_to_opt(blob)
+    }
+
+    /// Finds the blob with the given contents, if anything has them.
+    pub fn find_by_sha256(
+        conn: &DbConn,
+        the_sha256: &[u8],
+    ) -> Result<Option<Blob>, ModelError> {
+        use crate::schema::blobs::dsl::{blobs, sha256};
+        use diesel::prelude::*;
+
+        let blob = blobs
+            .filter(sha256.eq(the_sha256))
+            .limit(1)
+            .first::<Blob>(conn);
+
+        r_to_opt(blob)
+    }
+
+    /// What the blob's file is stored under.
+    pub fn key(&self) -> String {
+        hex::encode(&self.sha256)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Blob;
+    use crate::db::get_test_pool;
+    use crate::models::fixtures::{upload, user};
+
+    #[test]
+    fn test_release() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        user(&conn, 1);
+        user(&conn, 2);
+
+        let first = upload(&conn, 1, "same");
+        let second = upload(&conn, 2, "same");
+        let blob_id = first.blob_id.unwrap();
+        assert_eq!(Some(blob_id), second.blob_id);
+        let blob = Blob::find_by_id(&conn, blob_id).unwrap().unwrap();
+        assert_eq!(2, blob.ref_count);
+
+        // the file is still needed by the second
+        assert_eq!(None, first.delete(&conn).unwrap());
+        let same_blob = Blob::find_by_id(&conn, blob_id).unwrap().unwrap();
+        assert_eq!(1, same_blob.ref_count);
+
+        assert_eq!(Some(blob.key()), second.delete(&conn).unwrap());
+        assert!(Blob::find_by_id(&conn, blob_id).unwrap().is_none());
+        assert!(Blob::find_by_sha256(&conn, &blob.sha256).unwrap().is_none());
+    }
+}
@@ -0,0 +1,59 @@
+//! Models for tests to start from, so that a new column only needs adding to
+//! them in one place.
+
+use crate::{
+    db::DbConn,
+    models::{Attachment, GhUserRecord},
+};
+use std::io::Write;
+
+/// An image attachment which exists only in memory, belonging to nothing.
+pub fn attachment(
+    id: i32,
+    name: &str,
+    uploader_user_id: Option<i64>,
+) -> Attachment {
+    Attachment {
+        id,
+        name: name.to_string(),
+        published: true,
+        mime_type: "image/png".to_string(),
+        md5: vec![],
+        sha256: None,
+        blob_id: None,
+        uploader_user_id,
+        jam_id: None,
+        jam_entry_id: None,
+        jam_entry_update_id: None,
+        size: 0,
+        created_at: chrono::NaiveDate::from_ymd(2020, 10, 11).and_hms(0, 0, 0),
+        deleted_at: None,
+    }
+}
+
+/// Someone who has signed in, with the given GitHub id.
+pub fn user(conn: &DbConn, id: i64) -> GhUserRecord {
+    GhUserRecord::find_and_update(conn, id, &format!("user{}", id), "", "")
+        .unwrap()
+}
+
+/// An attachment uploaded by the given user, which belongs to nothing yet.
+/// Its file isn't stored anywhere.
+pub fn upload(
+    conn: &DbConn,
+    uploader_user_id: i64,
+    contents: &str,
+) -> Attachment {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+
+    Attachment::create(
+        conn,
+        file.path(),
+        "upload.zip",
+        "application/zip",
+        uploader_user_id,
+    )
+    .unwrap()
+    .0
+}
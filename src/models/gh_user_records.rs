@@ -1,6 +1,6 @@
 use crate::{
     db::DbConn,
-    models::{r_to_opt, ModelError},
+    models::{r_to_opt, Attachment, JamEntry, ModelError},
 };
 
 /// Local cache of part of Github's understanding of who a user is. Particularly
@@ -69,6 +69,73 @@ impl GhUserRecord {
         Ok(GhUserRecord::find_by_id(&conn, gh_id)?.unwrap())
     }
 
+    /// Deletes this user and their permissions. Anything they made is left
+    /// alone, see [`GhUserRecord::purge`] to delete that too.
+    pub fn delete(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::gh_user_records::dsl::gh_user_records;
+        use crate::schema::permissions::dsl::{gh_user_id, permissions};
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            diesel::delete(permissions.filter(gh_user_id.eq(self.id)))
+                .execute(conn)?;
+            diesel::delete(gh_user_records.find(self.id)).execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Deletes this user along with everything they made, all in one
+    /// transaction. Attachments which nothing else uses, whether they belonged
+    /// to the user's entries or the user uploaded them, are deleted too, and
+    /// returned along with the storage keys of files which no attachment has
+    /// any more, so that those can be removed from storage afterwards. Any the
+    /// user uploaded which something else still uses are kept, without an
+    /// uploader.
+    pub fn purge(
+        &self,
+        conn: &DbConn,
+    ) -> Result<(Vec<Attachment>, Vec<String>), ModelError> {
+        use diesel::prelude::*;
+
+        conn.transaction::<(Vec<Attachment>, Vec<String>), ModelError, _>(
+            || {
+                let mut attachments: Vec<Attachment> = Vec::new();
+
+                for jam_entry in
+                    JamEntry::find_by_submitter_user_id(conn, self.id)?
+                {
+                    attachments.extend(jam_entry.load_all_attachments(conn)?);
+                    jam_entry.delete(conn)?;
+                }
+
+                attachments.extend(Attachment::find_by_uploader_user_id(
+                    conn, self.id,
+                )?);
+
+                let mut orphans: Vec<Attachment> = Vec::new();
+                let mut unused_keys: Vec<String> = Vec::new();
+                for mut attachment in attachments {
+                    if orphans.iter().any(|o| o.id == attachment.id) {
+                        continue;
+                    }
+
+                    if !attachment.is_referenced(conn)? {
+                        unused_keys.extend(attachment.delete(conn)?);
+                        orphans.push(attachment);
+                    } else if attachment.is_uploaded_by(self.id) {
+                        attachment.clear_uploader(conn)?;
+                    }
+                }
+
+                // last, as their attachments refer to them until now
+                self.delete(conn)?;
+
+                Ok((orphans, unused_keys))
+            },
+        )
+    }
+
     /// Finds a given GhUserRecord by its id.
     pub fn find_by_id(
         conn: &DbConn,
@@ -85,19 +152,133 @@ impl GhUserRecord {
         r_to_opt(user_record)
     }
 
-    /// Finds a given GhUserRecord by its login.
+    /// Finds the one GhUserRecord with the given login. Should our cache have
+    /// more than one, this is an [`crate::models::ModelError::AmbiguousLogin`]
+    /// rather than a guess.
     pub fn find_by_login(
         conn: &DbConn,
         gh_login: &str,
     ) -> Result<Option<GhUserRecord>, ModelError> {
+        let mut user_records = GhUserRecord::find_all_by_login(conn, gh_login)?;
+
+        match user_records.len() {
+            0 => Ok(None),
+            1 => Ok(user_records.pop()),
+            _ => Err(ModelError::AmbiguousLogin(
+                gh_login.to_string(),
+                user_records.iter().map(|u| u.id).collect(),
+            )),
+        }
+    }
+
+    /// Finds every GhUserRecord with the given login. Logins are only unique
+    /// on Github at any one moment, so after renames our cache can have more
+    /// than one user with the same login until it is synced.
+    pub fn find_all_by_login(
+        conn: &DbConn,
+        gh_login: &str,
+    ) -> Result<Vec<GhUserRecord>, ModelError> {
         use crate::schema::gh_user_records::dsl::*;
         use diesel::prelude::*;
 
-        let user_record = gh_user_records
+        let user_records = gh_user_records
             .filter(login.eq(gh_login))
-            .limit(1)
-            .first::<GhUserRecord>(conn);
+            .order(id.asc())
+            .load::<GhUserRecord>(conn)?;
 
-        r_to_opt(user_record)
+        Ok(user_records)
+    }
+
+    /// Finds every GhUserRecord.
+    pub fn find_all(conn: &DbConn) -> Result<Vec<GhUserRecord>, ModelError> {
+        use crate::schema::gh_user_records::dsl::*;
+        use diesel::prelude::*;
+
+        let user_records =
+            gh_user_records.order(id.asc()).load::<GhUserRecord>(conn)?;
+
+        Ok(user_records)
+    }
+
+    /// Finds groups of GhUserRecords which share a login, each group in order
+    /// of id.
+    pub fn find_login_collisions(
+        conn: &DbConn,
+    ) -> Result<Vec<Vec<GhUserRecord>>, ModelError> {
+        use crate::schema::gh_user_records::dsl::*;
+        use diesel::prelude::*;
+
+        let user_records = gh_user_records
+            .order((login.asc(), id.asc()))
+            .load::<GhUserRecord>(conn)?;
+
+        let mut collisions: Vec<Vec<GhUserRecord>> = Vec::new();
+        let mut group: Vec<GhUserRecord> = Vec::new();
+
+        for user_record in user_records {
+            if group.first().map(|u| &u.login) != Some(&user_record.login) {
+                if group.len() > 1 {
+                    collisions.push(group);
+                }
+                group = Vec::new();
+            }
+            group.push(user_record);
+        }
+
+        if group.len() > 1 {
+            collisions.push(group);
+        }
+
+        Ok(collisions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GhUserRecord;
+    use crate::db::get_test_pool;
+    use crate::models::{
+        fixtures::{upload, user},
+        Attachment, Jam, JamEntry,
+    };
+
+    #[test]
+    fn test_purge() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let purged = user(&conn, 1);
+        user(&conn, 2);
+
+        let jam = Jam::create(&conn).unwrap();
+        let mut jam_entry = JamEntry::create(&conn, jam.id, 1).unwrap();
+        let summary = upload(&conn, 1, "summary");
+        jam_entry.summary_attachment_id = Some(summary.id);
+        jam_entry.update(&conn).unwrap();
+
+        // never used, and shares its file with someone else's
+        let unused = upload(&conn, 1, "unused");
+        let kept = upload(&conn, 2, "unused");
+
+        // used by something the user didn't make
+        let mut jam = Jam::find_by_id(&conn, jam.id).unwrap().unwrap();
+        let used = upload(&conn, 1, "used");
+        jam.summary_attachment_id = Some(used.id);
+        jam.update(&conn).unwrap();
+
+        let (orphans, unused_keys) = purged.purge(&conn).unwrap();
+        assert_eq!(
+            vec![summary.id, unused.id],
+            orphans.iter().map(|a| a.id).collect::<Vec<i32>>()
+        );
+        assert_eq!(vec![summary.storage_key()], unused_keys);
+
+        assert!(GhUserRecord::find_by_id(&conn, 1).unwrap().is_none());
+        assert!(JamEntry::find_by_id(&conn, jam_entry.id).unwrap().is_none());
+        assert!(Attachment::find_by_id(&conn, kept.id).unwrap().is_some());
+        let used = Attachment::find_by_id(&conn, used.id).unwrap().unwrap();
+        assert_eq!(None, used.uploader_user_id);
+        assert!(Attachment::find_by_uploader_user_id(&conn, 1)
+            .unwrap()
+            .is_empty());
     }
 }
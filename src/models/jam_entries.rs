@@ -0,0 +1,332 @@
+use crate::db::DbConn;
+use crate::models::{
+    check_rejection_reason, last_insert_rowid, ApprovalState, Attachment,
+    AttachmentOwner, Jam, JamEntryUpdate, ModelError, Release, RichText,
+};
+
+use super::r_to_opt;
+
+/// Models an entry into a game jam, which is what a participant is making for
+/// that jam and their goals for it.
+#[derive(Debug, Queryable)]
+pub struct JamEntry {
+    pub id: i32,
+    pub jam_id: i32,
+    pub submitter_user_id: i64,
+    pub approval_state: ApprovalState,
+    pub title: String,
+    pub slug: String,
+    pub summary: String,
+    pub summary_attachment_id: Option<i32>,
+    pub rich_text_id: i32,
+    pub rejection_reason: Option<String>,
+}
+
+impl JamEntry {
+    /// Creates a new draft entry in the given jam on behalf of the given user,
+    /// along with a blank rich text for its description.
+    pub fn create(
+        conn: &DbConn,
+        the_jam_id: i32,
+        the_submitter_user_id: i64,
+    ) -> Result<JamEntry, ModelError> {
+        use crate::schema::jam_entries::dsl::{
+            approval_state, id, jam_entries, jam_id, rich_text_id, slug,
+            submitter_user_id, summary, summary_attachment_id, title,
+        };
+        use diesel::prelude::*;
+
+        let jam_entry = conn.transaction::<JamEntry, ModelError, _>(|| {
+            let rich_text = RichText::create(conn)?;
+
+            diesel::insert_into(jam_entries)
+                .values((
+                    jam_id.eq(the_jam_id),
+                    submitter_user_id.eq(the_submitter_user_id),
+                    approval_state.eq(ApprovalState::Draft),
+                    title.eq("My Entry"),
+                    slug.eq("my-entry"),
+                    summary.eq(""),
+                    summary_attachment_id.eq::<Option<i32>>(None),
+                    rich_text_id.eq(rich_text.id),
+                ))
+                .execute(conn)?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(jam_entries
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<JamEntry>(conn)?)
+        })?;
+
+        Ok(jam_entry)
+    }
+
+    /// Finds a JamEntry by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        jam_entry_id: i32,
+    ) -> Result<Option<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        let jam_entry = jam_entries
+            .filter(id.eq(jam_entry_id))
+            .limit(1)
+            .first::<JamEntry>(conn);
+
+        r_to_opt(jam_entry)
+    }
+
+    /// Finds the JamEntry whose content is the given rich text, if any.
+    pub fn find_by_rich_text_id(
+        conn: &DbConn,
+        the_rich_text_id: i32,
+    ) -> Result<Option<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        let jam_entry = jam_entries
+            .filter(rich_text_id.eq(the_rich_text_id))
+            .limit(1)
+            .first::<JamEntry>(conn);
+
+        r_to_opt(jam_entry)
+    }
+
+    /// Finds all the entries in a given jam, ordered by title.
+    ///
+    /// * `approved_only` when `true` returns only entries which have the
+    ///   `approval_state` [`crate::models::ApprovalState::Approved`].
+    pub fn find_by_jam_id(
+        conn: &DbConn,
+        the_jam_id: i32,
+        approved_only: bool,
+    ) -> Result<Vec<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        let q = jam_entries.filter(jam_id.eq(the_jam_id)).order(title.asc());
+
+        let r = if approved_only {
+            q.filter(approval_state.eq(ApprovalState::Approved))
+                .load::<JamEntry>(conn)?
+        } else {
+            q.load::<JamEntry>(conn)?
+        };
+
+        Ok(r)
+    }
+
+    /// Finds every entry waiting on an admin, oldest first.
+    pub fn find_submitted(conn: &DbConn) -> Result<Vec<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        let r = jam_entries
+            .filter(approval_state.eq(ApprovalState::Submitted))
+            .order(id.asc())
+            .load::<JamEntry>(conn)?;
+
+        Ok(r)
+    }
+
+    /// Finds every entry a user has submitted to any jam, newest first.
+    pub fn find_by_submitter_user_id(
+        conn: &DbConn,
+        user_id: i64,
+    ) -> Result<Vec<JamEntry>, ModelError> {
+        use crate::schema::jam_entries::dsl::*;
+        use diesel::prelude::*;
+
+        let r = jam_entries
+            .filter(submitter_user_id.eq(user_id))
+            .order(id.desc())
+            .load::<JamEntry>(conn)?;
+
+        Ok(r)
+    }
+
+    /// Updates a JamEntry by making what's in the database look like what's
+    /// on the model. Its summary image comes to belong to it if it didn't
+    /// belong to anything yet.
+    pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entries::dsl::{
+            approval_state, jam_entries, jam_id, rejection_reason,
+            rich_text_id, slug, submitter_user_id, summary,
+            summary_attachment_id, title,
+        };
+        use diesel::prelude::*;
+
+        diesel::update(jam_entries.find(self.id))
+            .set((
+                jam_id.eq(self.jam_id),
+                submitter_user_id.eq(self.submitter_user_id),
+                approval_state.eq(self.approval_state),
+                title.eq(&self.title),
+                slug.eq(&self.slug),
+                summary.eq(&self.summary),
+                summary_attachment_id.eq(self.summary_attachment_id),
+                rich_text_id.eq(self.rich_text_id),
+                rejection_reason.eq(&self.rejection_reason),
+            ))
+            .execute(conn)?;
+
+        if let Some(mut attachment) = self.load_attachment(conn)? {
+            attachment.claim(conn, AttachmentOwner::JamEntry(self.id))?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the given user submitted this entry.
+    pub fn is_owned_by(&self, user_id: i64) -> bool {
+        self.submitter_user_id == user_id
+    }
+
+    /// Whether the participant may still change this entry. Once an entry has
+    /// been submitted for approval it is frozen, unless it is rejected and
+    /// needs fixing.
+    pub fn is_editable(&self) -> bool {
+        self.approval_state == ApprovalState::Draft
+            || self.approval_state == ApprovalState::Rejected
+    }
+
+    /// Submits a draft or rejected entry for approval.
+    pub fn submit(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        if !self.is_editable() {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Submitted,
+            ));
+        }
+
+        self.approval_state = ApprovalState::Submitted;
+        self.rejection_reason = None;
+        self.update(conn)
+    }
+
+    /// Approves a submitted entry, making it public.
+    pub fn approve(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        if self.approval_state != ApprovalState::Submitted {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Approved,
+            ));
+        }
+
+        self.approval_state = ApprovalState::Approved;
+        self.rejection_reason = None;
+        self.update(conn)
+    }
+
+    /// Rejects a submitted entry, recording why so its submitter can fix it.
+    pub fn reject(
+        &mut self,
+        conn: &DbConn,
+        reason: &str,
+    ) -> Result<(), ModelError> {
+        if self.approval_state != ApprovalState::Submitted {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Rejected,
+            ));
+        }
+
+        self.rejection_reason = Some(check_rejection_reason(reason)?);
+        self.approval_state = ApprovalState::Rejected;
+        self.update(conn)
+    }
+
+    /// Loads the jam this is an entry for. Since every entry belongs to a jam,
+    /// the absence of this is a schema violation and is returned as an
+    /// [`crate::models::ModelError::NotFound`].
+    pub fn load_jam(&self, conn: &DbConn) -> Result<Jam, ModelError> {
+        Jam::find_by_id(conn, self.jam_id)?.ok_or(ModelError::NotFound)
+    }
+
+    /// Deletes this entry along with its updates, releases and rich texts.
+    /// Attachments are left alone apart from no longer belonging to it, see
+    /// [`JamEntry::load_all_attachments`] for what they were.
+    pub fn delete(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entries::dsl::jam_entries;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            for jam_entry_update in
+                JamEntryUpdate::find_by_jam_entry_id(conn, self.id, false)?
+            {
+                jam_entry_update.delete(conn)?;
+            }
+            for release in Release::find_by_jam_entry_id(conn, self.id)? {
+                release.delete(conn)?;
+            }
+
+            Attachment::disown_all(conn, AttachmentOwner::JamEntry(self.id))?;
+            diesel::delete(jam_entries.find(self.id)).execute(conn)?;
+            self.load_rich_text(conn)?.delete(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Loads every attachment used by this entry: its summary image,
+    /// whatever is attached to its description or any of its updates, and
+    /// the builds and changelogs of its releases.
+    pub fn load_all_attachments(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Vec<Attachment>, ModelError> {
+        let mut attachments: Vec<Attachment> = Vec::new();
+
+        if let Some(attachment) = self.load_attachment(conn)? {
+            attachments.push(attachment);
+        }
+        attachments.extend(self.load_rich_text(conn)?.attachments(conn)?);
+
+        for jam_entry_update in
+            JamEntryUpdate::find_by_jam_entry_id(conn, self.id, false)?
+        {
+            if let Some(rich_text) = jam_entry_update.load_rich_text(conn)? {
+                attachments.extend(rich_text.attachments(conn)?);
+            }
+        }
+
+        for release in Release::find_by_jam_entry_id(conn, self.id)? {
+            attachments
+                .extend(release.load_rich_text(conn)?.attachments(conn)?);
+            for release_artifact in release.load_artifacts(conn)? {
+                attachments.extend(release_artifact.load_attachment(conn)?);
+            }
+        }
+
+        attachments.sort_by_key(|attachment| attachment.id);
+        attachments.dedup_by_key(|attachment| attachment.id);
+
+        Ok(attachments)
+    }
+
+    /// Loads the summary attachment, if present.
+    pub fn load_attachment(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<Attachment>, ModelError> {
+        match self.summary_attachment_id {
+            Some(id) => Ok(Attachment::find_by_id(conn, id)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads the rich text of this JamEntry. Since every entry has a rich
+    /// text, the absence of this is a schema violation and is returned as an
+    /// [`crate::models::ModelError::NotFound`].
+    pub fn load_rich_text(
+        &self,
+        conn: &DbConn,
+    ) -> Result<RichText, ModelError> {
+        RichText::find_by_id(conn, self.rich_text_id)?
+            .ok_or(ModelError::NotFound)
+    }
+}
@@ -0,0 +1,326 @@
+use crate::db::DbConn;
+use crate::models::{
+    check_rejection_reason, last_insert_rowid, ApprovalState, Attachment,
+    AttachmentOwner, JamEntry, ModelError, RichText,
+};
+use chrono::NaiveDateTime;
+
+use super::r_to_opt;
+
+/// Models an update on a jam entry, which is a devlog post describing some
+/// progress. An update either has rich text written here or links to content
+/// somewhere else, never both.
+#[derive(Debug, Queryable)]
+pub struct JamEntryUpdate {
+    pub id: i32,
+    pub jam_entry_id: i32,
+    pub title: String,
+    pub slug: String,
+    pub summary: String,
+    pub rich_text_id: Option<i32>,
+    pub external_content_url: Option<String>,
+    pub approval_state: ApprovalState,
+    pub created_at: NaiveDateTime,
+    pub rejection_reason: Option<String>,
+}
+
+impl JamEntryUpdate {
+    /// Creates a new draft update on the given entry, along with a blank rich
+    /// text for its content.
+    pub fn create_with_rich_text(
+        conn: &DbConn,
+        the_jam_entry_id: i32,
+    ) -> Result<JamEntryUpdate, ModelError> {
+        use diesel::prelude::*;
+
+        conn.transaction::<JamEntryUpdate, ModelError, _>(|| {
+            let rich_text = RichText::create(conn)?;
+            JamEntryUpdate::insert(
+                conn,
+                the_jam_entry_id,
+                Some(rich_text.id),
+                None,
+            )
+        })
+    }
+
+    /// Creates a new draft update on the given entry which links to content
+    /// elsewhere on the web.
+    pub fn create_with_external_content(
+        conn: &DbConn,
+        the_jam_entry_id: i32,
+        the_external_content_url: &str,
+    ) -> Result<JamEntryUpdate, ModelError> {
+        use diesel::prelude::*;
+
+        check_external_content_url(the_external_content_url)?;
+
+        conn.transaction::<JamEntryUpdate, ModelError, _>(|| {
+            JamEntryUpdate::insert(
+                conn,
+                the_jam_entry_id,
+                None,
+                Some(the_external_content_url),
+            )
+        })
+    }
+
+    /// Inserts a new draft update. Please only use this from within a
+    /// transaction, as it relies on `last_insert_rowid`.
+    fn insert(
+        conn: &DbConn,
+        the_jam_entry_id: i32,
+        the_rich_text_id: Option<i32>,
+        the_external_content_url: Option<&str>,
+    ) -> Result<JamEntryUpdate, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::{
+            approval_state, created_at, external_content_url, id, jam_entry_id,
+            jam_entry_updates, rich_text_id, slug, summary, title,
+        };
+        use diesel::prelude::*;
+
+        diesel::insert_into(jam_entry_updates)
+            .values((
+                jam_entry_id.eq(the_jam_entry_id),
+                title.eq("My Update"),
+                slug.eq("my-update"),
+                summary.eq(""),
+                rich_text_id.eq(the_rich_text_id),
+                external_content_url.eq(the_external_content_url),
+                approval_state.eq(ApprovalState::Draft),
+                created_at.eq(chrono::offset::Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+
+        let rowid =
+            diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+        Ok(jam_entry_updates
+            .filter(id.eq(rowid))
+            .limit(1)
+            .first::<JamEntryUpdate>(conn)?)
+    }
+
+    /// Finds a JamEntryUpdate by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        jam_entry_update_id: i32,
+    ) -> Result<Option<JamEntryUpdate>, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        let jam_entry_update = jam_entry_updates
+            .filter(id.eq(jam_entry_update_id))
+            .limit(1)
+            .first::<JamEntryUpdate>(conn);
+
+        r_to_opt(jam_entry_update)
+    }
+
+    /// Finds the JamEntryUpdate whose content is the given rich text, if any.
+    pub fn find_by_rich_text_id(
+        conn: &DbConn,
+        the_rich_text_id: i32,
+    ) -> Result<Option<JamEntryUpdate>, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        let jam_entry_update = jam_entry_updates
+            .filter(rich_text_id.eq(the_rich_text_id))
+            .limit(1)
+            .first::<JamEntryUpdate>(conn);
+
+        r_to_opt(jam_entry_update)
+    }
+
+    /// Finds all the updates on an entry, oldest first, so that they read
+    /// like a diary of the entry's development.
+    ///
+    /// * `approved_only` when `true` returns only updates which have the
+    ///   `approval_state` [`crate::models::ApprovalState::Approved`].
+    pub fn find_by_jam_entry_id(
+        conn: &DbConn,
+        the_jam_entry_id: i32,
+        approved_only: bool,
+    ) -> Result<Vec<JamEntryUpdate>, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        let q = jam_entry_updates
+            .filter(jam_entry_id.eq(the_jam_entry_id))
+            .order((created_at.asc(), id.asc()));
+
+        let r = if approved_only {
+            q.filter(approval_state.eq(ApprovalState::Approved))
+                .load::<JamEntryUpdate>(conn)?
+        } else {
+            q.load::<JamEntryUpdate>(conn)?
+        };
+
+        Ok(r)
+    }
+
+    /// Finds every update waiting on an admin, oldest first.
+    pub fn find_submitted(
+        conn: &DbConn,
+    ) -> Result<Vec<JamEntryUpdate>, ModelError> {
+        use crate::schema::jam_entry_updates::dsl::*;
+        use diesel::prelude::*;
+
+        let r = jam_entry_updates
+            .filter(approval_state.eq(ApprovalState::Submitted))
+            .order(id.asc())
+            .load::<JamEntryUpdate>(conn)?;
+
+        Ok(r)
+    }
+
+    /// Updates a JamEntryUpdate by making what's in the database look like
+    /// what's on the model, so long as the model still has exactly one kind of
+    /// content.
+    pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entry_updates::dsl::{
+            approval_state, external_content_url, jam_entry_id,
+            jam_entry_updates, rejection_reason, rich_text_id, slug, summary,
+            title,
+        };
+        use diesel::prelude::*;
+
+        if self.rich_text_id.is_some() == self.external_content_url.is_some() {
+            return Err(ModelError::InvalidJamEntryUpdateContent);
+        }
+
+        if let Some(url) = &self.external_content_url {
+            check_external_content_url(url)?;
+        }
+
+        diesel::update(jam_entry_updates.find(self.id))
+            .set((
+                jam_entry_id.eq(self.jam_entry_id),
+                title.eq(&self.title),
+                slug.eq(&self.slug),
+                summary.eq(&self.summary),
+                rich_text_id.eq(self.rich_text_id),
+                external_content_url.eq(&self.external_content_url),
+                approval_state.eq(self.approval_state),
+                rejection_reason.eq(&self.rejection_reason),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Whether the participant may still change this update. Once an update
+    /// has been submitted for approval it is frozen, unless it is rejected and
+    /// needs fixing.
+    pub fn is_editable(&self) -> bool {
+        self.approval_state == ApprovalState::Draft
+            || self.approval_state == ApprovalState::Rejected
+    }
+
+    /// Submits a draft or rejected update for approval.
+    pub fn submit(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        if !self.is_editable() {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Submitted,
+            ));
+        }
+
+        self.approval_state = ApprovalState::Submitted;
+        self.rejection_reason = None;
+        self.update(conn)
+    }
+
+    /// Approves a submitted update, making it public.
+    pub fn approve(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        if self.approval_state != ApprovalState::Submitted {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Approved,
+            ));
+        }
+
+        self.approval_state = ApprovalState::Approved;
+        self.rejection_reason = None;
+        self.update(conn)
+    }
+
+    /// Rejects a submitted update, recording why so its submitter can fix it.
+    pub fn reject(
+        &mut self,
+        conn: &DbConn,
+        reason: &str,
+    ) -> Result<(), ModelError> {
+        if self.approval_state != ApprovalState::Submitted {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Rejected,
+            ));
+        }
+
+        self.rejection_reason = Some(check_rejection_reason(reason)?);
+        self.approval_state = ApprovalState::Rejected;
+        self.update(conn)
+    }
+
+    /// Deletes this update and its rich text, if it has one. Attachments
+    /// which belonged to it are left alone, belonging to nothing.
+    pub fn delete(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::jam_entry_updates::dsl::jam_entry_updates;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            Attachment::disown_all(
+                conn,
+                AttachmentOwner::JamEntryUpdate(self.id),
+            )?;
+            diesel::delete(jam_entry_updates.find(self.id)).execute(conn)?;
+
+            if let Some(rich_text) = self.load_rich_text(conn)? {
+                rich_text.delete(conn)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Loads the entry this is an update on. Since every update belongs to an
+    /// entry, the absence of this is a schema violation and is returned as an
+    /// [`crate::models::ModelError::NotFound`].
+    pub fn load_jam_entry(
+        &self,
+        conn: &DbConn,
+    ) -> Result<JamEntry, ModelError> {
+        JamEntry::find_by_id(conn, self.jam_entry_id)?
+            .ok_or(ModelError::NotFound)
+    }
+
+    /// Loads the rich text of this update, if it has one rather than an
+    /// external link.
+    pub fn load_rich_text(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<RichText>, ModelError> {
+        match self.rich_text_id {
+            Some(id) => Ok(Some(
+                RichText::find_by_id(conn, id)?.ok_or(ModelError::NotFound)?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Links are shown to everyone who reads the feed, so only allow the kind that
+/// go to a web page.
+fn check_external_content_url(url: &str) -> Result<(), ModelError> {
+    let lowercase_url = url.trim().to_lowercase();
+
+    if lowercase_url.starts_with("https://")
+        || lowercase_url.starts_with("http://")
+    {
+        Ok(())
+    } else {
+        Err(ModelError::InvalidExternalContentUrl(url.to_string()))
+    }
+}
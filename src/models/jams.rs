@@ -1,8 +1,9 @@
 use crate::db::DbConn;
 use crate::models::{
-    last_insert_rowid, ApprovalState, Attachment, ModelError, RichText,
+    check_rejection_reason, last_insert_rowid, ApprovalState, Attachment,
+    AttachmentOwner, ModelError, RichText,
 };
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime};
 use diesel::debug_query;
 
 use super::r_to_opt;
@@ -19,10 +20,42 @@ pub struct Jam {
     pub start_date: NaiveDateTime,
     pub end_date: NaiveDateTime,
     pub approval_state: ApprovalState,
+    pub rejection_reason: Option<String>,
+}
+
+/// Everything needed to create a jam in one go, rather than starting from a
+/// placeholder and editing it.
+#[derive(Debug)]
+pub struct NewJam {
+    pub title: String,
+    pub slug: String,
+    pub summary: String,
+    pub rich_text_content: String,
+    pub start_date: NaiveDateTime,
+    pub end_date: NaiveDateTime,
 }
 
 impl Jam {
+    /// Creates a placeholder draft jam, to be filled in by editing it.
     pub fn create(conn: &DbConn) -> Result<Jam, ModelError> {
+        Jam::create_from(
+            conn,
+            &NewJam {
+                title: "My Jam".to_string(),
+                slug: "my-jam".to_string(),
+                summary: "My really cool game jam".to_string(),
+                rich_text_content: "".to_string(),
+                start_date: chrono::offset::Utc::now().naive_utc(),
+                end_date: chrono::offset::Utc::now().naive_utc(),
+            },
+        )
+    }
+
+    /// Creates a draft jam with the given details.
+    pub fn create_from(
+        conn: &DbConn,
+        new_jam: &NewJam,
+    ) -> Result<Jam, ModelError> {
         use crate::schema::jams::dsl::{
             approval_state, end_date, id, jams, rich_text_id, slug, start_date,
             summary, summary_attachment_id, title,
@@ -30,17 +63,19 @@ impl Jam {
         use diesel::prelude::*;
 
         let jam = conn.transaction::<Jam, ModelError, _>(|| {
-            let rich_text = RichText::create(conn)?;
+            let mut rich_text = RichText::create(conn)?;
+            rich_text.content = new_jam.rich_text_content.clone();
+            rich_text.update(conn)?;
 
             diesel::insert_into(jams)
                 .values((
-                    title.eq("My Jam"),
-                    slug.eq("my-jam"),
-                    summary.eq("My really cool game jam"),
+                    title.eq(&new_jam.title),
+                    slug.eq(&new_jam.slug),
+                    summary.eq(&new_jam.summary),
                     summary_attachment_id.eq::<Option<i32>>(None),
                     rich_text_id.eq(rich_text.id),
-                    start_date.eq(chrono::offset::Utc::now().naive_utc()),
-                    end_date.eq(chrono::offset::Utc::now().naive_utc()),
+                    start_date.eq(new_jam.start_date),
+                    end_date.eq(new_jam.end_date),
                     approval_state.eq(ApprovalState::Draft),
                 ))
                 .execute(conn)?;
@@ -67,6 +102,22 @@ impl Jam {
         r_to_opt(jam)
     }
 
+    /// Finds the Jam whose content is the given rich text, if any.
+    pub fn find_by_rich_text_id(
+        conn: &DbConn,
+        the_rich_text_id: i32,
+    ) -> Result<Option<Jam>, ModelError> {
+        use crate::schema::jams::dsl::*;
+        use diesel::prelude::*;
+
+        let jam = jams
+            .filter(rich_text_id.eq(the_rich_text_id))
+            .limit(1)
+            .first::<Jam>(conn);
+
+        r_to_opt(jam)
+    }
+
     /// Finds all Jams, paging them.
     ///
     /// * `approved_only` when `true` returns only [`crate::models::jams::Jam`]s
@@ -98,12 +149,49 @@ impl Jam {
         Ok(r)
     }
 
+    /// Finds every jam waiting on an admin, oldest first.
+    pub fn find_submitted(conn: &DbConn) -> Result<Vec<Jam>, ModelError> {
+        use crate::schema::jams::dsl::*;
+        use diesel::prelude::*;
+
+        let r = jams
+            .filter(approval_state.eq(ApprovalState::Submitted))
+            .order(id.asc())
+            .load::<Jam>(conn)?;
+
+        Ok(r)
+    }
+
+    /// Counts all Jams, for paging through them.
+    ///
+    /// * `approved_only` when `true` counts only [`crate::models::jams::Jam`]s
+    ///   which have the `approval_state`
+    ///   [`crate::models::ApprovalState::Approved`].
+    pub fn count(
+        conn: &DbConn,
+        approved_only: bool,
+    ) -> Result<i64, ModelError> {
+        use crate::schema::jams::dsl::*;
+        use diesel::prelude::*;
+
+        let r = if approved_only {
+            jams.filter(approval_state.eq(ApprovalState::Approved))
+                .count()
+                .get_result::<i64>(conn)?
+        } else {
+            jams.count().get_result::<i64>(conn)?
+        };
+
+        Ok(r)
+    }
+
     /// Updates a Jam by making what's in the database look like what's on the
-    /// model.
+    /// model. Its summary image comes to belong to it if it didn't belong to
+    /// anything yet.
     pub fn update(&self, conn: &DbConn) -> Result<(), ModelError> {
         use crate::schema::jams::dsl::{
-            approval_state, end_date, jams, rich_text_id, slug, start_date,
-            summary, summary_attachment_id, title,
+            approval_state, end_date, jams, rejection_reason, rich_text_id,
+            slug, start_date, summary, summary_attachment_id, title,
         };
         use diesel::prelude::*;
 
@@ -117,12 +205,76 @@ impl Jam {
                 start_date.eq(self.start_date),
                 end_date.eq(self.end_date),
                 approval_state.eq(self.approval_state),
+                rejection_reason.eq(&self.rejection_reason),
             ))
             .execute(conn)?;
 
+        if let Some(mut attachment) = self.load_attachment(conn)? {
+            attachment.claim(conn, AttachmentOwner::Jam(self.id))?;
+        }
+
         Ok(())
     }
 
+    /// Approves a submitted jam, making it public.
+    pub fn approve(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        if self.approval_state != ApprovalState::Submitted {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Approved,
+            ));
+        }
+
+        self.approval_state = ApprovalState::Approved;
+        self.rejection_reason = None;
+        self.update(conn)
+    }
+
+    /// Rejects a submitted jam, recording why so its submitter can fix it.
+    pub fn reject(
+        &mut self,
+        conn: &DbConn,
+        reason: &str,
+    ) -> Result<(), ModelError> {
+        if self.approval_state != ApprovalState::Submitted {
+            return Err(ModelError::InvalidApprovalStateTransition(
+                self.approval_state,
+                ApprovalState::Rejected,
+            ));
+        }
+
+        self.rejection_reason = Some(check_rejection_reason(reason)?);
+        self.approval_state = ApprovalState::Rejected;
+        self.update(conn)
+    }
+
+    /// Moves the jam straight to any approval state, skipping the usual trip
+    /// through submission. A reason is needed to reject it, and is cleared
+    /// otherwise.
+    pub fn set_approval_state(
+        &mut self,
+        conn: &DbConn,
+        state: ApprovalState,
+        reason: Option<&str>,
+    ) -> Result<(), ModelError> {
+        self.rejection_reason = match state {
+            ApprovalState::Rejected => {
+                Some(check_rejection_reason(reason.unwrap_or(""))?)
+            }
+            _ => None,
+        };
+        self.approval_state = state;
+        self.update(conn)
+    }
+
+    /// Whether participants may enter this jam at the given time. Head starts
+    /// are welcome, so a jam accepts entries as soon as it is approved and
+    /// until the end of its last day.
+    pub fn is_accepting_entries(&self, now: NaiveDateTime) -> bool {
+        self.approval_state == ApprovalState::Approved
+            && now < self.end_date + Duration::days(1)
+    }
+
     /// Loads the summary attachment, if present.
     pub fn load_attachment(
         &self,
@@ -4,14 +4,23 @@
 //! database directly from either command-line tool or controller code.
 
 mod attachments;
+mod blobs;
+#[cfg(test)]
+pub mod fixtures;
 mod gh_user_records;
+mod jam_entries;
+mod jam_entry_updates;
 mod jams;
 mod permissions;
+mod releases;
 mod rich_texts;
+mod web_builds;
 
 use crate::attachments::AttachmentStorageError;
 pub use crate::models::{
-    attachments::*, gh_user_records::*, jams::*, permissions::*, rich_texts::*,
+    attachments::*, blobs::*, gh_user_records::*, jam_entries::*,
+    jam_entry_updates::*, jams::*, permissions::*, releases::*, rich_texts::*,
+    web_builds::*,
 };
 use diesel::{
     backend::Backend, deserialize, r2d2::PoolError,
@@ -43,6 +52,40 @@ pub enum ModelError {
 
     #[error("Could not store attachment with error {0}")]
     AttachmentStorageError(#[from] AttachmentStorageError),
+
+    /// The entity cannot move from its current approval state to the one
+    /// requested, such as submitting something that was already approved.
+    #[error("Cannot move from approval state {0:?} to {1:?}")]
+    InvalidApprovalStateTransition(ApprovalState, ApprovalState),
+
+    /// A jam entry update must be either rich text or a link to somewhere
+    /// else, never both and never neither.
+    #[error("A jam entry update needs exactly one of rich text or a link")]
+    InvalidJamEntryUpdateContent,
+
+    /// Links to content elsewhere must be plain old web pages.
+    #[error("{0} is not an http or https url")]
+    InvalidExternalContentUrl(String),
+
+    /// Rejecting something needs a reason to show its submitter.
+    #[error("A rejection must give a reason")]
+    MissingRejectionReason,
+
+    /// A release needs a version to tell it apart from the others.
+    #[error("A release must have a version")]
+    MissingReleaseVersion,
+
+    /// No two releases of an entry may have the same version.
+    #[error("There is already a release {0} of this entry")]
+    DuplicateReleaseVersion(String),
+
+    /// More than one cached user has this login, so which one was meant can't
+    /// be known until the cache is synced with Github.
+    #[error(
+        "More than one user has the login {0} (ids {1:?}), run \
+        `udevgames user sync -u @{0}` and try again"
+    )]
+    AmbiguousLogin(String, Vec<i64>),
 }
 
 // Gets the most recently inserted row. Please only use this from within a
@@ -66,8 +109,42 @@ fn r_to_opt<T>(
     }
 }
 
+/// Turns a human title into something that can be used as the friendly part of
+/// a url, such as `My Cool Game!` into `my-cool-game`.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() {
+        "untitled".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Rejections have to say why, so that whoever submitted the thing knows what
+/// to fix before submitting it again.
+fn check_rejection_reason(reason: &str) -> Result<String, ModelError> {
+    let reason = reason.trim();
+
+    if reason.is_empty() {
+        Err(ModelError::MissingRejectionReason)
+    } else {
+        Ok(reason.to_string())
+    }
+}
+
 #[repr(i32)]
-#[derive(Debug, Clone, Copy, FromSqlRow, AsExpression)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromSqlRow, AsExpression)]
 #[sql_type = "Integer"]
 pub enum ApprovalState {
     Draft = 0,
@@ -134,3 +211,15 @@ where
         (*self as i32).to_sql(out)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::slugify;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!("my-cool-game", slugify("My Cool Game!"));
+        assert_eq!("a-b", slugify("  --a   b--  "));
+        assert_eq!("untitled", slugify("!!!"));
+    }
+}
@@ -2,6 +2,13 @@ use crate::{
     db::DbConn,
     models::{r_to_opt, ModelError},
 };
+use diesel::{
+    backend::Backend, deserialize, serialize, serialize::Output,
+    sql_types::Text, types::FromSql, types::ToSql,
+};
+use serde::Serialize;
+use std::{fmt, io::Write, str::FromStr};
+use thiserror::Error;
 
 /// Permissions sloppily model, well, permissions. A GhUserRecord may "have"
 /// zero or more permissions. Permissions are known by their name, which is
@@ -17,10 +24,126 @@ pub struct Permission {
     /// The user id who this permission is granted to.
     pub gh_user_id: i64,
 
-    /// The name of the permission granted.
+    /// The permission granted.
+    pub name: KnownPermission,
+}
+
+/// Every permission the site knows how to act on. These are stored by name,
+/// which is also how they are given on the command line and how templates
+/// check for them, as in `"admin" in auth.user.permissions`.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromSqlRow, AsExpression, Serialize,
+)]
+#[sql_type = "Text"]
+#[serde(rename_all = "lowercase")]
+pub enum KnownPermission {
+    /// Runs the site, approving and rejecting whatever is submitted to it.
+    Admin,
+    /// May look, but may not participate.
+    Banned,
+    /// Reserved for helping admins with moderation.
+    Moderator,
+    /// Reserved for judging jam entries.
+    Judge,
+    /// May upload beyond the attachment quotas, as can admins.
+    Unmetered,
+}
+
+#[derive(Debug, Error)]
+pub enum KnownPermissionParseError {
+    #[error(
+        "Unrecognized permission {0}, expected one of admin, banned, \
+        moderator, judge, or unmetered"
+    )]
+    UnrecognizedPermission(String),
+}
+
+impl KnownPermission {
+    /// The name this permission is stored and shown as.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KnownPermission::Admin => "admin",
+            KnownPermission::Banned => "banned",
+            KnownPermission::Moderator => "moderator",
+            KnownPermission::Judge => "judge",
+            KnownPermission::Unmetered => "unmetered",
+        }
+    }
+}
+
+impl FromStr for KnownPermission {
+    type Err = KnownPermissionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "admin" => KnownPermission::Admin,
+            "banned" => KnownPermission::Banned,
+            "moderator" => KnownPermission::Moderator,
+            "judge" => KnownPermission::Judge,
+            "unmetered" => KnownPermission::Unmetered,
+            _ => {
+                return Err(KnownPermissionParseError::UnrecognizedPermission(
+                    s.to_string(),
+                ))
+            }
+        })
+    }
+}
+
+impl fmt::Display for KnownPermission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<DB> FromSql<Text, DB> for KnownPermission
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        let name = String::from_sql(bytes)?;
+        Ok(name.parse()?)
+    }
+}
+
+impl<DB> ToSql<Text, DB> for KnownPermission
+where
+    DB: Backend,
+    str: ToSql<Text, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        self.as_str().to_sql(out)
+    }
+}
+
+/// A grant made before permission names were checked, whose name didn't match
+/// any [`KnownPermission`]. These are set aside by a migration rather than
+/// thrown away, so that an admin can decide what was meant.
+#[derive(Debug, Queryable)]
+pub struct UnrecognizedPermission {
+    pub id: i32,
+    pub gh_user_id: i64,
+    /// The name exactly as it was granted.
     pub name: String,
 }
 
+impl UnrecognizedPermission {
+    /// Finds every grant that was set aside.
+    pub fn find_all(
+        conn: &DbConn,
+    ) -> Result<Vec<UnrecognizedPermission>, ModelError> {
+        use crate::schema::unrecognized_permissions::dsl::*;
+        use diesel::prelude::*;
+
+        let perms = unrecognized_permissions
+            .order(id.asc())
+            .load::<UnrecognizedPermission>(conn)?;
+
+        Ok(perms)
+    }
+}
+
 impl Permission {
     /// Finds all permissions on a given user.
     pub fn find_by_gh_user_id(
@@ -41,7 +164,7 @@ impl Permission {
     /// this describes all users with a specific permission.
     pub fn find_by_name(
         conn: &DbConn,
-        permission_name: &str,
+        permission_name: KnownPermission,
     ) -> Result<Vec<Permission>, ModelError> {
         use crate::schema::permissions::dsl::*;
         use diesel::prelude::*;
@@ -57,7 +180,7 @@ impl Permission {
     pub fn grant_permission(
         conn: &DbConn,
         user_id: i64,
-        permission_name: &str,
+        permission_name: KnownPermission,
     ) -> Result<(), ModelError> {
         use crate::schema::permissions::dsl::*;
         use diesel::prelude::*;
@@ -66,7 +189,7 @@ impl Permission {
         let existing_permission = Permission::find_by_user_id_and_name(
             &conn,
             user_id,
-            &permission_name,
+            permission_name,
         )?;
 
         if existing_permission.is_some() {
@@ -85,7 +208,7 @@ impl Permission {
     pub fn revoke_permission(
         conn: &DbConn,
         user_id: i64,
-        permission_name: &str,
+        permission_name: KnownPermission,
     ) -> Result<usize, ModelError> {
         use crate::schema::permissions::dsl::*;
         use diesel::prelude::*;
@@ -104,7 +227,7 @@ impl Permission {
     pub fn find_by_user_id_and_name(
         conn: &DbConn,
         user_id: i64,
-        permission_name: &str,
+        permission_name: KnownPermission,
     ) -> Result<Option<Permission>, ModelError> {
         use crate::schema::permissions::dsl::*;
         use diesel::prelude::*;
@@ -118,3 +241,23 @@ impl Permission {
         r_to_opt(perm)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{KnownPermission, KnownPermissionParseError};
+
+    #[test]
+    fn test_known_permission_from_str() {
+        assert_eq!(KnownPermission::Admin, "admin".parse().unwrap());
+        assert_eq!(KnownPermission::Banned, " Banned ".parse().unwrap());
+        assert_eq!("judge", KnownPermission::Judge.to_string());
+        assert_eq!(KnownPermission::Unmetered, "unmetered".parse().unwrap());
+
+        match "admn".parse::<KnownPermission>() {
+            Err(KnownPermissionParseError::UnrecognizedPermission(name)) => {
+                assert_eq!("admn", name)
+            }
+            r => panic!("Expected admn to be unrecognized, got {:?}", r),
+        }
+    }
+}
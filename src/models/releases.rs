@@ -0,0 +1,506 @@
+use crate::{
+    db::DbConn,
+    models::{
+        last_insert_rowid, r_to_opt, Attachment, AttachmentOwner, ModelError,
+        RichText,
+    },
+};
+use chrono::NaiveDateTime;
+use diesel::{
+    backend::Backend, deserialize, serialize, serialize::Output,
+    sql_types::Text, types::FromSql, types::ToSql,
+};
+use serde::Serialize;
+use std::{fmt, io::Write, str::FromStr};
+use thiserror::Error;
+
+/// A version of a jam entry which can be downloaded, made up of a build for
+/// each platform it runs on along with a changelog.
+#[derive(Debug, Queryable)]
+pub struct Release {
+    pub id: i32,
+    pub jam_entry_id: i32,
+    /// The version, such as `1.0.1`, which is unique within an entry.
+    pub version: String,
+    /// The changelog, saying what changed since the last release.
+    pub rich_text_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+/// One build in a release, for a single platform and architecture.
+#[derive(Debug, Queryable)]
+pub struct ReleaseArtifact {
+    pub id: i32,
+    pub release_id: i32,
+    /// The build itself.
+    pub attachment_id: i32,
+    pub platform: Platform,
+    pub architecture: Architecture,
+}
+
+/// What a build runs on. These are stored by name, which is also how they are
+/// given in forms.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromSqlRow, AsExpression, Serialize,
+)]
+#[sql_type = "Text"]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    MacOs,
+    Windows,
+    Linux,
+    /// Played in the browser.
+    Web,
+}
+
+/// What processors a build runs on. These are stored by name, which is also
+/// how they are given in forms.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromSqlRow, AsExpression, Serialize,
+)]
+#[sql_type = "Text"]
+pub enum Architecture {
+    #[serde(rename = "x86_64")]
+    X86_64,
+    #[serde(rename = "arm64")]
+    Arm64,
+    /// Both of the above in one build, as with a macOS universal binary.
+    #[serde(rename = "universal")]
+    Universal,
+    /// Doesn't care, such as a web build or a script.
+    #[serde(rename = "any")]
+    Any,
+}
+
+#[derive(Debug, Error)]
+pub enum ReleaseParseError {
+    #[error(
+        "Unrecognized platform {0}, expected one of macos, windows, linux, \
+        or web"
+    )]
+    UnrecognizedPlatform(String),
+
+    #[error(
+        "Unrecognized architecture {0}, expected one of x86_64, arm64, \
+        universal, or any"
+    )]
+    UnrecognizedArchitecture(String),
+}
+
+impl Platform {
+    /// The name this platform is stored and given as.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::MacOs => "macos",
+            Platform::Windows => "windows",
+            Platform::Linux => "linux",
+            Platform::Web => "web",
+        }
+    }
+
+    /// Format the Platform as something we can show to humans on the
+    /// Internet.
+    pub fn to_human_str(&self) -> &'static str {
+        match self {
+            Platform::MacOs => "macOS",
+            Platform::Windows => "Windows",
+            Platform::Linux => "Linux",
+            Platform::Web => "Web",
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = ReleaseParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "macos" => Platform::MacOs,
+            "windows" => Platform::Windows,
+            "linux" => Platform::Linux,
+            "web" => Platform::Web,
+            _ => {
+                return Err(ReleaseParseError::UnrecognizedPlatform(
+                    s.to_string(),
+                ))
+            }
+        })
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<DB> FromSql<Text, DB> for Platform
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        let name = String::from_sql(bytes)?;
+        Ok(name.parse()?)
+    }
+}
+
+impl<DB> ToSql<Text, DB> for Platform
+where
+    DB: Backend,
+    str: ToSql<Text, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        self.as_str().to_sql(out)
+    }
+}
+
+impl Architecture {
+    /// The name this architecture is stored and given as.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Architecture::X86_64 => "x86_64",
+            Architecture::Arm64 => "arm64",
+            Architecture::Universal => "universal",
+            Architecture::Any => "any",
+        }
+    }
+}
+
+impl FromStr for Architecture {
+    type Err = ReleaseParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_lowercase().as_str() {
+            "x86_64" => Architecture::X86_64,
+            "arm64" => Architecture::Arm64,
+            "universal" => Architecture::Universal,
+            "any" => Architecture::Any,
+            _ => {
+                return Err(ReleaseParseError::UnrecognizedArchitecture(
+                    s.to_string(),
+                ))
+            }
+        })
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<DB> FromSql<Text, DB> for Architecture
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        let name = String::from_sql(bytes)?;
+        Ok(name.parse()?)
+    }
+}
+
+impl<DB> ToSql<Text, DB> for Architecture
+where
+    DB: Backend,
+    str: ToSql<Text, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut Output<W, DB>) -> serialize::Result {
+        self.as_str().to_sql(out)
+    }
+}
+
+impl Release {
+    /// Creates a new release of the given entry, along with a blank rich text
+    /// for its changelog.
+    pub fn create(
+        conn: &DbConn,
+        the_jam_entry_id: i32,
+        the_version: &str,
+    ) -> Result<Release, ModelError> {
+        use crate::schema::releases::dsl::{
+            created_at, id, jam_entry_id, releases, rich_text_id, version,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<Release, ModelError, _>(|| {
+            let the_version =
+                check_version(conn, the_jam_entry_id, None, the_version)?;
+            let rich_text = RichText::create(conn)?;
+
+            diesel::insert_into(releases)
+                .values((
+                    jam_entry_id.eq(the_jam_entry_id),
+                    version.eq(&the_version),
+                    rich_text_id.eq(rich_text.id),
+                    created_at.eq(chrono::offset::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(releases
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<Release>(conn)?)
+        })
+    }
+
+    /// Finds a Release by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        release_id: i32,
+    ) -> Result<Option<Release>, ModelError> {
+        use crate::schema::releases::dsl::{id, releases};
+        use diesel::prelude::*;
+
+        let release = releases
+            .filter(id.eq(release_id))
+            .limit(1)
+            .first::<Release>(conn);
+
+        r_to_opt(release)
+    }
+
+    /// Finds the Release whose changelog is the given rich text, if any.
+    pub fn find_by_rich_text_id(
+        conn: &DbConn,
+        the_rich_text_id: i32,
+    ) -> Result<Option<Release>, ModelError> {
+        use crate::schema::releases::dsl::{releases, rich_text_id};
+        use diesel::prelude::*;
+
+        let release = releases
+            .filter(rich_text_id.eq(the_rich_text_id))
+            .limit(1)
+            .first::<Release>(conn);
+
+        r_to_opt(release)
+    }
+
+    /// Finds every release of an entry, newest first.
+    pub fn find_by_jam_entry_id(
+        conn: &DbConn,
+        the_jam_entry_id: i32,
+    ) -> Result<Vec<Release>, ModelError> {
+        use crate::schema::releases::dsl::{
+            created_at, id, jam_entry_id, releases,
+        };
+        use diesel::prelude::*;
+
+        Ok(releases
+            .filter(jam_entry_id.eq(the_jam_entry_id))
+            .order((created_at.desc(), id.desc()))
+            .load::<Release>(conn)?)
+    }
+
+    /// Updates a Release by making what's in the database look like what's on
+    /// the model.
+    pub fn update(&mut self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::releases::dsl::{releases, version};
+        use diesel::prelude::*;
+
+        self.version = check_version(
+            conn,
+            self.jam_entry_id,
+            Some(self.id),
+            &self.version,
+        )?;
+
+        diesel::update(releases.find(self.id))
+            .set(version.eq(&self.version))
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes this release along with its artifacts and changelog. The
+    /// attachments which were its builds are left alone, as something else
+    /// may use them.
+    pub fn delete(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::releases::dsl::releases;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            for release_artifact in self.load_artifacts(conn)? {
+                release_artifact.delete(conn)?;
+            }
+            diesel::delete(releases.find(self.id)).execute(conn)?;
+            self.load_rich_text(conn)?.delete(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Loads the changelog of this release. Since every release has one, the
+    /// absence of this is a schema violation and is returned as an
+    /// [`crate::models::ModelError::NotFound`].
+    pub fn load_rich_text(
+        &self,
+        conn: &DbConn,
+    ) -> Result<RichText, ModelError> {
+        RichText::find_by_id(conn, self.rich_text_id)?
+            .ok_or(ModelError::NotFound)
+    }
+
+    /// Loads the builds in this release, ordered by platform the way they
+    /// were added.
+    pub fn load_artifacts(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Vec<ReleaseArtifact>, ModelError> {
+        use crate::schema::release_artifacts::dsl::{
+            id, release_artifacts, release_id,
+        };
+        use diesel::prelude::*;
+
+        Ok(release_artifacts
+            .filter(release_id.eq(self.id))
+            .order(id.asc())
+            .load::<ReleaseArtifact>(conn)?)
+    }
+}
+
+impl ReleaseArtifact {
+    /// Adds a build to a release. The attachment comes to belong to the
+    /// release's entry if it didn't belong to anything yet, so that it can be
+    /// downloaded once the entry is approved.
+    pub fn create(
+        conn: &DbConn,
+        release: &Release,
+        attachment: &mut Attachment,
+        the_platform: Platform,
+        the_architecture: Architecture,
+    ) -> Result<ReleaseArtifact, ModelError> {
+        use crate::schema::release_artifacts::dsl::{
+            architecture, attachment_id, id, platform, release_artifacts,
+            release_id,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<ReleaseArtifact, ModelError, _>(|| {
+            diesel::insert_into(release_artifacts)
+                .values((
+                    release_id.eq(release.id),
+                    attachment_id.eq(attachment.id),
+                    platform.eq(the_platform),
+                    architecture.eq(the_architecture),
+                ))
+                .execute(conn)?;
+            attachment
+                .claim(conn, AttachmentOwner::JamEntry(release.jam_entry_id))?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(release_artifacts
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<ReleaseArtifact>(conn)?)
+        })
+    }
+
+    /// Finds a ReleaseArtifact by its id.
+    pub fn find_by_id(
+        conn: &DbConn,
+        release_artifact_id: i32,
+    ) -> Result<Option<ReleaseArtifact>, ModelError> {
+        use crate::schema::release_artifacts::dsl::{id, release_artifacts};
+        use diesel::prelude::*;
+
+        let release_artifact = release_artifacts
+            .filter(id.eq(release_artifact_id))
+            .limit(1)
+            .first::<ReleaseArtifact>(conn);
+
+        r_to_opt(release_artifact)
+    }
+
+    /// Removes this build from its release, along with its record of being
+    /// extracted as a web build. The attachment itself is left alone, as are
+    /// the extracted files until nothing plays them.
+    pub fn delete(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::release_artifacts::dsl::release_artifacts;
+        use crate::schema::web_builds::dsl::{release_artifact_id, web_builds};
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            diesel::delete(web_builds.filter(release_artifact_id.eq(self.id)))
+                .execute(conn)?;
+            diesel::delete(release_artifacts.find(self.id)).execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Loads the build, unless its attachment has since been deleted.
+    pub fn load_attachment(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<Attachment>, ModelError> {
+        Attachment::find_by_id(conn, self.attachment_id)
+    }
+}
+
+/// Versions can't be blank, and no two releases of an entry can share one.
+/// The release being checked, if it already exists, doesn't count as sharing
+/// its own version.
+fn check_version(
+    conn: &DbConn,
+    the_jam_entry_id: i32,
+    the_release_id: Option<i32>,
+    the_version: &str,
+) -> Result<String, ModelError> {
+    use crate::schema::releases::dsl::{id, jam_entry_id, releases, version};
+    use diesel::prelude::*;
+
+    let the_version = the_version.trim();
+    if the_version.is_empty() {
+        return Err(ModelError::MissingReleaseVersion);
+    }
+
+    let existing = releases
+        .filter(jam_entry_id.eq(the_jam_entry_id))
+        .filter(version.eq(the_version))
+        .filter(id.ne(the_release_id.unwrap_or(-1)))
+        .count()
+        .get_result::<i64>(conn)?;
+    if existing > 0 {
+        return Err(ModelError::DuplicateReleaseVersion(
+            the_version.to_string(),
+        ));
+    }
+
+    Ok(the_version.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Architecture, Platform, ReleaseParseError};
+
+    #[test]
+    fn test_platform_from_str() {
+        assert_eq!(Platform::MacOs, "macos".parse().unwrap());
+        assert_eq!(Platform::Web, " Web ".parse().unwrap());
+        assert_eq!("windows", Platform::Windows.to_string());
+        assert_eq!("macOS", Platform::MacOs.to_human_str());
+
+        match "amiga".parse::<Platform>() {
+            Err(ReleaseParseError::UnrecognizedPlatform(name)) => {
+                assert_eq!("amiga", name)
+            }
+            r => panic!("Expected amiga to be unrecognized, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_architecture_from_str() {
+        assert_eq!(Architecture::X86_64, "x86_64".parse().unwrap());
+        assert_eq!(Architecture::Arm64, "ARM64".parse().unwrap());
+        assert_eq!("universal", Architecture::Universal.to_string());
+        assert!("ppc".parse::<Architecture>().is_err());
+    }
+}
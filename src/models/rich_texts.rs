@@ -1,12 +1,34 @@
 use crate::{
     db::DbConn,
-    models::{last_insert_rowid, ModelError},
+    models::{
+        last_insert_rowid, Attachment, AttachmentOwner, Jam, JamEntry,
+        JamEntryUpdate, ModelError, Release,
+    },
 };
+use ammonia::{Builder as SanitizerBuilder, UrlRelative};
 use diesel::result::Error as DieselError;
-use pulldown_cmark::{html, Options, Parser};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
 
 use super::r_to_opt;
 
+/// Where a rich text is shown, which decides how much HTML it is allowed to
+/// produce. Whatever isn't allowed is stripped after rendering, along with
+/// any script, event handler or url that isn't to a web page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RichTextPolicy {
+    /// The rules of a jam, written by admins and shown as a whole page.
+    JamDescription,
+    /// What participants write about their entries and in their updates,
+    /// which sits beneath the entry's own title.
+    JamEntry,
+    /// Short remarks, which are kept to text formatting and links.
+    Comment,
+}
+
 #[derive(Debug, Queryable)]
 pub struct RichText {
     pub id: i32,
@@ -64,18 +86,429 @@ impl RichText {
         Ok(())
     }
 
-    /// Renders the rich text's markdown to HTML.
-    pub fn render(&self) -> String {
-        let parser = Parser::new_ext(&self.content, Options::all());
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
-        html_output
+    /// What this rich text is the content of, which is what attachments
+    /// attached to it belong to.
+    pub fn owner(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Option<AttachmentOwner>, ModelError> {
+        if let Some(jam) = Jam::find_by_rich_text_id(conn, self.id)? {
+            return Ok(Some(AttachmentOwner::Jam(jam.id)));
+        }
+        if let Some(jam_entry) = JamEntry::find_by_rich_text_id(conn, self.id)?
+        {
+            return Ok(Some(AttachmentOwner::JamEntry(jam_entry.id)));
+        }
+        if let Some(jam_entry_update) =
+            JamEntryUpdate::find_by_rich_text_id(conn, self.id)?
+        {
+            return Ok(Some(AttachmentOwner::JamEntryUpdate(
+                jam_entry_update.id,
+            )));
+        }
+        // a changelog belongs to the entry it is a release of
+        if let Some(release) = Release::find_by_rich_text_id(conn, self.id)? {
+            return Ok(Some(AttachmentOwner::JamEntry(release.jam_entry_id)));
+        }
+
+        Ok(None)
+    }
+
+    /// Attaches an attachment to this rich text, so that it can be referred
+    /// to from the markdown as `attachment:<id>`, and makes it belong to
+    /// whatever this is the content of if it doesn't belong to anything yet.
+    /// Attaching the same attachment twice does nothing.
+    pub fn attach(
+        &self,
+        conn: &DbConn,
+        attachment: &mut Attachment,
+    ) -> Result<(), ModelError> {
+        use crate::schema::rich_text_attachments::dsl::{
+            attachment_id, rich_text_attachments, rich_text_id,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            let existing = rich_text_attachments
+                .filter(rich_text_id.eq(self.id))
+                .filter(attachment_id.eq(attachment.id))
+                .count()
+                .get_result::<i64>(conn)?;
+
+            if existing == 0 {
+                diesel::insert_into(rich_text_attachments)
+                    .values((
+                        rich_text_id.eq(self.id),
+                        attachment_id.eq(attachment.id),
+                    ))
+                    .execute(conn)?;
+            }
+
+            if let Some(owner) = self.owner(conn)? {
+                attachment.claim(conn, owner)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Finds every attachment on this rich text which hasn't been deleted, in
+    /// the order they were uploaded.
+    pub fn attachments(
+        &self,
+        conn: &DbConn,
+    ) -> Result<Vec<Attachment>, ModelError> {
+        use crate::schema::attachments::dsl::{attachments, deleted_at, id};
+        use crate::schema::rich_text_attachments::dsl::{
+            attachment_id, rich_text_attachments, rich_text_id,
+        };
+        use diesel::prelude::*;
+
+        let attachment_ids = rich_text_attachments
+            .filter(rich_text_id.eq(self.id))
+            .select(attachment_id)
+            .load::<i32>(conn)?;
+
+        Ok(attachments
+            .filter(id.eq_any(attachment_ids))
+            .filter(deleted_at.is_null())
+            .order(id.asc())
+            .load::<Attachment>(conn)?)
+    }
+
+    /// Detaches an attachment from this rich text. The attachment itself is
+    /// left alone, it just can't be referred to from here anymore.
+    pub fn detach(
+        &self,
+        conn: &DbConn,
+        the_attachment_id: i32,
+    ) -> Result<(), ModelError> {
+        use crate::schema::rich_text_attachments::dsl::{
+            attachment_id, rich_text_attachments, rich_text_id,
+        };
+        use diesel::prelude::*;
+
+        diesel::delete(
+            rich_text_attachments
+                .filter(rich_text_id.eq(self.id))
+                .filter(attachment_id.eq(the_attachment_id)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Deletes this rich text along with its attachment links. The
+    /// attachments themselves are left alone, as something else may use them.
+    pub fn delete(&self, conn: &DbConn) -> Result<(), ModelError> {
+        use crate::schema::rich_text_attachments::dsl::{
+            rich_text_attachments, rich_text_id,
+        };
+        use crate::schema::rich_texts::dsl::rich_texts;
+        use diesel::prelude::*;
+
+        conn.transaction::<(), ModelError, _>(|| {
+            diesel::delete(
+                rich_text_attachments.filter(rich_text_id.eq(self.id)),
+            )
+            .execute(conn)?;
+            diesel::delete(rich_texts.find(self.id)).execute(conn)?;
+
+            Ok(())
+        })
+    }
+
+    /// Renders the rich text's markdown to HTML that is safe to put on a page
+    /// under the given policy. Links and images pointing at `attachment:<id>`
+    /// are pointed at the real location of that attachment, so long as it is
+    /// attached to this rich text.
+    pub fn render(
+        &self,
+        conn: &DbConn,
+        policy: RichTextPolicy,
+    ) -> Result<String, ModelError> {
+        let html = render_markdown(&self.content, &self.attachments(conn)?);
+        Ok(sanitize_html(&html, policy))
+    }
+}
+
+/// Renders markdown to HTML, resolving `attachment:<id>` urls against the
+/// given attachments. References to anything else are left as they are.
+fn render_markdown(content: &str, attachments: &[Attachment]) -> String {
+    let parser =
+        Parser::new_ext(content, Options::all()).map(|event| match event {
+            Event::Start(Tag::Link(link_type, url, title)) => {
+                Event::Start(Tag::Link(
+                    link_type,
+                    resolve_attachment_url(url, attachments),
+                    title,
+                ))
+            }
+            Event::End(Tag::Link(link_type, url, title)) => {
+                Event::End(Tag::Link(
+                    link_type,
+                    resolve_attachment_url(url, attachments),
+                    title,
+                ))
+            }
+            Event::Start(Tag::Image(link_type, url, title)) => {
+                Event::Start(Tag::Image(
+                    link_type,
+                    resolve_attachment_url(url, attachments),
+                    title,
+                ))
+            }
+            Event::End(Tag::Image(link_type, url, title)) => {
+                Event::End(Tag::Image(
+                    link_type,
+                    resolve_attachment_url(url, attachments),
+                    title,
+                ))
+            }
+            _ => event,
+        });
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, parser);
+    html_output
+}
+
+/// Strips everything from rendered HTML which the policy doesn't allow. Markup
+/// that's simply not allowed is unwrapped, keeping its text, whereas scripts
+/// and styles are removed along with their contents.
+fn sanitize_html(html: &str, policy: RichTextPolicy) -> String {
+    let mut tags: HashSet<&str> = [
+        "a",
+        "blockquote",
+        "br",
+        "code",
+        "del",
+        "em",
+        "li",
+        "ol",
+        "p",
+        "pre",
+        "strong",
+        "ul",
+    ]
+    .iter()
+    .cloned()
+    .collect();
+    let mut tag_attributes: HashMap<&str, HashSet<&str>> = HashMap::new();
+    tag_attributes.insert("a", ["href", "title"].iter().cloned().collect());
+
+    if policy != RichTextPolicy::Comment {
+        tags.extend(
+            [
+                "h2", "h3", "h4", "h5", "h6", "hr", "img", "sub", "sup",
+                "table", "tbody", "td", "th", "thead", "tr",
+            ]
+            .iter(),
+        );
+        tag_attributes
+            .insert("img", ["src", "alt", "title"].iter().cloned().collect());
+        // table cells can be aligned, which is all they can do; see
+        // filter_attribute
+        tag_attributes
+            .insert("th", ["align", "style"].iter().cloned().collect());
+        tag_attributes
+            .insert("td", ["align", "style"].iter().cloned().collect());
+    }
+
+    // a jam description is the whole page, so it gets the top heading too
+    if policy == RichTextPolicy::JamDescription {
+        tags.insert("h1");
+    }
+
+    SanitizerBuilder::default()
+        .tags(tags)
+        .tag_attributes(tag_attributes)
+        .generic_attributes(HashSet::new())
+        .attribute_filter(filter_attribute)
+        .url_schemes(["http", "https", "mailto"].iter().cloned().collect())
+        .url_relative(UrlRelative::PassThrough)
+        .link_rel(Some("nofollow noopener noreferrer"))
+        .clean(html)
+        .to_string()
+}
+
+/// Only lets the alignment of table cells through out of their `align` and
+/// `style` attributes. Markdown tables come out with one or the other,
+/// depending on the version of pulldown-cmark, and a `style` which could say
+/// anything else would let a rich text restyle the page.
+fn filter_attribute<'u>(
+    _element: &str,
+    attribute: &str,
+    value: &'u str,
+) -> Option<Cow<'u, str>> {
+    let alignment = match attribute {
+        "align" => value,
+        "style" => value.strip_prefix("text-align: ")?,
+        _ => return Some(value.into()),
+    };
+
+    match alignment {
+        "left" | "center" | "right" => Some(value.into()),
+        _ => None,
+    }
+}
+
+/// Points `attachment:<id>` at where that attachment is served from, if it is
+/// one of the given attachments.
+fn resolve_attachment_url<'a>(
+    url: CowStr<'a>,
+    attachments: &[Attachment],
+) -> CowStr<'a> {
+    let attachment = url
+        .strip_prefix("attachment:")
+        .and_then(|attachment_id| attachment_id.parse::<i32>().ok())
+        .and_then(|attachment_id| {
+            attachments.iter().find(|a| a.id == attachment_id)
+        });
+
+    match attachment {
+        Some(attachment) => CowStr::from(attachment.url()),
+        None => url,
     }
 }
 
 #[derive(Debug, Queryable)]
 pub struct RichTextAttachment {
-    id: i32,
-    rich_text_id: i32,
-    attachment_id: i32,
+    pub id: i32,
+    pub rich_text_id: i32,
+    pub attachment_id: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_markdown, sanitize_html, RichTextPolicy};
+    use crate::models::fixtures::attachment;
+
+    fn render(content: &str, policy: RichTextPolicy) -> String {
+        sanitize_html(&render_markdown(content, &[]), policy)
+    }
+
+    #[test]
+    fn test_render_resolves_attachments() {
+        let attachments = vec![attachment(42, "shot.png", None)];
+
+        assert_eq!(
+            "<p><img src=\"/attachments/42/shot.png\" alt=\"a shot\" /></p>\n",
+            render_markdown("![a shot](attachment:42)", &attachments)
+        );
+        assert_eq!(
+            "<p><a href=\"/attachments/42/shot.png\">download</a></p>\n",
+            render_markdown("[download](attachment:42)", &attachments)
+        );
+        // attachments belonging to something else aren't resolved
+        assert_eq!(
+            "<p><img src=\"attachment:7\" alt=\"nope\" /></p>\n",
+            render_markdown("![nope](attachment:7)", &attachments)
+        );
+    }
+
+    #[test]
+    fn test_sanitize_removes_scripts() {
+        for policy in &[
+            RichTextPolicy::JamDescription,
+            RichTextPolicy::JamEntry,
+            RichTextPolicy::Comment,
+        ] {
+            let html = render("hi <script>alert(1)</script>", *policy);
+            assert!(!html.contains("script"), "{:?}: {}", policy, html);
+            assert!(!html.contains("alert"), "{:?}: {}", policy, html);
+
+            let html =
+                render("<SCRIPT SRC=//evil.example/x.js></SCRIPT>", *policy);
+            assert!(!html.to_lowercase().contains("script"), "{}", html);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_removes_event_handlers() {
+        let policy = RichTextPolicy::JamEntry;
+        let html = render("<img src=x onerror=alert(1)>", policy);
+        assert!(!html.contains("onerror"), "{}", html);
+
+        let html = render("<p onclick=\"alert(1)\">hi</p>", policy);
+        assert!(!html.contains("onclick"), "{}", html);
+
+        let html = render("<svg onload=alert(1)><circle/></svg>", policy);
+        assert!(!html.contains("svg"), "{}", html);
+        assert!(!html.contains("onload"), "{}", html);
+    }
+
+    #[test]
+    fn test_sanitize_removes_dangerous_urls() {
+        let policy = RichTextPolicy::JamEntry;
+        for content in &[
+            "[click](javascript:alert(1))",
+            "[click](JaVaScRiPt:alert(1))",
+            "<a href=\"javascript:alert(1)\">click</a>",
+            "<a href=\"&#106;avascript:alert(1)\">click</a>",
+            "[click](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+            "[click](vbscript:msgbox(1))",
+            "![x](javascript:alert(1))",
+        ] {
+            let html = render(content, policy);
+            assert!(!html.contains("href"), "{}: {}", content, html);
+            assert!(!html.contains("src"), "{}: {}", content, html);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_removes_embeds() {
+        let policy = RichTextPolicy::JamDescription;
+        for content in &[
+            "<iframe src=\"https://evil.example\"></iframe>",
+            "<object data=\"https://evil.example/x.swf\"></object>",
+            "<embed src=\"https://evil.example/x.swf\">",
+            "<style>body { display: none }</style>",
+            "<form action=\"https://evil.example\"><input name=x></form>",
+            "<meta http-equiv=\"refresh\" content=\"0;url=https://evil.example\">",
+        ] {
+            let html = render(content, policy);
+            assert!(!html.contains("evil"), "{}: {}", content, html);
+            assert!(!html.contains("display"), "{}: {}", content, html);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_keeps_markdown() {
+        let html = render(
+            "# Rules\n\n**bold** [site](https://example.com) ![a](/x.png)",
+            RichTextPolicy::JamDescription,
+        );
+        assert!(html.contains("<h1>Rules</h1>"), "{}", html);
+        assert!(html.contains("<strong>bold</strong>"), "{}", html);
+        assert!(html.contains("href=\"https://example.com\""), "{}", html);
+        assert!(html.contains("rel=\"nofollow noopener noreferrer\""));
+        assert!(html.contains("src=\"/x.png\""), "{}", html);
+
+        // entries sit under their own title, comments are just text
+        let html = render("# Big\n\n![a](/x.png)", RichTextPolicy::JamEntry);
+        assert!(!html.contains("<h1>"), "{}", html);
+        assert!(html.contains("<img"), "{}", html);
+        let html = render("# Big\n\n![a](/x.png)", RichTextPolicy::Comment);
+        assert!(!html.contains("<h1>"), "{}", html);
+        assert!(!html.contains("<img"), "{}", html);
+        assert!(html.contains("Big"), "{}", html);
+    }
+
+    #[test]
+    fn test_sanitize_keeps_only_table_alignment() {
+        let policy = RichTextPolicy::JamEntry;
+        let html = render("| a | b |\n|:-:|--:|\n| 1 | 2 |", policy);
+        assert!(html.contains("<th align=\"center\">a</th>"), "{}", html);
+        assert!(html.contains("<td align=\"right\">2</td>"), "{}", html);
+
+        let html = render(
+            "<table><tr><td style=\"text-align: right\">1</td>\
+            <td style=\"position: fixed\">2</td>\
+            <td align=\"justify\">3</td></tr></table>",
+            policy,
+        );
+        assert!(html.contains("<td style=\"text-align: right\">1</td>"));
+        assert!(html.contains("<td>2</td>"), "{}", html);
+        assert!(html.contains("<td>3</td>"), "{}", html);
+    }
 }
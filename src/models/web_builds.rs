@@ -0,0 +1,188 @@
+use crate::{
+    db::DbConn,
+    models::{last_insert_rowid, r_to_opt, ModelError, ReleaseArtifact},
+};
+use chrono::NaiveDateTime;
+
+/// A web build which has been extracted so that it can be played in the
+/// browser. The files themselves live in
+/// [`crate::web_builds::WebBuildStorage`].
+#[derive(Debug, Queryable)]
+pub struct WebBuild {
+    pub id: i32,
+    /// The artifact this was extracted from.
+    pub release_artifact_id: i32,
+    /// Where it was extracted to, named for the hex SHA-256 of the archive.
+    pub directory: String,
+    /// The page to play it from, relative to the directory.
+    pub entry_point: String,
+    /// The total size of the extracted files, in bytes.
+    pub size: i64,
+    pub created_at: NaiveDateTime,
+}
+
+impl WebBuild {
+    /// Records that an artifact's archive has been extracted.
+    pub fn create(
+        conn: &DbConn,
+        the_release_artifact_id: i32,
+        the_directory: &str,
+        the_entry_point: &str,
+        the_size: i64,
+    ) -> Result<WebBuild, ModelError> {
+        use crate::schema::web_builds::dsl::{
+            created_at, directory, entry_point, id, release_artifact_id, size,
+            web_builds,
+        };
+        use diesel::prelude::*;
+
+        conn.transaction::<WebBuild, ModelError, _>(|| {
+            diesel::insert_into(web_builds)
+                .values((
+                    release_artifact_id.eq(the_release_artifact_id),
+                    directory.eq(the_directory),
+                    entry_point.eq(the_entry_point),
+                    size.eq(the_size),
+                    created_at.eq(chrono::offset::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+
+            let rowid =
+                diesel::select(last_insert_rowid).get_result::<i32>(conn)?;
+
+            Ok(web_builds
+                .filter(id.eq(rowid))
+                .limit(1)
+                .first::<WebBuild>(conn)?)
+        })
+    }
+
+    /// Finds the WebBuild extracted from an artifact, if it was one.
+    pub fn find_by_release_artifact_id(
+        conn: &DbConn,
+        the_release_artifact_id: i32,
+    ) -> Result<Option<WebBuild>, ModelError> {
+        use crate::schema::web_builds::dsl::{release_artifact_id, web_builds};
+        use diesel::prelude::*;
+
+        let web_build = web_builds
+            .filter(release_artifact_id.eq(the_release_artifact_id))
+            .limit(1)
+            .first::<WebBuild>(conn);
+
+        r_to_opt(web_build)
+    }
+
+    /// Finds every WebBuild extracted to a directory. There is more than one
+    /// when the same archive was added to several releases.
+    pub fn find_by_directory(
+        conn: &DbConn,
+        the_directory: &str,
+    ) -> Result<Vec<WebBuild>, ModelError> {
+        use crate::schema::web_builds::dsl::{directory, id, web_builds};
+        use diesel::prelude::*;
+
+        Ok(web_builds
+            .filter(directory.eq(the_directory))
+            .order(id.asc())
+            .load::<WebBuild>(conn)?)
+    }
+
+    /// Lists the directory of every WebBuild, without repeats.
+    pub fn find_all_directories(
+        conn: &DbConn,
+    ) -> Result<Vec<String>, ModelError> {
+        use crate::schema::web_builds::dsl::{directory, web_builds};
+        use diesel::prelude::*;
+
+        Ok(web_builds
+            .select(directory)
+            .distinct()
+            .order(directory.asc())
+            .load::<String>(conn)?)
+    }
+
+    /// Whether this build can be played, which is whenever anyone at all may
+    /// download the attachment it was extracted from: it is published and
+    /// its entry is approved. The iframe playing it doesn't know who is
+    /// looking, so not even the entry's owner can play it before then.
+    pub fn is_playable(&self, conn: &DbConn) -> Result<bool, ModelError> {
+        let release_artifact = match ReleaseArtifact::find_by_id(
+            conn,
+            self.release_artifact_id,
+        )? {
+            Some(release_artifact) => release_artifact,
+            None => return Ok(false),
+        };
+
+        match release_artifact.load_attachment(conn)? {
+            Some(attachment) => attachment.is_visible_to(conn, None, false),
+            None => Ok(false),
+        }
+    }
+
+    /// The url the build is played from.
+    pub fn url(&self) -> String {
+        format!("/play/{}/{}", self.directory, self.entry_point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebBuild;
+    use crate::db::get_test_pool;
+    use crate::models::{
+        Architecture, Attachment, GhUserRecord, Jam, JamEntry, Platform,
+        Release, ReleaseArtifact,
+    };
+    use std::io::Write;
+
+    #[test]
+    fn test_is_playable() {
+        let pool = get_test_pool();
+        let conn = pool.get().unwrap();
+        let user =
+            GhUserRecord::find_and_update(&conn, 7, "someone", "", "").unwrap();
+        let jam = Jam::create(&conn).unwrap();
+        let mut jam_entry = JamEntry::create(&conn, jam.id, user.id).unwrap();
+        let release = Release::create(&conn, jam_entry.id, "1.0").unwrap();
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"not much of a game").unwrap();
+        let (mut attachment, _) = Attachment::create(
+            &conn,
+            file.path(),
+            "game.zip",
+            "application/zip",
+            user.id,
+        )
+        .unwrap();
+        let release_artifact = ReleaseArtifact::create(
+            &conn,
+            &release,
+            &mut attachment,
+            Platform::Web,
+            Architecture::Any,
+        )
+        .unwrap();
+        let web_build = WebBuild::create(
+            &conn,
+            release_artifact.id,
+            "ab",
+            "index.html",
+            18,
+        )
+        .unwrap();
+
+        // a draft, or one waiting on approval, isn't public yet
+        assert!(!web_build.is_playable(&conn).unwrap());
+        jam_entry.submit(&conn).unwrap();
+        assert!(!web_build.is_playable(&conn).unwrap());
+
+        jam_entry.approve(&conn).unwrap();
+        assert!(web_build.is_playable(&conn).unwrap());
+
+        attachment.set_published(&conn, false).unwrap();
+        assert!(!web_build.is_playable(&conn).unwrap());
+    }
+}
@@ -5,6 +5,25 @@ table! {
         published -> Bool,
         mime_type -> Text,
         md5 -> Binary,
+        sha256 -> Nullable<Binary>,
+        blob_id -> Nullable<Integer>,
+        uploader_user_id -> Nullable<BigInt>,
+        jam_id -> Nullable<Integer>,
+        jam_entry_id -> Nullable<Integer>,
+        jam_entry_update_id -> Nullable<Integer>,
+        size -> BigInt,
+        created_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    blobs (id) {
+        id -> Integer,
+        sha256 -> Binary,
+        md5 -> Binary,
+        size -> BigInt,
+        ref_count -> Integer,
     }
 }
 
@@ -20,13 +39,15 @@ table! {
 table! {
     jam_entries (id) {
         id -> Integer,
+        jam_id -> Integer,
         submitter_user_id -> BigInt,
         approval_state -> Integer,
         title -> Text,
         slug -> Text,
         summary -> Text,
-        summary_attachment_id -> Integer,
+        summary_attachment_id -> Nullable<Integer>,
         rich_text_id -> Integer,
+        rejection_reason -> Nullable<Text>,
     }
 }
 
@@ -40,6 +61,8 @@ table! {
         rich_text_id -> Nullable<Integer>,
         external_content_url -> Nullable<Text>,
         approval_state -> Integer,
+        created_at -> Timestamp,
+        rejection_reason -> Nullable<Text>,
     }
 }
 
@@ -54,6 +77,7 @@ table! {
         start_date -> Timestamp,
         end_date -> Timestamp,
         approval_state -> Integer,
+        rejection_reason -> Nullable<Text>,
     }
 }
 
@@ -65,6 +89,26 @@ table! {
     }
 }
 
+table! {
+    release_artifacts (id) {
+        id -> Integer,
+        release_id -> Integer,
+        attachment_id -> Integer,
+        platform -> Text,
+        architecture -> Text,
+    }
+}
+
+table! {
+    releases (id) {
+        id -> Integer,
+        jam_entry_id -> Integer,
+        version -> Text,
+        rich_text_id -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     rich_text_attachments (id) {
         id -> Integer,
@@ -80,13 +124,37 @@ table! {
     }
 }
 
+table! {
+    unrecognized_permissions (id) {
+        id -> Integer,
+        gh_user_id -> BigInt,
+        name -> Text,
+    }
+}
+
+table! {
+    web_builds (id) {
+        id -> Integer,
+        release_artifact_id -> Integer,
+        directory -> Text,
+        entry_point -> Text,
+        size -> BigInt,
+        created_at -> Timestamp,
+    }
+}
+
 allow_tables_to_appear_in_same_query!(
     attachments,
+    blobs,
     gh_user_records,
     jam_entries,
     jam_entry_updates,
     jams,
     permissions,
+    release_artifacts,
+    releases,
     rich_text_attachments,
     rich_texts,
+    unrecognized_permissions,
+    web_builds,
 );
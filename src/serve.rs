@@ -1,6 +1,8 @@
 use crate::{
-    attachments::AttachmentStorage, controllers::gh_oauth::GhCredentials,
+    attachments::AttachmentStorage,
+    controllers::{attachments::UploadLimits, gh_oauth::GhCredentials},
     db::DbPool,
+    web_builds::WebBuildStorage,
 };
 use rocket::{
     catchers, config::Config as RocketConfig, figment::Figment, routes,
@@ -20,6 +22,8 @@ pub async fn serve(
     db_pool: DbPool,
     gh_credentials: GhCredentials,
     attachment_storage: AttachmentStorage,
+    web_build_storage: WebBuildStorage,
+    upload_limits: UploadLimits,
 ) {
     let config = Figment::from(RocketConfig::default())
         .merge(("address", address))
@@ -32,6 +36,8 @@ pub async fn serve(
         .manage(crate::controllers::gh_oauth::gh_client())
         .manage(db_pool)
         .manage(attachment_storage)
+        .manage(web_build_storage)
+        .manage(upload_limits)
         .attach(Template::fairing())
         //        .attach(Compression::fairing())
         .attach(SpaceHelmet::default())
@@ -40,16 +46,46 @@ pub async fn serve(
             routes![
                 crate::controllers::homepage::homepage,
                 crate::controllers::attachments::get_attachment,
+                crate::controllers::attachments::upload_attachment,
+                crate::controllers::attachments::delete_attachment,
                 crate::controllers::gh_oauth::login_with_github,
                 crate::controllers::gh_oauth::gh_callback,
                 crate::controllers::gh_oauth::logout,
+                crate::controllers::jams::list_jams,
+                crate::controllers::jams::show_jam,
                 crate::controllers::jams::create_jam,
                 crate::controllers::jams::edit_jam,
                 crate::controllers::jams::update_jam,
+                crate::controllers::jam_entries::create_jam_entry,
+                crate::controllers::jam_entries::edit_jam_entry,
+                crate::controllers::jam_entries::update_jam_entry,
+                crate::controllers::jam_entries::submit_jam_entry,
+                crate::controllers::jam_entries::show_jam_entry,
+                crate::controllers::jam_entry_updates::create_jam_entry_update,
+                crate::controllers::jam_entry_updates::edit_jam_entry_update,
+                crate::controllers::jam_entry_updates::update_jam_entry_update,
+                crate::controllers::jam_entry_updates::submit_jam_entry_update,
+                crate::controllers::moderation::moderation,
+                crate::controllers::moderation::approve_jam,
+                crate::controllers::moderation::reject_jam,
+                crate::controllers::moderation::approve_jam_entry,
+                crate::controllers::moderation::reject_jam_entry,
+                crate::controllers::moderation::approve_jam_entry_update,
+                crate::controllers::moderation::reject_jam_entry_update,
+                crate::controllers::releases::create_release,
+                crate::controllers::releases::edit_release,
+                crate::controllers::releases::update_release,
+                crate::controllers::releases::delete_release,
+                crate::controllers::releases::create_release_artifact,
+                crate::controllers::releases::delete_release_artifact,
+                crate::controllers::rich_texts::upload_rich_text_attachment,
+                crate::controllers::rich_texts::detach_rich_text_attachment,
+                crate::controllers::web_builds::play_web_build,
             ],
         )
         .mount("/static", StaticFiles::from(crate_relative!("/static")))
         .register(catchers![
+            crate::error_handlers::bad_request,
             crate::error_handlers::not_found,
             crate::error_handlers::not_authorized,
             crate::error_handlers::forbidden,
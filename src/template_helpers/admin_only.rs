@@ -1,5 +1,5 @@
 use crate::{
-    models::{GhUserRecord, ModelError},
+    models::{GhUserRecord, KnownPermission, ModelError},
     template_helpers::TemplateContextUser,
 };
 use rocket::{
@@ -10,7 +10,7 @@ use serde::Serialize;
 use std::num::ParseIntError;
 use thiserror::Error;
 
-use super::{auth_from_request, AuthFromRequestError};
+use super::{auth_from_request, AuthFromRequestError, Refusal};
 
 /// Request guard for which there must be a logged in user with the admin role.
 /// This is for pages which cannot be accessed except by an admin.
@@ -18,7 +18,7 @@ pub struct AdminOnly {
     /// The admin user.
     user: GhUserRecord,
     /// The permissions of the admin user.
-    permissions: Vec<String>,
+    permissions: Vec<KnownPermission>,
 }
 
 /// This is the context that goes to the template itself. This should always be
@@ -30,6 +30,11 @@ pub struct AdminOnlyContext {
 }
 
 impl AdminOnly {
+    /// The id of the admin user.
+    pub fn user_id(&self) -> i64 {
+        self.user.id
+    }
+
     /// Produces a serializable context that can be passed to a template.
     pub fn to_context(&self) -> AdminOnlyContext {
         AdminOnlyContext {
@@ -49,6 +54,9 @@ pub enum AdminOnlyError {
     #[error("The user is not an admin")]
     NotAdmin,
 
+    #[error("The user is banned")]
+    Banned,
+
     #[error("No user is logged in")]
     NotLoggedIn,
 
@@ -69,7 +77,14 @@ impl<'a, 'r> FromRequest<'a, 'r> for AdminOnly {
     async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
         match auth_from_request(req) {
             Ok(Some((user, permissions))) => {
-                if permissions.contains(&"admin".to_string()) {
+                if permissions.contains(&KnownPermission::Banned) {
+                    // a banned admin is a contradiction, but banned wins
+                    Refusal::banned(req);
+                    Outcome::Failure((
+                        Status::Forbidden,
+                        AdminOnlyError::Banned,
+                    ))
+                } else if permissions.contains(&KnownPermission::Admin) {
                     Outcome::Success(AdminOnly { user, permissions })
                 } else {
                     Outcome::Failure((
@@ -1,4 +1,7 @@
-use crate::models::Attachment;
+use crate::{
+    attachments::{is_resizable, VARIANT_WIDTHS},
+    models::Attachment,
+};
 use hex::encode as hex_encode;
 use serde::Serialize;
 
@@ -10,8 +13,13 @@ pub struct AttachmentContext {
     name: String,
     published: bool,
     mime_type: String,
+    is_image: bool,
     md5: String,
+    sha256: Option<String>,
     url: String,
+    /// Resized WebP variants of the image for an `<img srcset>`, when it is
+    /// one which can be resized.
+    srcset: Option<String>,
 }
 
 impl AttachmentContext {
@@ -22,8 +30,28 @@ impl AttachmentContext {
             name: attachment.name.clone(),
             published: attachment.published.clone(),
             mime_type: attachment.mime_type.clone(),
+            is_image: attachment.mime_type.starts_with("image/"),
             md5: hex_encode(&attachment.md5),
+            sha256: attachment.sha256.as_ref().map(hex_encode),
             url: attachment.url(),
+            srcset: srcset(attachment),
         }
     }
 }
+
+/// Lists a variant of the attachment at every width, such as
+/// `/attachments/1/a.png?w=320 320w, /attachments/1/a.png?w=640 640w`.
+fn srcset(attachment: &Attachment) -> Option<String> {
+    if !is_resizable(&attachment.mime_type) {
+        return None;
+    }
+
+    let url = attachment.url();
+    let srcset = VARIANT_WIDTHS
+        .iter()
+        .map(|width| format!("{}?w={} {}w", url, width, width))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    Some(srcset)
+}
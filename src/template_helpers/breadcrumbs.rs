@@ -10,6 +10,28 @@ pub struct Breadcrumbs(Vec<Breadcrumb>);
 pub enum Breadcrumb {
     /// Will link to the homepage.
     Home,
+
+    /// Will link to the listing of every jam.
+    Jams,
+
+    /// Will link to a jam's public page.
+    Jam {
+        id: i32,
+        slug: String,
+        title: String,
+    },
+
+    /// Will link to a jam entry's public page.
+    JamEntry {
+        jam_id: i32,
+        jam_slug: String,
+        id: i32,
+        slug: String,
+        title: String,
+    },
+
+    /// Will link to the admin moderation queue.
+    Moderation,
 }
 
 /// Unwraps the concept of a breadcrumb from a higher-level abstraction into a
@@ -53,6 +75,23 @@ impl Breadcrumb {
     fn to_breadcrumb_context(&self) -> BreadcrumbContext {
         match self {
             Breadcrumb::Home => BreadcrumbContext::new("Home", "/"),
+            Breadcrumb::Jams => BreadcrumbContext::new("Jams", "/jams"),
+            Breadcrumb::Moderation => {
+                BreadcrumbContext::new("Moderation", "/moderation")
+            }
+            Breadcrumb::Jam { id, slug, title } => {
+                BreadcrumbContext::new(title, &format!("/jams/{}/{}", id, slug))
+            }
+            Breadcrumb::JamEntry {
+                jam_id,
+                jam_slug,
+                id,
+                slug,
+                title,
+            } => BreadcrumbContext::new(
+                title,
+                &format!("/jams/{}/{}/{}/{}", jam_id, jam_slug, id, slug),
+            ),
         }
     }
 }
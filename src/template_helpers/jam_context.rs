@@ -1,5 +1,5 @@
 use crate::db::DbConn;
-use crate::models::{Jam, ModelError};
+use crate::models::{Jam, ModelError, RichTextPolicy};
 use crate::template_helpers::attachment_context::AttachmentContext;
 use serde::Serialize;
 
@@ -11,11 +11,15 @@ pub struct JamContext {
     slug: String,
     summary: String,
     summary_attachment: Option<AttachmentContext>,
+    rich_text_id: i32,
     rich_text_content: String,
     rendered_rich_text_content: Option<String>,
+    rich_text_attachments: Vec<AttachmentContext>,
     start_date: String,
     end_date: String,
     approval_state: String,
+    rejection_reason: Option<String>,
+    accepting_entries: bool,
 }
 
 impl JamContext {
@@ -30,10 +34,15 @@ impl JamContext {
         let attachment = jam.load_attachment(conn)?;
         let rich_text = jam.load_rich_text(conn)?;
         let rendered_rich_text_content = if render_markdown {
-            Some(rich_text.render())
+            Some(rich_text.render(conn, RichTextPolicy::JamDescription)?)
         } else {
             None
         };
+        let rich_text_attachments = rich_text
+            .attachments(conn)?
+            .iter()
+            .map(AttachmentContext::from_model)
+            .collect();
 
         Ok(JamContext {
             id: jam.id,
@@ -42,11 +51,16 @@ impl JamContext {
             summary: jam.summary.clone(),
             summary_attachment: attachment
                 .map(|a| AttachmentContext::from_model(&a)),
+            rich_text_id: rich_text.id,
             rich_text_content: rich_text.content.clone(),
             rendered_rich_text_content: rendered_rich_text_content,
+            rich_text_attachments,
             start_date: jam.start_date.format("%Y-%m-%d").to_string(),
             end_date: jam.end_date.format("%Y-%m-%d").to_string(),
             approval_state: jam.approval_state.to_human_str(),
+            rejection_reason: jam.rejection_reason.clone(),
+            accepting_entries: jam
+                .is_accepting_entries(chrono::Utc::now().naive_utc()),
         })
     }
 }
@@ -0,0 +1,64 @@
+use crate::db::DbConn;
+use crate::models::{JamEntry, ModelError, RichTextPolicy};
+use crate::template_helpers::attachment_context::AttachmentContext;
+use serde::Serialize;
+
+/// Describes what a [`crate::models::jam_entries::JamEntry`] is to a Tera
+/// Template context.
+#[derive(Debug, Serialize)]
+pub struct JamEntryContext {
+    id: i32,
+    jam_id: i32,
+    submitter_user_id: i64,
+    title: String,
+    slug: String,
+    summary: String,
+    summary_attachment: Option<AttachmentContext>,
+    rich_text_id: i32,
+    rich_text_content: String,
+    rendered_rich_text_content: Option<String>,
+    rich_text_attachments: Vec<AttachmentContext>,
+    approval_state: String,
+    rejection_reason: Option<String>,
+}
+
+impl JamEntryContext {
+    /// Populates a [`JamEntryContext`] from a database model, pulling other
+    /// related values from the database at `conn`. Optionally renders
+    /// Markdown, so it can be omitted if not used in the page.
+    pub fn from_model(
+        conn: &DbConn,
+        jam_entry: &JamEntry,
+        render_markdown: bool,
+    ) -> Result<Self, ModelError> {
+        let attachment = jam_entry.load_attachment(conn)?;
+        let rich_text = jam_entry.load_rich_text(conn)?;
+        let rendered_rich_text_content = if render_markdown {
+            Some(rich_text.render(conn, RichTextPolicy::JamEntry)?)
+        } else {
+            None
+        };
+        let rich_text_attachments = rich_text
+            .attachments(conn)?
+            .iter()
+            .map(AttachmentContext::from_model)
+            .collect();
+
+        Ok(JamEntryContext {
+            id: jam_entry.id,
+            jam_id: jam_entry.jam_id,
+            submitter_user_id: jam_entry.submitter_user_id,
+            title: jam_entry.title.clone(),
+            slug: jam_entry.slug.clone(),
+            summary: jam_entry.summary.clone(),
+            summary_attachment: attachment
+                .map(|a| AttachmentContext::from_model(&a)),
+            rich_text_id: rich_text.id,
+            rich_text_content: rich_text.content.clone(),
+            rendered_rich_text_content,
+            rich_text_attachments,
+            approval_state: jam_entry.approval_state.to_human_str(),
+            rejection_reason: jam_entry.rejection_reason.clone(),
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use crate::db::DbConn;
+use crate::models::{JamEntryUpdate, ModelError, RichTextPolicy};
+use crate::template_helpers::attachment_context::AttachmentContext;
+use serde::Serialize;
+
+/// Describes what a [`crate::models::jam_entry_updates::JamEntryUpdate`] is to
+/// a Tera Template context. Exactly one of `rich_text_content` and
+/// `external_content_url` is present.
+#[derive(Debug, Serialize)]
+pub struct JamEntryUpdateContext {
+    id: i32,
+    jam_entry_id: i32,
+    title: String,
+    slug: String,
+    summary: String,
+    rich_text_id: Option<i32>,
+    rich_text_content: Option<String>,
+    rendered_rich_text_content: Option<String>,
+    rich_text_attachments: Vec<AttachmentContext>,
+    external_content_url: Option<String>,
+    approval_state: String,
+    rejection_reason: Option<String>,
+    created_at: String,
+}
+
+impl JamEntryUpdateContext {
+    /// Populates a [`JamEntryUpdateContext`] from a database model, pulling
+    /// other related values from the database at `conn`. Optionally renders
+    /// Markdown, so it can be omitted if not used in the page.
+    pub fn from_model(
+        conn: &DbConn,
+        jam_entry_update: &JamEntryUpdate,
+        render_markdown: bool,
+    ) -> Result<Self, ModelError> {
+        let rich_text = jam_entry_update.load_rich_text(conn)?;
+        let rendered_rich_text_content = match &rich_text {
+            Some(rich_text) if render_markdown => {
+                Some(rich_text.render(conn, RichTextPolicy::JamEntry)?)
+            }
+            _ => None,
+        };
+        let rich_text_attachments = match &rich_text {
+            Some(rich_text) => rich_text
+                .attachments(conn)?
+                .iter()
+                .map(AttachmentContext::from_model)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(JamEntryUpdateContext {
+            id: jam_entry_update.id,
+            jam_entry_id: jam_entry_update.jam_entry_id,
+            title: jam_entry_update.title.clone(),
+            slug: jam_entry_update.slug.clone(),
+            summary: jam_entry_update.summary.clone(),
+            rich_text_id: jam_entry_update.rich_text_id,
+            rich_text_content: rich_text.map(|r| r.content),
+            rendered_rich_text_content,
+            rich_text_attachments,
+            external_content_url: jam_entry_update.external_content_url.clone(),
+            approval_state: jam_entry_update.approval_state.to_human_str(),
+            rejection_reason: jam_entry_update.rejection_reason.clone(),
+            created_at: jam_entry_update
+                .created_at
+                .format("%Y-%m-%d")
+                .to_string(),
+        })
+    }
+}
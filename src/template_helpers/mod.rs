@@ -2,6 +2,11 @@ mod admin_only;
 mod attachment_context;
 mod breadcrumbs;
 mod jam_context;
+mod jam_entry_context;
+mod jam_entry_update_context;
+mod pagination;
+mod participant_only;
+mod release_context;
 mod user_optional;
 
 use std::num::ParseIntError;
@@ -11,11 +16,12 @@ use serde::Serialize;
 
 pub use crate::template_helpers::{
     admin_only::*, attachment_context::*, breadcrumbs::*, jam_context::*,
-    user_optional::*,
+    jam_entry_context::*, jam_entry_update_context::*, pagination::*,
+    participant_only::*, release_context::*, user_optional::*,
 };
 use crate::{
     db::DbPool,
-    models::{GhUserRecord, ModelError, Permission},
+    models::{GhUserRecord, KnownPermission, ModelError, Permission},
 };
 use thiserror::Error;
 
@@ -34,7 +40,7 @@ struct TemplateContextUser {
     avatar_url: String,
 
     /// List of the user's permissions.
-    permissions: Vec<String>,
+    permissions: Vec<KnownPermission>,
 }
 
 #[derive(Debug, Error)]
@@ -51,7 +57,8 @@ pub enum AuthFromRequestError {
 
 fn auth_from_request<'a, 'r>(
     req: &'a Request<'r>,
-) -> Result<Option<(GhUserRecord, Vec<String>)>, AuthFromRequestError> {
+) -> Result<Option<(GhUserRecord, Vec<KnownPermission>)>, AuthFromRequestError>
+{
     // unwrap is okay here, if there's no pool then the entire application
     // bootstrap was wrong
     let pool = req.managed_state::<DbPool>().unwrap();
@@ -77,7 +84,7 @@ fn auth_from_request<'a, 'r>(
 
             let permissions = Permission::find_by_gh_user_id(&conn, uid)?
                 .iter()
-                .map(|p| p.name.clone())
+                .map(|p| p.name)
                 .collect();
 
             return Ok(Some((user, permissions)));
@@ -0,0 +1,78 @@
+use serde::Serialize;
+
+/// Describes where a paged listing is to a Tera template context. Pages are
+/// counted from 1 here, as that is what humans see in the url, even though the
+/// models count them from 0.
+#[derive(Debug, Serialize)]
+pub struct PaginationContext {
+    /// The current page.
+    page: i64,
+
+    /// How many pages there are in total. There is always at least one, even
+    /// if it is empty.
+    page_count: i64,
+
+    /// How many items there are across all pages.
+    total: i64,
+
+    /// The previous page, if there is one.
+    previous_page: Option<i64>,
+
+    /// The next page, if there is one.
+    next_page: Option<i64>,
+}
+
+impl PaginationContext {
+    /// Builds pagination for the human-facing `page` of a listing with `total`
+    /// items, shown `page_size` at a time.
+    pub fn new(page: i64, page_size: i64, total: i64) -> Self {
+        let page_count = PaginationContext::page_count(page_size, total);
+
+        PaginationContext {
+            page,
+            page_count,
+            total,
+            previous_page: if page > 1 { Some(page - 1) } else { None },
+            next_page: if page < page_count {
+                Some(page + 1)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// How many pages a listing with `total` items takes up, shown
+    /// `page_size` at a time. There is always at least one.
+    pub fn page_count(page_size: i64, total: i64) -> i64 {
+        ((total + page_size - 1) / page_size).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PaginationContext;
+
+    #[test]
+    fn test_pagination_bounds() {
+        let empty = PaginationContext::new(1, 10, 0);
+        assert_eq!(1, empty.page_count);
+        assert_eq!(None, empty.previous_page);
+        assert_eq!(None, empty.next_page);
+
+        let middle = PaginationContext::new(2, 10, 21);
+        assert_eq!(3, middle.page_count);
+        assert_eq!(Some(1), middle.previous_page);
+        assert_eq!(Some(3), middle.next_page);
+
+        let last = PaginationContext::new(3, 10, 30);
+        assert_eq!(3, last.page_count);
+        assert_eq!(None, last.next_page);
+    }
+
+    #[test]
+    fn test_page_count() {
+        assert_eq!(1, PaginationContext::page_count(10, 0));
+        assert_eq!(1, PaginationContext::page_count(10, 10));
+        assert_eq!(2, PaginationContext::page_count(10, 11));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    models::{GhUserRecord, KnownPermission, ModelError},
+    template_helpers::TemplateContextUser,
+};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+};
+use serde::Serialize;
+use std::num::ParseIntError;
+use thiserror::Error;
+
+use super::{auth_from_request, AuthFromRequestError};
+
+/// Request guard for which there must be a logged in user who has not been
+/// banned. This is for anything that changes the site, such as entering a jam
+/// or uploading a file.
+pub struct ParticipantOnly {
+    /// The participating user.
+    user: GhUserRecord,
+    /// The permissions of the participating user.
+    permissions: Vec<KnownPermission>,
+}
+
+/// This is the context that goes to the template itself. This should always be
+/// in the `auth` field of a template context.
+#[derive(Debug, Serialize)]
+pub struct ParticipantOnlyContext {
+    /// The user.
+    user: TemplateContextUser,
+}
+
+/// Why a guard turned a request away, remembered in the request's local cache
+/// so that the error catcher can explain it rather than showing a bare 403.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    /// Nothing has been refused, or at least nothing worth explaining.
+    Unexplained,
+    /// The user has the banned permission.
+    Banned,
+}
+
+impl Refusal {
+    /// The refusal recorded on this request, if any.
+    pub fn of(req: &Request<'_>) -> Refusal {
+        *req.local_cache(|| Refusal::Unexplained)
+    }
+
+    /// Records that this request was refused because the user is banned.
+    pub(super) fn banned(req: &Request<'_>) {
+        req.local_cache(|| Refusal::Banned);
+    }
+}
+
+impl ParticipantOnly {
+    /// The id of the participating user.
+    pub fn user_id(&self) -> i64 {
+        self.user.id
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.permissions.contains(&KnownPermission::Admin)
+    }
+
+    /// Whether the participant may upload beyond the attachment quotas.
+    pub fn is_unmetered(&self) -> bool {
+        self.is_admin()
+            || self.permissions.contains(&KnownPermission::Unmetered)
+    }
+
+    /// Produces a serializable context that can be passed to a template.
+    pub fn to_context(&self) -> ParticipantOnlyContext {
+        ParticipantOnlyContext {
+            user: TemplateContextUser {
+                id: self.user.id,
+                login: self.user.login.clone(),
+                html_url: self.user.html_url.clone(),
+                avatar_url: self.user.avatar_url.clone(),
+                permissions: self.permissions.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParticipantOnlyError {
+    #[error("The user is banned")]
+    Banned,
+
+    #[error("No user is logged in")]
+    NotLoggedIn,
+
+    #[error("Could not get a connection from the pool with error {0}")]
+    DbPoolError(#[from] diesel::r2d2::PoolError),
+
+    #[error("Could not parse uid from cookie with error {0}")]
+    UserIdDecodeError(#[from] ParseIntError),
+
+    #[error("Could not query the database with error {0}")]
+    DbQueryError(#[from] ModelError),
+}
+
+#[rocket::async_trait]
+impl<'a, 'r> FromRequest<'a, 'r> for ParticipantOnly {
+    type Error = ParticipantOnlyError;
+
+    async fn from_request(req: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match auth_from_request(req) {
+            Ok(Some((user, permissions))) => {
+                if permissions.contains(&KnownPermission::Banned) {
+                    Refusal::banned(req);
+                    Outcome::Failure((
+                        Status::Forbidden,
+                        ParticipantOnlyError::Banned,
+                    ))
+                } else {
+                    Outcome::Success(ParticipantOnly { user, permissions })
+                }
+            }
+            Ok(None) => Outcome::Failure((
+                Status::Unauthorized,
+                ParticipantOnlyError::NotLoggedIn,
+            )),
+            Err(e) => match e {
+                AuthFromRequestError::DbPoolError(e) => Outcome::Failure((
+                    Status::InternalServerError,
+                    ParticipantOnlyError::DbPoolError(e),
+                )),
+                AuthFromRequestError::UserIdDecodeError(e) => {
+                    Outcome::Failure((
+                        Status::BadRequest,
+                        ParticipantOnlyError::UserIdDecodeError(e),
+                    ))
+                }
+                AuthFromRequestError::DbQueryError(e) => Outcome::Failure((
+                    Status::BadRequest,
+                    ParticipantOnlyError::DbQueryError(e),
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParticipantOnly;
+    use crate::models::{GhUserRecord, KnownPermission};
+
+    fn participant(permissions: Vec<KnownPermission>) -> ParticipantOnly {
+        ParticipantOnly {
+            user: GhUserRecord {
+                id: 1,
+                login: "ed".to_string(),
+                avatar_url: "".to_string(),
+                html_url: "".to_string(),
+            },
+            permissions,
+        }
+    }
+
+    #[test]
+    fn test_is_unmetered() {
+        assert!(!participant(vec![]).is_unmetered());
+        assert!(!participant(vec![KnownPermission::Judge]).is_unmetered());
+        assert!(participant(vec![KnownPermission::Unmetered]).is_unmetered());
+        assert!(participant(vec![KnownPermission::Admin]).is_unmetered());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::db::DbConn;
+use crate::models::{
+    ModelError, Release, ReleaseArtifact, RichTextPolicy, WebBuild,
+};
+use crate::template_helpers::attachment_context::AttachmentContext;
+use serde::Serialize;
+
+/// Describes what a [`crate::models::releases::Release`] is to a Tera Template
+/// context.
+#[derive(Debug, Serialize)]
+pub struct ReleaseContext {
+    id: i32,
+    jam_entry_id: i32,
+    version: String,
+    rich_text_id: i32,
+    rich_text_content: String,
+    rendered_rich_text_content: Option<String>,
+    rich_text_attachments: Vec<AttachmentContext>,
+    artifacts: Vec<ReleaseArtifactContext>,
+    created_at: String,
+}
+
+/// Describes what a [`crate::models::releases::ReleaseArtifact`] is to a Tera
+/// Template context. Builds whose attachment has been deleted are left out of
+/// their release's context entirely.
+#[derive(Debug, Serialize)]
+pub struct ReleaseArtifactContext {
+    id: i32,
+    platform: String,
+    platform_name: String,
+    architecture: String,
+    attachment: AttachmentContext,
+    /// Where the build can be played in the browser, when it is a web build
+    /// which was extracted and anyone may play it.
+    play_url: Option<String>,
+}
+
+impl ReleaseContext {
+    /// Populates a [`ReleaseContext`] from a database model, pulling other
+    /// related values from the database at `conn`. Optionally renders
+    /// Markdown, so it can be omitted if not used in the page.
+    pub fn from_model(
+        conn: &DbConn,
+        release: &Release,
+        render_markdown: bool,
+    ) -> Result<Self, ModelError> {
+        let rich_text = release.load_rich_text(conn)?;
+        let rendered_rich_text_content = if render_markdown {
+            Some(rich_text.render(conn, RichTextPolicy::JamEntry)?)
+        } else {
+            None
+        };
+        let rich_text_attachments = rich_text
+            .attachments(conn)?
+            .iter()
+            .map(AttachmentContext::from_model)
+            .collect();
+
+        let mut artifacts = Vec::new();
+        for release_artifact in release.load_artifacts(conn)? {
+            if let Some(context) =
+                ReleaseArtifactContext::from_model(conn, &release_artifact)?
+            {
+                artifacts.push(context);
+            }
+        }
+
+        Ok(ReleaseContext {
+            id: release.id,
+            jam_entry_id: release.jam_entry_id,
+            version: release.version.clone(),
+            rich_text_id: release.rich_text_id,
+            rich_text_content: rich_text.content,
+            rendered_rich_text_content,
+            rich_text_attachments,
+            artifacts,
+            created_at: release.created_at.format("%Y-%m-%d").to_string(),
+        })
+    }
+
+    /// Where the first of this release's builds which can be played in the
+    /// browser is played from, if any can.
+    pub fn play_url(&self) -> Option<&str> {
+        self.artifacts
+            .iter()
+            .find_map(|artifact| artifact.play_url.as_deref())
+    }
+}
+
+impl ReleaseArtifactContext {
+    /// Populates a [`ReleaseArtifactContext`] from a database model, unless
+    /// the build's attachment no longer exists.
+    pub fn from_model(
+        conn: &DbConn,
+        release_artifact: &ReleaseArtifact,
+    ) -> Result<Option<Self>, ModelError> {
+        let attachment = match release_artifact.load_attachment(conn)? {
+            Some(attachment) => attachment,
+            None => return Ok(None),
+        };
+
+        let play_url = match WebBuild::find_by_release_artifact_id(
+            conn,
+            release_artifact.id,
+        )? {
+            Some(web_build) if web_build.is_playable(conn)? => {
+                Some(web_build.url())
+            }
+            _ => None,
+        };
+
+        Ok(Some(ReleaseArtifactContext {
+            id: release_artifact.id,
+            platform: release_artifact.platform.to_string(),
+            platform_name: release_artifact.platform.to_human_str().to_string(),
+            architecture: release_artifact.architecture.to_string(),
+            attachment: AttachmentContext::from_model(&attachment),
+            play_url,
+        }))
+    }
+}
@@ -1,6 +1,9 @@
 //! This request guard is also a template helper because it provides the user
 //! and permissions to a template context.
-use crate::{models::GhUserRecord, template_helpers::TemplateContextUser};
+use crate::{
+    models::{GhUserRecord, KnownPermission},
+    template_helpers::TemplateContextUser,
+};
 use rocket::{
     http::Status,
     request::{FromRequest, Outcome, Request},
@@ -17,7 +20,7 @@ pub struct UserOptional {
     user: Option<GhUserRecord>,
 
     /// The permissions the current user has, if any.
-    permissions: Vec<String>,
+    permissions: Vec<KnownPermission>,
 }
 
 /// This is the context that goes to the template itself. To check for the
@@ -30,12 +33,17 @@ pub struct UserOptionalContext {
 }
 
 impl UserOptional {
+    /// The id of the current user, if someone is logged in.
+    pub fn user_id(&self) -> Option<i64> {
+        self.user.as_ref().map(|u| u.id)
+    }
+
     pub fn is_banned(&self) -> bool {
-        self.permissions.contains(&"banned".to_string())
+        self.permissions.contains(&KnownPermission::Banned)
     }
 
     pub fn is_admin(&self) -> bool {
-        self.permissions.contains(&"admin".to_string())
+        self.permissions.contains(&KnownPermission::Admin)
     }
 
     /// Produces a serializable context that can be passed to a template.
@@ -101,7 +109,7 @@ mod tests {
                 login: "ed".to_string(),
                 html_url: "".to_string(),
                 avatar_url: "".to_string(),
-                permissions: vec!["admin".to_string()],
+                permissions: vec![KnownPermission::Admin],
             }),
         };
         let mut tera = Tera::default();
@@ -0,0 +1,506 @@
+//! Extracts and serves web builds, which are zip archives of a game built for
+//! the browser. Each archive is unpacked into a directory of its own, named
+//! for the SHA-256 of the archive, so that its files can be served one at a
+//! time to an iframe on the entry page. Archives come from participants, so
+//! nothing in one is trusted: names which would land outside the directory,
+//! links, and archives which expand to far more than they look like are all
+//! refused before anything is written.
+
+use std::{
+    fs::{create_dir_all, read_dir, remove_dir_all, rename, File, OpenOptions},
+    io::{copy, Error as IOError, Read},
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+/// More files than any reasonable game, but few enough that an archive of
+/// empty files can't exhaust the disk's inodes.
+const MAX_WEB_BUILD_FILES: usize = 10_000;
+
+/// How many times larger than its compressed size a file may be once
+/// extracted. Game assets are nowhere near this, whereas zip bombs are far
+/// beyond it.
+const MAX_COMPRESSION_RATIO: u64 = 200;
+
+/// Files smaller than this are too small to be a bomb, however well they
+/// compress.
+const COMPRESSION_RATIO_THRESHOLD: u64 = 1024 * 1024;
+
+/// The page a build is played from, which has to be in the archive.
+const ENTRY_POINT_NAME: &str = "index.html";
+
+/// Finder and Explorer both put these in archives they make, and nothing ever
+/// loads them.
+const IGNORED_DIR_NAMES: [&str; 1] = ["__MACOSX"];
+
+#[derive(Debug, Error)]
+pub enum WebBuildError {
+    #[error("IOError {0}")]
+    IoError(#[from] IOError),
+
+    #[error("Could not read the archive with error {0}")]
+    ZipError(#[from] ZipError),
+
+    #[error("The archive has a file which would be outside it, {0}")]
+    UnsafePath(String),
+
+    #[error("The archive has a link, {0}, which aren't allowed")]
+    Link(String),
+
+    #[error("The archive has {0} files, more than the {1} allowed")]
+    TooManyFiles(usize, usize),
+
+    #[error("The archive expands to more than the {0} bytes allowed")]
+    TooLarge(u64),
+
+    #[error("The archive has a file, {0}, which expands suspiciously far")]
+    SuspiciousCompression(String),
+
+    #[error("The archive has no index.html to play it from")]
+    MissingEntryPoint,
+}
+
+impl WebBuildError {
+    /// Whether the archive itself is at fault, rather than us, which is the
+    /// participant's mistake to fix.
+    pub fn is_invalid_archive(&self) -> bool {
+        !matches!(self, WebBuildError::IoError(_))
+    }
+}
+
+/// What came out of an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedWebBuild {
+    /// The page to play the build from, relative to its directory and always
+    /// separated by `/`.
+    pub entry_point: String,
+
+    /// The total size of every file extracted, in bytes.
+    pub size: u64,
+}
+
+/// Hides where extracted web builds are kept and how they get there.
+#[derive(Debug, Clone)]
+pub struct WebBuildStorage {
+    /// Where builds are extracted to, one directory each.
+    storage_path: PathBuf,
+    /// The most an archive may expand to, in bytes.
+    max_size: u64,
+}
+
+impl WebBuildStorage {
+    /// Creates a new web build storage managing the given local directory,
+    /// which is created if it doesn't exist yet.
+    pub fn new(storage_path: PathBuf, max_size: u64) -> WebBuildStorage {
+        WebBuildStorage {
+            storage_path,
+            max_size,
+        }
+    }
+
+    /// Extracts the archive into the named directory, replacing anything
+    /// already there. Everything is unpacked into a temporary directory
+    /// first, so that a bad archive never leaves a half-extracted build
+    /// behind.
+    pub fn extract(
+        &self,
+        archive: &Path,
+        directory: &str,
+    ) -> Result<ExtractedWebBuild, WebBuildError> {
+        if !is_directory_name(directory) {
+            return Err(WebBuildError::UnsafePath(directory.to_string()));
+        }
+
+        create_dir_all(&self.storage_path)?;
+        let temp_dir = tempfile::Builder::new()
+            .prefix(".extract")
+            .tempdir_in(&self.storage_path)?;
+
+        let extracted = extract_archive(
+            File::open(archive)?,
+            temp_dir.path(),
+            self.max_size,
+        )?;
+
+        let destination = self.storage_path.join(directory);
+        if destination.exists() {
+            remove_dir_all(&destination)?;
+        }
+        // the temporary directory is only kept once it has been moved into
+        // place, so that a failed rename doesn't leave it behind
+        rename(temp_dir.path(), &destination)?;
+        temp_dir.into_path();
+
+        Ok(extracted)
+    }
+
+    /// Finds the file at the given path within a build, so long as it is a
+    /// file which was extracted there.
+    pub fn file(&self, directory: &str, path: &Path) -> Option<PathBuf> {
+        if !is_directory_name(directory) || enclosed_path(path).is_none() {
+            return None;
+        }
+
+        let file = self.storage_path.join(directory).join(path);
+        match file.symlink_metadata() {
+            Ok(metadata) if metadata.is_file() => Some(file),
+            _ => None,
+        }
+    }
+
+    /// Removes the named directory. A directory which is already gone is not
+    /// an error.
+    pub fn delete(&self, directory: &str) -> Result<(), WebBuildError> {
+        if !is_directory_name(directory) {
+            return Err(WebBuildError::UnsafePath(directory.to_string()));
+        }
+
+        match remove_dir_all(self.storage_path.join(directory)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            r => Ok(r?),
+        }
+    }
+
+    /// Lists every directory builds have been extracted to.
+    pub fn directories(&self) -> Result<Vec<String>, WebBuildError> {
+        let mut directories = Vec::new();
+        let entries = match read_dir(&self.storage_path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(directories)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if is_directory_name(name) {
+                    directories.push(name.to_string());
+                }
+            }
+        }
+
+        directories.sort();
+        Ok(directories)
+    }
+}
+
+/// Builds are extracted into directories named for the hex SHA-256 of their
+/// archive, which is also what they're served under.
+fn is_directory_name(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Turns the name of a file in an archive into a path relative to where the
+/// archive is being extracted, unless it would end up anywhere else.
+/// Absolute paths, drive letters, `..` and Windows separators are all
+/// refused rather than cleaned up, since no honest archive has them.
+fn enclosed_path(name: &Path) -> Option<PathBuf> {
+    let string = name.to_str()?;
+    if string.is_empty() || string.contains('\\') || string.contains('\0') {
+        return None;
+    }
+
+    let mut path = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => {
+                if part.to_str()?.contains(':') {
+                    return None;
+                }
+                path.push(part);
+            }
+            Component::CurDir => (),
+            Component::ParentDir
+            | Component::RootDir
+            | Component::Prefix(_) => return None,
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// Whether a zip entry's unix mode says it is a symbolic link.
+fn is_symlink(unix_mode: Option<u32>) -> bool {
+    const S_IFMT: u32 = 0o170000;
+    const S_IFLNK: u32 = 0o120000;
+
+    unix_mode.map_or(false, |mode| mode & S_IFMT == S_IFLNK)
+}
+
+/// Unpacks every file in the archive under the destination. The sizes an
+/// archive claims for its files are checked up front, but since those can
+/// lie, how much is actually written is counted as well.
+fn extract_archive<R: Read + std::io::Seek>(
+    reader: R,
+    destination: &Path,
+    max_size: u64,
+) -> Result<ExtractedWebBuild, WebBuildError> {
+    let mut archive = ZipArchive::new(reader)?;
+    if archive.len() > MAX_WEB_BUILD_FILES {
+        return Err(WebBuildError::TooManyFiles(
+            archive.len(),
+            MAX_WEB_BUILD_FILES,
+        ));
+    }
+
+    // check everything before writing anything
+    let mut paths = Vec::with_capacity(archive.len());
+    let mut claimed_size: u64 = 0;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        let path = match enclosed_path(Path::new(file.name())) {
+            Some(path) => path,
+            None => return Err(WebBuildError::UnsafePath(file.name().into())),
+        };
+        if is_symlink(file.unix_mode()) {
+            return Err(WebBuildError::Link(file.name().to_string()));
+        }
+        if file.size() > COMPRESSION_RATIO_THRESHOLD
+            && file.size() / file.compressed_size().max(1)
+                > MAX_COMPRESSION_RATIO
+        {
+            return Err(WebBuildError::SuspiciousCompression(
+                file.name().to_string(),
+            ));
+        }
+
+        claimed_size = claimed_size.saturating_add(file.size());
+        if claimed_size > max_size {
+            return Err(WebBuildError::TooLarge(max_size));
+        }
+
+        let ignored = path.components().next().map_or(false, |first| {
+            IGNORED_DIR_NAMES
+                .iter()
+                .any(|name| first.as_os_str() == *name)
+        });
+        paths.push(match ignored {
+            true => None,
+            false => Some((path, file.is_dir())),
+        });
+    }
+
+    let mut size: u64 = 0;
+    let mut entry_point: Option<PathBuf> = None;
+    for (i, path) in paths.into_iter().enumerate() {
+        let (path, is_dir) = match path {
+            Some(path) => path,
+            None => continue,
+        };
+        let target = destination.join(&path);
+
+        if is_dir {
+            create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent)?;
+        }
+
+        // create_new refuses to follow or replace anything already there,
+        // such as a second copy of the same name
+        let mut output = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&target)?;
+        let file = archive.by_index(i)?;
+        let claimed = file.size();
+        let written =
+            copy(&mut file.take(claimed.saturating_add(1)), &mut output)?;
+        if written > claimed {
+            return Err(WebBuildError::SuspiciousCompression(
+                path.to_string_lossy().into_owned(),
+            ));
+        }
+
+        size += written;
+        if size > max_size {
+            return Err(WebBuildError::TooLarge(max_size));
+        }
+
+        // the shallowest index.html is the one to play, which allows for
+        // archives of a folder as well as of the files in it
+        if path
+            .file_name()
+            .map_or(false, |name| name == ENTRY_POINT_NAME)
+            && entry_point.as_ref().map_or(true, |entry_point| {
+                path.components().count() < entry_point.components().count()
+            })
+        {
+            entry_point = Some(path);
+        }
+    }
+
+    let entry_point = entry_point.ok_or(WebBuildError::MissingEntryPoint)?;
+    let entry_point = entry_point
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Ok(ExtractedWebBuild { entry_point, size })
+}
+
+/// The MIME type to serve a file in a build as, going by its extension, and
+/// the encoding it was compressed with, if any. Unity compresses its builds
+/// ahead of time and expects them to be served as, for instance, WebAssembly
+/// encoded with gzip.
+pub fn content_type_of(path: &Path) -> (&'static str, Option<&'static str>) {
+    let extension = |path: &Path| {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase)
+    };
+
+    let (path, encoding) = match extension(path).as_deref() {
+        Some("gz") => (path.with_extension(""), Some("gzip")),
+        Some("br") => (path.with_extension(""), Some("br")),
+        _ => (path.to_path_buf(), None),
+    };
+
+    let mime_type = match extension(&path).as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("wasm") => "application/wasm",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") | Some("oga") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("m4a") => "audio/mp4",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    };
+
+    (mime_type, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_builds::*;
+    use std::io::{Cursor, Write};
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    fn archive_with(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated);
+        for (name, contents) in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+
+        let mut archive = writer.finish().unwrap();
+        archive.set_position(0);
+        archive
+    }
+
+    #[test]
+    fn test_enclosed_path() {
+        assert_eq!(
+            Some(PathBuf::from("Build/game.wasm")),
+            enclosed_path(Path::new("./Build/game.wasm"))
+        );
+        assert_eq!(None, enclosed_path(Path::new("../index.html")));
+        assert_eq!(None, enclosed_path(Path::new("Build/../../index.html")));
+        assert_eq!(None, enclosed_path(Path::new("/etc/passwd")));
+        assert_eq!(None, enclosed_path(Path::new("..\\index.html")));
+        assert_eq!(None, enclosed_path(Path::new("C:/index.html")));
+        assert_eq!(None, enclosed_path(Path::new("")));
+    }
+
+    #[test]
+    fn test_is_symlink() {
+        assert!(is_symlink(Some(0o120777)));
+        assert!(!is_symlink(Some(0o100644)));
+        assert!(!is_symlink(None));
+    }
+
+    #[test]
+    fn test_extract_archive() {
+        let destination = tempfile::tempdir().unwrap();
+        let archive = archive_with(&[
+            ("Game/index.html", b"<html></html>"),
+            ("Game/Build/game.wasm", b"\0asm"),
+            ("Game/Build/index.html", b"<html></html>"),
+            ("__MACOSX/Game/._index.html", b"junk"),
+        ]);
+
+        let extracted =
+            extract_archive(archive, destination.path(), 1024).unwrap();
+
+        assert_eq!("Game/index.html", extracted.entry_point);
+        assert_eq!(30, extracted.size);
+        assert!(destination.path().join("Game/Build/game.wasm").is_file());
+        assert!(!destination.path().join("__MACOSX").exists());
+    }
+
+    #[test]
+    fn test_extract_archive_refuses_bad_archives() {
+        let refused = |files: &[(&str, &[u8])], max_size| {
+            let destination = tempfile::tempdir().unwrap();
+            extract_archive(archive_with(files), destination.path(), max_size)
+                .unwrap_err()
+        };
+
+        assert!(matches!(
+            refused(&[("../index.html", b"<html></html>")], 1024),
+            WebBuildError::UnsafePath(_)
+        ));
+        assert!(matches!(
+            refused(&[("index.html", &[b'a'; 2048])], 1024),
+            WebBuildError::TooLarge(1024)
+        ));
+        assert!(matches!(
+            refused(&[("bomb.bin", &vec![0; 4 * 1024 * 1024])], u64::MAX),
+            WebBuildError::SuspiciousCompression(_)
+        ));
+        assert!(matches!(
+            refused(&[("game.js", b"")], 1024),
+            WebBuildError::MissingEntryPoint
+        ));
+    }
+
+    #[test]
+    fn test_content_type_of() {
+        assert_eq!(
+            ("application/wasm", None),
+            content_type_of(Path::new("Build/game.wasm"))
+        );
+        assert_eq!(
+            ("application/wasm", Some("gzip")),
+            content_type_of(Path::new("Build/game.wasm.gz"))
+        );
+        assert_eq!(
+            ("text/html; charset=utf-8", None),
+            content_type_of(Path::new("INDEX.HTML"))
+        );
+        assert_eq!(
+            ("application/octet-stream", Some("br")),
+            content_type_of(Path::new("Build/game.data.br"))
+        );
+    }
+}